@@ -0,0 +1,205 @@
+use crate::{PathExt, PathJail};
+use error_annotation::AnnotateResult;
+use std::io::{Read, Result};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Options for [PathExt::pe_tar_create](crate::PathExt::pe_tar_create),
+/// [PathExt::pe_zip_create](crate::PathExt::pe_zip_create), and
+/// [PathExt::pe_extract_with](crate::PathExt::pe_extract_with).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ArchiveOptions {
+    /// Record or restore unix permission bits. Otherwise `0o644` files and `0o755` directories
+    /// are recorded, and extracted entries get the default modes of new files and directories,
+    /// as filtered by the process umask.
+    pub keep_mode: bool,
+    /// Record or restore modification times; otherwise the unix epoch is recorded, or the time
+    /// of extraction is kept.
+    pub keep_mtime: bool,
+}
+
+impl Default for ArchiveOptions {
+    fn default() -> Self {
+        ArchiveOptions {
+            keep_mode: true,
+            keep_mtime: true,
+        }
+    }
+}
+
+/// The archive formats recognized by [PathExt::pe_extract](crate::PathExt::pe_extract).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Format {
+    Tar,
+    Zip,
+}
+
+/// Detect the archive format from magic bytes, falling back to the file extension.
+pub(crate) fn detect(archive: &Path) -> Result<Format> {
+    let mut head = Vec::with_capacity(262);
+    archive.pe_open()?.take(262).read_to_end(&mut head)?;
+
+    if head.starts_with(b"PK\x03\x04") || head.starts_with(b"PK\x05\x06") {
+        Ok(Format::Zip)
+    } else if head.get(257..262) == Some(b"ustar") {
+        Ok(Format::Tar)
+    } else {
+        match archive.extension().and_then(|e| e.to_str()) {
+            Some("tar") => Ok(Format::Tar),
+            Some("zip") => Ok(Format::Zip),
+            _ => Err(other_error_fmt!("unrecognized archive format"))
+                .annotate_err_into("archive", || archive.display()),
+        }
+    }
+}
+
+pub(crate) fn extract(archive: &Path, dest: &Path, options: ArchiveOptions) -> Result<()> {
+    dest.pe_create_dir_all()?;
+    let jail = PathJail::new(dest)?;
+
+    match detect(archive)? {
+        #[cfg(feature = "tar")]
+        Format::Tar => crate::tarball::extract(archive, &jail, options),
+        #[cfg(feature = "zip")]
+        Format::Zip => crate::ziparchive::extract(archive, &jail, options),
+        #[cfg(not(feature = "tar"))]
+        Format::Tar => Err(other_error_fmt!("tar archives require the `tar` feature"))
+            .annotate_err_into("archive", || archive.display()),
+        #[cfg(not(feature = "zip"))]
+        Format::Zip => Err(other_error_fmt!("zip archives require the `zip` feature"))
+            .annotate_err_into("archive", || archive.display()),
+    }
+}
+
+/// Walk `root` yielding each entry below it along with its archive member name.
+pub(crate) fn members(
+    root: &Path,
+) -> impl Iterator<Item = Result<(String, crate::PathMetadata<'static>)>> + '_ {
+    root.pe_walk().skip(1).map(move |res| {
+        let md = res?;
+        let name = crate::walk::relative_name(root, md.path())?;
+        Ok((name, md))
+    })
+}
+
+/// Resolve the destination of archive member `name`, rejecting any path traversal.
+pub(crate) fn destination(jail: &PathJail, name: &Path) -> Result<PathBuf> {
+    if name.as_os_str().is_empty() {
+        return Err(other_error_fmt!("empty entry name"));
+    }
+    let dest = jail.resolve(name)?;
+    if dest == jail.root() {
+        return Err(other_error_fmt!("entry names the destination root"));
+    }
+    Ok(dest)
+}
+
+/// Resolve the destination of directory member `name`, or `None` for the destination root
+/// itself, such as the leading `./` entry of archives made by `tar -C dir .`.
+pub(crate) fn dir_destination(jail: &PathJail, name: &Path) -> Result<Option<PathBuf>> {
+    if name.as_os_str().is_empty() {
+        return Err(other_error_fmt!("empty entry name"));
+    }
+    let dest = jail.resolve(name)?;
+    Ok((dest != jail.root()).then_some(dest))
+}
+
+/// The modes and mtimes of extracted directories, which are restored once every entry is
+/// extracted, deepest first, since a read-only directory would block extracting its children,
+/// and extracting them would change its mtime.
+#[derive(Debug, Default)]
+pub(crate) struct DeferredDirs(Vec<(PathBuf, Option<u32>, Option<SystemTime>)>);
+
+impl DeferredDirs {
+    pub(crate) fn push(&mut self, dir: PathBuf, mode: Option<u32>, mtime: Option<SystemTime>) {
+        self.0.push((dir, mode, mtime));
+    }
+
+    pub(crate) fn apply(mut self) -> Result<()> {
+        self.0
+            .sort_by_key(|(dir, _, _)| std::cmp::Reverse(dir.components().count()));
+        for (dir, mode, mtime) in self.0 {
+            if let Some(mode) = mode {
+                set_mode(&dir, mode)?;
+            }
+            if let Some(mtime) = mtime {
+                dir.pe_set_mtime(mtime)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check that a symlink member at `name` pointing to `target` stays within the jail.
+pub(crate) fn check_symlink(jail: &PathJail, name: &Path, target: &Path) -> Result<()> {
+    if target.is_absolute() {
+        return Err(other_error_fmt!("absolute symlink target"))
+            .annotate_err_into("target", || target.display());
+    }
+    let parent = name.parent().unwrap_or_else(|| Path::new(""));
+    jail.resolve(parent.join(target))
+        .annotate_err_into("target", || target.display())
+        .map(|_| ())
+}
+
+/// Write the contents of a regular file member to `dest`, then restore its `mode` and `mtime`.
+pub(crate) fn write_file<R>(
+    dest: &Path,
+    contents: &mut R,
+    mode: Option<u32>,
+    mtime: Option<SystemTime>,
+) -> Result<()>
+where
+    R: Read,
+{
+    create_parent(dest)?;
+    std::io::copy(contents, &mut dest.pe_create()?)?;
+    if let Some(mode) = mode {
+        set_mode(dest, mode)?;
+    }
+    if let Some(mtime) = mtime {
+        dest.pe_set_mtime(mtime)?;
+    }
+    Ok(())
+}
+
+/// Create the parent directories of an extracted entry.
+pub(crate) fn create_parent(dest: &Path) -> Result<()> {
+    match dest.parent() {
+        Some(parent) => parent.pe_create_dir_all(),
+        None => Ok(()),
+    }
+}
+
+#[cfg(unix)]
+pub(crate) fn mode(md: &crate::PathMetadata, options: ArchiveOptions) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+
+    match (options.keep_mode, md.is_dir()) {
+        (true, _) => md.permissions().mode() & 0o7777,
+        (false, true) => 0o755,
+        (false, false) => 0o644,
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn mode(md: &crate::PathMetadata, _options: ArchiveOptions) -> u32 {
+    if md.is_dir() {
+        0o755
+    } else {
+        0o644
+    }
+}
+
+#[cfg(unix)]
+pub(crate) fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+        .annotate_err_into("path", || path.display())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn set_mode(_path: &Path, _mode: u32) -> Result<()> {
+    Ok(())
+}
@@ -0,0 +1,350 @@
+use crate::PathExt;
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+/// The default number of errors [MultiPathError] displays before summarizing the rest.
+const DEFAULT_DISPLAY_LIMIT: usize = 10;
+
+/// Every failure of a batch operation, such as [remove_all], [copy_all], or
+/// [PathWalk::collect_all](crate::PathWalk::collect_all), which carries on past errors.
+///
+/// Each error keeps its own annotations. The display starts with a summary line, then shows the
+/// errors in the order they occurred, up to a display limit.
+///
+/// # Example
+///
+/// ```
+/// use pathutil::remove_all;
+/// use std::io::ErrorKind;
+///
+/// let res = remove_all(["/this/path/does/not/exist", "/nor/does/this"]);
+/// let err = res.err().unwrap();
+/// assert_eq!(err.len(), 2);
+/// assert_eq!(err.of_kind(ErrorKind::NotFound).count(), 2);
+///
+/// let errstr = err.to_string();
+/// assert_eq!(&errstr, "
+///
+/// 2 errors:
+/// No such file or directory (os error 2)
+/// -with path: /this/path/does/not/exist
+/// No such file or directory (os error 2)
+/// -with path: /nor/does/this
+///
+/// ".trim());
+/// ```
+#[derive(Debug)]
+pub struct MultiPathError {
+    errors: Vec<Error>,
+    display_limit: usize,
+}
+
+impl Default for MultiPathError {
+    fn default() -> Self {
+        MultiPathError {
+            errors: vec![],
+            display_limit: DEFAULT_DISPLAY_LIMIT,
+        }
+    }
+}
+
+impl MultiPathError {
+    pub fn new() -> Self {
+        MultiPathError::default()
+    }
+
+    /// Display at most `limit` errors, followed by a count of the rest.
+    pub fn with_display_limit(self, limit: usize) -> Self {
+        MultiPathError {
+            display_limit: limit,
+            ..self
+        }
+    }
+
+    pub fn push(&mut self, err: Error) {
+        self.errors.push(err);
+    }
+
+    /// Return the value of `res`, or collect its error and return `None`.
+    pub fn collect<T>(&mut self, res: std::io::Result<T>) -> Option<T> {
+        match res {
+            Ok(v) => Some(v),
+            Err(e) => {
+                self.push(e);
+                None
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Error> {
+        self.errors.iter()
+    }
+
+    /// Iterate over the errors of `kind`.
+    pub fn of_kind(&self, kind: ErrorKind) -> impl Iterator<Item = &Error> {
+        self.errors.iter().filter(move |e| e.kind() == kind)
+    }
+
+    /// The distinct kinds of the errors with their counts, in order of first occurrence.
+    pub fn kinds(&self) -> Vec<(ErrorKind, usize)> {
+        let mut kinds: Vec<(ErrorKind, usize)> = vec![];
+        for e in &self.errors {
+            match kinds.iter_mut().find(|(k, _)| *k == e.kind()) {
+                Some((_, n)) => *n += 1,
+                None => kinds.push((e.kind(), 1)),
+            }
+        }
+        kinds
+    }
+
+    /// `Ok` if no errors were collected, or else `Err` of all of them.
+    pub fn into_result(self) -> Result<(), MultiPathError> {
+        match self.is_empty() {
+            true => Ok(()),
+            false => Err(self),
+        }
+    }
+
+    pub fn into_errors(self) -> Vec<Error> {
+        self.errors
+    }
+}
+
+impl fmt::Display for MultiPathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let n = self.errors.len();
+        write!(f, "{} error{}:", n, if n == 1 { "" } else { "s" })?;
+        for e in self.errors.iter().take(self.display_limit) {
+            write!(f, "\n{}", e)?;
+        }
+        if n > self.display_limit {
+            write!(f, "\n...and {} more", n - self.display_limit)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for MultiPathError {}
+
+impl Extend<Error> for MultiPathError {
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = Error>,
+    {
+        self.errors.extend(iter);
+    }
+}
+
+impl IntoIterator for MultiPathError {
+    type Item = Error;
+    type IntoIter = std::vec::IntoIter<Error>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.errors.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a MultiPathError {
+    type Item = &'a Error;
+    type IntoIter = std::slice::Iter<'a, Error>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.errors.iter()
+    }
+}
+
+/// Convert to a single [std::io::Error] with the display of all errors, of their kind if they
+/// all share one, or else [ErrorKind::Other].
+impl From<MultiPathError> for Error {
+    fn from(multi: MultiPathError) -> Self {
+        let kind = match multi.kinds().as_slice() {
+            [(kind, _)] => *kind,
+            _ => ErrorKind::Other,
+        };
+        Error::new(kind, multi.to_string())
+    }
+}
+
+/// Remove every path, whether a file, symlink, or directory tree, carrying on past failures and
+/// returning all of them.
+///
+/// Within a tree, every entry which can be removed is, and each directory left non-empty by a
+/// failure beneath it is kept without reporting it again.
+pub fn remove_all<I, P>(paths: I) -> Result<(), MultiPathError>
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<Path>,
+{
+    let mut errors = MultiPathError::new();
+    for p in paths {
+        remove_collecting(p.as_ref(), &mut errors);
+    }
+    errors.into_result()
+}
+
+/// Remove `path` and everything beneath it, depth first, collecting every failure in `errors`.
+/// Returns whether `path` itself was removed.
+fn remove_collecting(path: &Path, errors: &mut MultiPathError) -> bool {
+    let Some(md) = errors.collect(path.pe_symlink_metadata()) else {
+        return false;
+    };
+    if !md.is_dir() {
+        return errors.collect(path.pe_remove_file()).is_some();
+    }
+
+    let Some(entries) = errors.collect(path.pe_read_dir()) else {
+        return false;
+    };
+    let before = errors.len();
+    let children: Vec<_> = entries
+        .filter_map(|res| errors.collect(res.map(|de| de.path())))
+        .collect();
+    let mut emptied = errors.len() == before;
+    for child in children {
+        emptied &= remove_collecting(&child, errors);
+    }
+    // A directory with entries left would only fail again with "directory not empty":
+    emptied && errors.collect(path.pe_remove_dir()).is_some()
+}
+
+/// Copy each `(from, to)` pair of files, carrying on past failures and returning all of them.
+pub fn copy_all<I, P, Q>(pairs: I) -> Result<(), MultiPathError>
+where
+    I: IntoIterator<Item = (P, Q)>,
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let mut errors = MultiPathError::new();
+    for (from, to) in pairs {
+        errors.collect(from.as_ref().pe_copy(to));
+    }
+    errors.into_result()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use crate::testing::scratch;
+    use crate::{copy_all, remove_all};
+    use std::io::ErrorKind;
+    use std::path::Path;
+
+    /// `FS_IMMUTABLE_FL` from `linux/fs.h`.
+    #[cfg(target_os = "linux")]
+    const IMMUTABLE: libc::c_long = 0x10;
+
+    /// Set or clear the immutable flag of `path`, returning whether it could be changed, which
+    /// needs `CAP_LINUX_IMMUTABLE` and filesystem support.
+    #[cfg(target_os = "linux")]
+    fn set_immutable(path: &Path, on: bool) -> bool {
+        use std::os::unix::io::AsRawFd;
+
+        let f = std::fs::File::open(path).unwrap();
+        let mut flags: libc::c_long = 0;
+        unsafe {
+            libc::ioctl(f.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut flags) == 0 && {
+                flags = if on {
+                    flags | IMMUTABLE
+                } else {
+                    flags & !IMMUTABLE
+                };
+                libc::ioctl(f.as_raw_fd(), libc::FS_IOC_SETFLAGS, &flags) == 0
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn removes_everything_removable_within_a_tree() {
+        let dir = scratch("remove-all-partial");
+        let tree = dir.join("tree");
+        for sub in ["a", "b", "c"] {
+            std::fs::create_dir_all(tree.join(sub)).unwrap();
+            std::fs::write(tree.join(sub).join("f"), sub).unwrap();
+        }
+        let stuck = tree.join("b/f");
+        if !set_immutable(&stuck, true) {
+            return;
+        }
+
+        let res = remove_all([&tree, &dir.join("missing")]);
+        set_immutable(&stuck, false);
+
+        let err = res.unwrap_err();
+        assert_eq!(err.len(), 2, "{}", err);
+        assert!(
+            err.iter().next().unwrap().to_string().contains("b/f"),
+            "{}",
+            err
+        );
+        assert!(!tree.join("a").exists());
+        assert!(!tree.join("c").exists());
+        assert!(stuck.exists());
+    }
+
+    #[test]
+    fn remove_all_collects_every_failure() {
+        let dir = scratch("remove-all-failures");
+        std::fs::write(dir.join("file"), "").unwrap();
+        std::fs::create_dir_all(dir.join("tree/sub")).unwrap();
+        std::fs::write(dir.join("tree/sub/f"), "").unwrap();
+        let under_file = dir.join("file/child");
+        let missing = dir.join("missing");
+
+        let err = remove_all([&under_file, &dir.join("tree"), &missing]).unwrap_err();
+        let errors: Vec<_> = err.iter().map(|e| (e.kind(), e.to_string())).collect();
+        assert_eq!(errors.len(), 2, "{}", err);
+        assert_eq!(errors[0].0, ErrorKind::NotADirectory);
+        assert!(errors[0]
+            .1
+            .ends_with(&format!("-with path: {}", under_file.display())));
+        assert_eq!(errors[1].0, ErrorKind::NotFound);
+        assert!(errors[1]
+            .1
+            .ends_with(&format!("-with path: {}", missing.display())));
+        assert_eq!(
+            err.kinds(),
+            [(ErrorKind::NotADirectory, 1), (ErrorKind::NotFound, 1)]
+        );
+
+        // The failures did not stop the removable tree between them:
+        assert!(!dir.join("tree").exists());
+        assert!(dir.join("file").exists());
+    }
+
+    #[test]
+    fn copy_all_collects_every_failure() {
+        let dir = scratch("copy-all-failures");
+        std::fs::write(dir.join("src"), "data").unwrap();
+        std::fs::create_dir(dir.join("full")).unwrap();
+        std::fs::write(dir.join("full/entry"), "").unwrap();
+        let pairs = [
+            (dir.join("missing"), dir.join("a")),
+            (dir.join("src"), dir.join("src/child")),
+            (dir.join("src"), dir.join("copy")),
+            // A directory cannot be opened for writing:
+            (dir.join("src"), dir.join("full")),
+        ];
+
+        let err = copy_all(pairs.clone()).unwrap_err();
+        assert_eq!(err.len(), 3, "{}", err);
+        let failed = [&pairs[0], &pairs[1], &pairs[3]];
+        for (e, (from, to)) in err.iter().zip(failed) {
+            let annotations = format!("-with from: {}\n-with to: {}", from.display(), to.display());
+            assert!(e.to_string().ends_with(&annotations), "{}", e);
+        }
+        assert_eq!(err.iter().next().unwrap().kind(), ErrorKind::NotFound);
+        assert_eq!(err.to_string().lines().next(), Some("3 errors:"));
+
+        assert_eq!(std::fs::read(dir.join("copy")).unwrap(), b"data");
+        assert!(!dir.join("a").exists());
+    }
+}
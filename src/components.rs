@@ -0,0 +1,104 @@
+use crate::PathExt;
+use error_annotation::AnnotateResult;
+use std::ffi::OsStr;
+use std::io::Result;
+use std::path::{Component, Path, PathBuf};
+
+pub(crate) fn components_str(path: &Path) -> Result<Vec<&str>> {
+    path.components()
+        .enumerate()
+        .map(|(i, c)| {
+            c.as_os_str()
+                .to_str()
+                .ok_or_else(|| other_error_fmt!("invalid utf8"))
+                .annotate_err_into("path", || path.display())
+                .annotate_err_into("component", || i)
+        })
+        .collect()
+}
+
+/// Whether `name` is a single normal component, so it names an entry of the parent directory.
+fn is_single_name(name: &OsStr) -> bool {
+    let mut components = Path::new(name).components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(c)), None) if c == name
+    )
+}
+
+pub(crate) fn with_file_name_checked(path: &Path, name: &OsStr) -> Result<PathBuf> {
+    file_name(path)?;
+    if !is_single_name(name) {
+        return Err(other_error_fmt!("invalid file name"))
+            .annotate_err_into("path", || path.display())
+            .annotate_err_into("name", || name.to_string_lossy());
+    }
+    Ok(path.with_file_name(name))
+}
+
+pub(crate) fn with_extension_checked(path: &Path, ext: &OsStr) -> Result<PathBuf> {
+    file_name(path)?;
+    let invalid = match ext.as_encoded_bytes().first() {
+        None => false,
+        Some(b'.') => true,
+        Some(_) => !is_single_name(ext),
+    };
+    if invalid {
+        return Err(other_error_fmt!("invalid extension"))
+            .annotate_err_into("path", || path.display())
+            .annotate_err_into("extension", || ext.to_string_lossy());
+    }
+    Ok(path.with_extension(ext))
+}
+
+/// Split the file name of `path` into the part before the first `.`, ignoring a leading `.`, and
+/// the `.`-separated extensions after it.
+pub(crate) fn split_extensions(path: &Path) -> Result<(&OsStr, Vec<&OsStr>)> {
+    let name = file_name(path)?;
+    let bytes = name.as_encoded_bytes();
+    let start = usize::from(bytes.first() == Some(&b'.'));
+    let Some(dot) = bytes[start..].iter().position(|&b| b == b'.') else {
+        return Ok((name, vec![]));
+    };
+    let dot = start + dot;
+
+    let exts = bytes[dot + 1..].split(|&b| b == b'.').map(os).collect();
+    Ok((os(&bytes[..dot]), exts))
+}
+
+/// As [PathExt::pe_file_name], borrowing from `path` itself.
+fn file_name(path: &Path) -> Result<&OsStr> {
+    path.file_name()
+        .ok_or_else(|| other_error_fmt!("no file name"))
+        .annotate_err_into("path", || path.display())
+}
+
+fn os(bytes: &[u8]) -> &OsStr {
+    // Safety: the bytes come from an `OsStr` and are only split around ASCII `.` bytes.
+    unsafe { OsStr::from_encoded_bytes_unchecked(bytes) }
+}
+
+pub(crate) fn replace_prefix(path: &Path, old: &Path, new: &Path) -> Result<PathBuf> {
+    let rest = path.pe_strip_prefix(old)?;
+    match rest.as_os_str().is_empty() {
+        true => Ok(new.to_path_buf()),
+        false => Ok(new.join(rest)),
+    }
+}
+
+pub(crate) fn ancestors_until<'a>(path: &'a Path, root: &Path) -> Result<Vec<&'a Path>> {
+    if !path.starts_with(root) {
+        return Err(other_error_fmt!("not within root"))
+            .annotate_err_into("path", || path.display())
+            .annotate_err_into("root", || root.display());
+    }
+
+    let mut ancestors = vec![];
+    for p in path.ancestors() {
+        ancestors.push(p);
+        if p == root {
+            break;
+        }
+    }
+    Ok(ancestors)
+}
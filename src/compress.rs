@@ -0,0 +1,481 @@
+use crate::PathFile;
+use error_annotation::AnnotateResult;
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Result, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// A compression format recognized by
+/// [PathExt::pe_open_decompressed](crate::PathExt::pe_open_decompressed) and
+/// [PathExt::pe_create_compressed](crate::PathExt::pe_create_compressed).
+///
+/// Each codec is only available with the cargo feature of the same name (`gzip`, `zstd`, `xz`,
+/// and `bzip2`); the others report an error naming the missing feature.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Xz,
+    Bzip2,
+}
+
+impl Compression {
+    /// The compression implied by the extension of `path`: `.gz`, `.zst`, `.xz`, or `.bz2`.
+    pub fn from_extension<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        match path.as_ref().extension().and_then(|e| e.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            Some("xz") => Compression::Xz,
+            Some("bz2") => Compression::Bzip2,
+            _ => Compression::None,
+        }
+    }
+
+    /// The compression identified by the magic bytes at the start of `head`, if any.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pathutil::Compression;
+    ///
+    /// assert_eq!(Compression::from_magic(b"\x1f\x8b\x08"), Some(Compression::Gzip));
+    /// assert_eq!(Compression::from_magic(b"BZh9"), Some(Compression::Bzip2));
+    /// assert_eq!(Compression::from_magic(b"BZh, said the text"), None);
+    /// ```
+    pub fn from_magic(head: &[u8]) -> Option<Self> {
+        if head.starts_with(b"\x1f\x8b") {
+            Some(Compression::Gzip)
+        } else if head.starts_with(b"\x28\xb5\x2f\xfd") {
+            Some(Compression::Zstd)
+        } else if head.starts_with(b"\xfd7zXZ\x00") {
+            Some(Compression::Xz)
+        } else if head.starts_with(b"BZh") && matches!(head.get(3), Some(b'1'..=b'9')) {
+            // The digit is the block size, which distinguishes a stream from text starting `BZh`:
+            Some(Compression::Bzip2)
+        } else {
+            None
+        }
+    }
+
+    /// The error for a codec whose feature is disabled.
+    #[cfg(not(all(feature = "gzip", feature = "zstd", feature = "xz", feature = "bzip2")))]
+    fn unsupported(self) -> std::io::Error {
+        let feature = match self {
+            Compression::None => "",
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+            Compression::Xz => "xz",
+            Compression::Bzip2 => "bzip2",
+        };
+        other_error_fmt!("{} compression requires the `{}` feature", self, feature)
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Compression::None => "no",
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+            Compression::Xz => "xz",
+            Compression::Bzip2 => "bzip2",
+        })
+    }
+}
+
+/// A reader of a possibly compressed file, created by
+/// [PathExt::pe_open_decompressed](crate::PathExt::pe_open_decompressed), which yields the
+/// decompressed contents.
+///
+/// Errors are annotated with the path and the offset into the compressed file at which they were
+/// detected. Since codecs read ahead, the offset is the amount of compressed input consumed so
+/// far, which may be slightly past the corruption itself.
+#[derive(Debug)]
+pub struct PathDecoder {
+    path: PathBuf,
+    compression: Compression,
+    decoder: Decoder,
+}
+
+enum Decoder {
+    Plain(Counting),
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::read::MultiGzDecoder<Counting>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::Decoder<'static, std::io::BufReader<Counting>>),
+    #[cfg(feature = "xz")]
+    Xz(liblzma::read::XzDecoder<Counting>),
+    #[cfg(feature = "bzip2")]
+    Bzip2(bzip2::read::MultiBzDecoder<Counting>),
+}
+
+impl fmt::Debug for Decoder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Decoder")
+            .field("offset", &self.offset())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Decoder {
+    fn offset(&self) -> u64 {
+        match self {
+            Decoder::Plain(r) => r.count,
+            #[cfg(feature = "gzip")]
+            Decoder::Gzip(d) => d.get_ref().count,
+            #[cfg(feature = "zstd")]
+            Decoder::Zstd(d) => d.get_ref().get_ref().count,
+            #[cfg(feature = "xz")]
+            Decoder::Xz(d) => d.get_ref().count,
+            #[cfg(feature = "bzip2")]
+            Decoder::Bzip2(d) => d.get_ref().count,
+        }
+    }
+}
+
+/// A [File] reader which counts the bytes read from it.
+struct Counting {
+    f: File,
+    count: u64,
+}
+
+impl Read for Counting {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = self.f.read(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+}
+
+impl PathDecoder {
+    /// Access associated [Path].
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The detected [Compression] of the file.
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+}
+
+impl Read for PathDecoder {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let res = match &mut self.decoder {
+            Decoder::Plain(r) => r.read(buf),
+            #[cfg(feature = "gzip")]
+            Decoder::Gzip(d) => d.read(buf),
+            #[cfg(feature = "zstd")]
+            Decoder::Zstd(d) => d.read(buf),
+            #[cfg(feature = "xz")]
+            Decoder::Xz(d) => d.read(buf),
+            #[cfg(feature = "bzip2")]
+            Decoder::Bzip2(d) => d.read(buf),
+        };
+        res.annotate_err_into("path", || self.path.display())
+            .annotate_err_into("offset", || self.decoder.offset())
+    }
+}
+
+pub(crate) fn open(path: &Path) -> Result<PathDecoder> {
+    let (compression, decoder) = open_decoder(path).annotate_err_into("path", || path.display())?;
+
+    Ok(PathDecoder {
+        path: path.to_path_buf(),
+        compression,
+        decoder,
+    })
+}
+
+fn open_decoder(path: &Path) -> Result<(Compression, Decoder)> {
+    let mut f = File::open(path)?;
+    let mut head = Vec::with_capacity(6);
+    (&mut f).take(6).read_to_end(&mut head)?;
+    f.seek(SeekFrom::Start(0))?;
+
+    let compression = match Compression::from_magic(&head) {
+        Some(c) => c,
+        None => match Compression::from_extension(path) {
+            Compression::None => Compression::None,
+            c => return Err(other_error_fmt!("missing {} magic bytes", c)),
+        },
+    };
+
+    let r = Counting { f, count: 0 };
+    let decoder = match compression {
+        Compression::None => Decoder::Plain(r),
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => Decoder::Gzip(flate2::read::MultiGzDecoder::new(r)),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Decoder::Zstd(zstd::Decoder::new(r)?),
+        #[cfg(feature = "xz")]
+        Compression::Xz => Decoder::Xz(liblzma::read::XzDecoder::new_multi_decoder(r)),
+        #[cfg(feature = "bzip2")]
+        Compression::Bzip2 => Decoder::Bzip2(bzip2::read::MultiBzDecoder::new(r)),
+        #[cfg(not(all(feature = "gzip", feature = "zstd", feature = "xz", feature = "bzip2")))]
+        c => return Err(c.unsupported()),
+    };
+    Ok((compression, decoder))
+}
+
+/// A writer which compresses according to the extension of its path, created by
+/// [PathExt::pe_create_compressed](crate::PathExt::pe_create_compressed).
+///
+/// [PathEncoder::finish] must be called to write the end of the compressed stream and surface any
+/// error in doing so; a dropped encoder may leave a truncated file.
+#[derive(Debug)]
+pub struct PathEncoder {
+    path: PathBuf,
+    compression: Compression,
+    encoder: Encoder,
+}
+
+enum Encoder {
+    Plain(File),
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::write::GzEncoder<File>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::Encoder<'static, File>),
+    #[cfg(feature = "xz")]
+    Xz(liblzma::write::XzEncoder<File>),
+    #[cfg(feature = "bzip2")]
+    Bzip2(bzip2::write::BzEncoder<File>),
+}
+
+impl fmt::Debug for Encoder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Encoder").finish_non_exhaustive()
+    }
+}
+
+impl PathEncoder {
+    /// Access associated [Path].
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The [Compression] being written.
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Write the end of the compressed stream, returning the underlying [PathFile].
+    pub fn finish(self) -> Result<PathFile> {
+        let res = match self.encoder {
+            Encoder::Plain(f) => Ok(f),
+            #[cfg(feature = "gzip")]
+            Encoder::Gzip(e) => e.finish(),
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(e) => e.finish(),
+            #[cfg(feature = "xz")]
+            Encoder::Xz(e) => e.finish(),
+            #[cfg(feature = "bzip2")]
+            Encoder::Bzip2(e) => e.finish(),
+        };
+        res.annotate_err_into("path", || self.path.display())
+            .map(|f| PathFile::new(self.path, f))
+    }
+}
+
+impl Write for PathEncoder {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        match &mut self.encoder {
+            Encoder::Plain(f) => f.write(buf),
+            #[cfg(feature = "gzip")]
+            Encoder::Gzip(e) => e.write(buf),
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(e) => e.write(buf),
+            #[cfg(feature = "xz")]
+            Encoder::Xz(e) => e.write(buf),
+            #[cfg(feature = "bzip2")]
+            Encoder::Bzip2(e) => e.write(buf),
+        }
+        .annotate_err_into("path", || self.path.display())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match &mut self.encoder {
+            Encoder::Plain(f) => f.flush(),
+            #[cfg(feature = "gzip")]
+            Encoder::Gzip(e) => e.flush(),
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(e) => e.flush(),
+            #[cfg(feature = "xz")]
+            Encoder::Xz(e) => e.flush(),
+            #[cfg(feature = "bzip2")]
+            Encoder::Bzip2(e) => e.flush(),
+        }
+        .annotate_err_into("path", || self.path.display())
+    }
+}
+
+pub(crate) fn create(path: &Path) -> Result<PathEncoder> {
+    let compression = Compression::from_extension(path);
+    let encoder = create_encoder(path, compression).annotate_err_into("path", || path.display())?;
+
+    Ok(PathEncoder {
+        path: path.to_path_buf(),
+        compression,
+        encoder,
+    })
+}
+
+fn create_encoder(path: &Path, compression: Compression) -> Result<Encoder> {
+    let f = || File::create(path);
+    Ok(match compression {
+        Compression::None => Encoder::Plain(f()?),
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => Encoder::Gzip(flate2::write::GzEncoder::new(
+            f()?,
+            flate2::Compression::default(),
+        )),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Encoder::Zstd(zstd::Encoder::new(f()?, 0)?),
+        #[cfg(feature = "xz")]
+        Compression::Xz => Encoder::Xz(liblzma::write::XzEncoder::new(f()?, 6)),
+        #[cfg(feature = "bzip2")]
+        Compression::Bzip2 => Encoder::Bzip2(bzip2::write::BzEncoder::new(
+            f()?,
+            bzip2::Compression::default(),
+        )),
+        #[cfg(not(all(feature = "gzip", feature = "zstd", feature = "xz", feature = "bzip2")))]
+        c => return Err(c.unsupported()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::scratch;
+    use crate::{Compression, PathExt};
+    use std::io::{Read, Write};
+    use std::path::Path;
+
+    /// Text long enough to compress, with some variety so corruption is detected.
+    #[cfg(any(feature = "gzip", feature = "zstd", feature = "xz", feature = "bzip2"))]
+    fn sample() -> Vec<u8> {
+        (0..2000)
+            .flat_map(|i| format!("line {} of the sample\n", i * 7919 % 1000).into_bytes())
+            .collect()
+    }
+
+    fn write_compressed(path: &Path, data: &[u8]) {
+        let mut enc = path.pe_create_compressed().unwrap();
+        enc.write_all(data).unwrap();
+        enc.finish().unwrap();
+    }
+
+    fn read_decompressed(path: &Path) -> std::io::Result<(Compression, Vec<u8>)> {
+        let mut dec = path.pe_open_decompressed()?;
+        let mut data = vec![];
+        dec.read_to_end(&mut data)?;
+        Ok((dec.compression(), data))
+    }
+
+    #[cfg(any(feature = "gzip", feature = "zstd", feature = "xz", feature = "bzip2"))]
+    fn round_trip(ext: &str, compression: Compression) {
+        let dir = scratch(&format!("compress-round-trip-{}", ext));
+        let path = dir.join(format!("data.{}", ext));
+        write_compressed(&path, &sample());
+
+        let head = std::fs::read(&path).unwrap();
+        assert_eq!(Compression::from_magic(&head), Some(compression));
+        assert!(head.len() < sample().len());
+        assert_eq!(read_decompressed(&path).unwrap(), (compression, sample()));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn gzip_round_trip() {
+        round_trip("gz", Compression::Gzip);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd_round_trip() {
+        round_trip("zst", Compression::Zstd);
+    }
+
+    #[cfg(feature = "xz")]
+    #[test]
+    fn xz_round_trip() {
+        round_trip("xz", Compression::Xz);
+    }
+
+    #[cfg(feature = "bzip2")]
+    #[test]
+    fn bzip2_round_trip() {
+        round_trip("bz2", Compression::Bzip2);
+    }
+
+    #[test]
+    fn plain_round_trip() {
+        let path = scratch("compress-plain").join("data.txt");
+        write_compressed(&path, b"BZh, said the text");
+        assert_eq!(
+            read_decompressed(&path).unwrap(),
+            (Compression::None, b"BZh, said the text".to_vec())
+        );
+    }
+
+    #[test]
+    fn magic_numbers() {
+        let cases: &[(&[u8], Option<Compression>)] = &[
+            (b"\x1f\x8b\x08\x00", Some(Compression::Gzip)),
+            (b"\x28\xb5\x2f\xfd", Some(Compression::Zstd)),
+            (b"\xfd7zXZ\x00", Some(Compression::Xz)),
+            (b"BZh1", Some(Compression::Bzip2)),
+            (b"BZh9", Some(Compression::Bzip2)),
+            (b"BZh0", None),
+            (b"BZh", None),
+            (b"BZhello", None),
+            (b"", None),
+        ];
+        for &(head, expected) in cases {
+            assert_eq!(Compression::from_magic(head), expected, "{:?}", head);
+        }
+    }
+
+    #[test]
+    fn extension_without_magic_is_an_error() {
+        let path = scratch("compress-no-magic").join("data.gz");
+        std::fs::write(&path, "plain text").unwrap();
+
+        let errstr = read_decompressed(&path).unwrap_err().to_string();
+        assert!(errstr.starts_with("missing gzip magic bytes"), "{}", errstr);
+        let expected = format!("-with path: {}", path.display());
+        assert!(errstr.contains(&expected), "{}", errstr);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn magic_is_preferred_over_extension() {
+        let dir = scratch("compress-magic-first");
+        write_compressed(&dir.join("data.gz"), &sample());
+        std::fs::rename(dir.join("data.gz"), dir.join("data.bin")).unwrap();
+
+        let (compression, data) = read_decompressed(&dir.join("data.bin")).unwrap();
+        assert_eq!(compression, Compression::Gzip);
+        assert_eq!(data, sample());
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn corruption_error_names_path_and_offset() {
+        let path = scratch("compress-corrupt").join("data.gz");
+        write_compressed(&path, &sample());
+        let mut bytes = std::fs::read(&path).unwrap();
+        let mid = bytes.len() / 2;
+        for b in &mut bytes[mid..mid + 16] {
+            *b ^= 0x55;
+        }
+        std::fs::write(&path, bytes).unwrap();
+
+        let errstr = read_decompressed(&path).unwrap_err().to_string();
+        let expected = format!("-with path: {}\n-with offset: ", path.display());
+        assert!(errstr.contains(&expected), "{}", errstr);
+    }
+}
@@ -0,0 +1,533 @@
+use crate::progress::{check_cancelled, Quiet};
+use crate::{Observer, ProgressEvent};
+use error_annotation::AnnotateResult;
+use std::fmt;
+use std::fs::{File, FileTimes, OpenOptions};
+use std::io::{Read, Result, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// The size of each chunk of a buffered copy, and the largest step between progress reports.
+const CHUNK_LEN: u64 = 1 << 20;
+
+/// How [PathExt::pe_copy_with](crate::PathExt::pe_copy_with) copied the contents of a file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CopyStrategy {
+    /// The destination shares the extents of the source copy-on-write, as on btrfs and xfs.
+    Reflink,
+    /// The kernel copied the data with `copy_file_range`, without passing through userspace.
+    CopyFileRange,
+    /// The data was read and written in chunks. This is also reported when there was no data
+    /// to copy, as for an empty or entirely sparse source.
+    Buffered,
+}
+
+/// Options for [PathExt::pe_copy_with](crate::PathExt::pe_copy_with).
+///
+/// The default tries a reflink and keeps permissions, like [std::fs::copy], but neither extended
+/// attributes nor timestamps.
+pub struct CopyOptions<'a> {
+    /// Try a reflink before copying the data.
+    pub reflink: bool,
+    /// Give the destination the permissions of the source.
+    pub permissions: bool,
+    /// Copy the extended attributes of the source. These are only supported on Linux and are
+    /// ignored elsewhere.
+    pub xattrs: bool,
+    /// Give the destination the access and modification times of the source.
+    pub timestamps: bool,
+    /// Skip the holes of a sparse source so the destination is sparse too. Holes are only
+    /// detected on Linux; elsewhere the whole file is copied as data.
+    pub sparse: bool,
+    /// Receives a [ProgressEvent::Entry] for the source, then [ProgressEvent::Bytes] as the copy
+    /// proceeds, ending with all bytes done. Skipped holes count as done. If the observer is
+    /// cancelled, the copy stops between chunks and the destination is left partially written.
+    pub observer: Option<&'a mut dyn Observer>,
+}
+
+impl Default for CopyOptions<'_> {
+    fn default() -> Self {
+        CopyOptions {
+            reflink: true,
+            permissions: true,
+            xattrs: false,
+            timestamps: false,
+            sparse: false,
+            observer: None,
+        }
+    }
+}
+
+impl fmt::Debug for CopyOptions<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CopyOptions")
+            .field("reflink", &self.reflink)
+            .field("permissions", &self.permissions)
+            .field("xattrs", &self.xattrs)
+            .field("timestamps", &self.timestamps)
+            .field("sparse", &self.sparse)
+            .field("observer", &self.observer.is_some())
+            .finish()
+    }
+}
+
+/// The outcome of [PathExt::pe_copy_with](crate::PathExt::pe_copy_with).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CopyReport {
+    pub strategy: CopyStrategy,
+    /// The length of the copied file, which is less than the length the source reported if it
+    /// ended early.
+    pub len: u64,
+}
+
+pub(crate) fn copy(from: &Path, to: &Path, mut options: CopyOptions) -> Result<CopyReport> {
+    copy_inner(from, to, None, &mut options)
+        .annotate_err_into("from", || from.display())
+        .annotate_err_into("to", || to.display())
+}
+
+/// [copy] into `dst`, the already open file at `to`, such as an exclusively created temporary
+/// file.
+pub(crate) fn copy_to_file(
+    from: &Path,
+    to: &Path,
+    dst: &File,
+    mut options: CopyOptions,
+) -> Result<CopyReport> {
+    copy_inner(from, to, Some(dst), &mut options)
+        .annotate_err_into("from", || from.display())
+        .annotate_err_into("to", || to.display())
+}
+
+fn copy_inner(
+    from: &Path,
+    to: &Path,
+    dst: Option<&File>,
+    options: &mut CopyOptions,
+) -> Result<CopyReport> {
+    let src = File::open(from)?;
+    let srcmd = src.metadata()?;
+    if !srcmd.is_file() {
+        return Err(other_error_fmt!("not a regular file"));
+    }
+    let len = srcmd.len();
+
+    // Truncate only once the destination is known not to be the source:
+    let opened;
+    let dst = match dst {
+        Some(dst) => dst,
+        None => {
+            opened = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(to)?;
+            &opened
+        }
+    };
+    if same_file(&srcmd, &dst.metadata()?) {
+        return Err(other_error_fmt!("source and destination are the same file"));
+    }
+    dst.set_len(0)?;
+
+    let mut quiet = Quiet;
+    let observer: &mut dyn Observer = match options.observer.as_deref_mut() {
+        Some(observer) => observer,
+        None => &mut quiet,
+    };
+    observer.event(ProgressEvent::Entry(from));
+    check_cancelled(observer)?;
+    let mut reported = None;
+    let mut progress = |done: u64| {
+        if reported != Some(done) {
+            reported = Some(done);
+            observer.event(ProgressEvent::Bytes {
+                path: from,
+                done,
+                total: len,
+            });
+        }
+        check_cancelled(observer)
+    };
+
+    let (strategy, len) = if options.reflink && reflink(&src, dst).is_ok() {
+        progress(len)?;
+        (CopyStrategy::Reflink, len)
+    } else {
+        let extents = match options.sparse {
+            true => data_extents(&src, len)?,
+            false => vec![(0, len)],
+        };
+        let (strategy, eof) = copy_extents(&src, dst, &extents, &mut progress)?;
+        // Extend over a trailing hole, unless the source ended early, as files in sysfs and
+        // procfs do, or because it shrank during the copy:
+        let end = eof.unwrap_or(len);
+        dst.set_len(end)?;
+        progress(end)?;
+        (strategy, end)
+    };
+
+    if options.xattrs {
+        copy_xattrs(from, to)?;
+    }
+    if options.timestamps {
+        let times = FileTimes::new()
+            .set_accessed(srcmd.accessed()?)
+            .set_modified(srcmd.modified()?);
+        dst.set_times(times)?;
+    }
+    // Permissions go last, since a read-only destination could refuse the other changes:
+    if options.permissions {
+        dst.set_permissions(srcmd.permissions())?;
+    }
+
+    Ok(CopyReport { strategy, len })
+}
+
+/// Copy each `(offset, len)` extent of `src` to the same offset in `dst`, with
+/// `copy_file_range` where the kernel supports it for these files, or else buffered.
+///
+/// The strategy is the one which copied the first bytes, or [CopyStrategy::Buffered] if there
+/// were none. If the source ends before the last extent does, the copy stops there and that
+/// offset is returned as well.
+fn copy_extents<F>(
+    src: &File,
+    dst: &File,
+    extents: &[(u64, u64)],
+    progress: &mut F,
+) -> Result<(CopyStrategy, Option<u64>)>
+where
+    F: FnMut(u64) -> Result<()>,
+{
+    // Undecided until the first chunk, whatever its offset:
+    let mut strategy = None;
+    let mut buf = vec![];
+    for &(offset, len) in extents {
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done;
+            let step = (len - done).min(CHUNK_LEN);
+            let n = match strategy {
+                None | Some(CopyStrategy::CopyFileRange) => {
+                    match copy_file_range(src, dst, pos, step) {
+                        Ok(n) if n > 0 => {
+                            strategy = Some(CopyStrategy::CopyFileRange);
+                            n
+                        }
+                        // Only fall back before anything was copied, so real failures still
+                        // surface. Files in procfs and sysfs copy nothing rather than failing:
+                        Ok(_) | Err(_) if strategy.is_none() => {
+                            strategy = Some(CopyStrategy::Buffered);
+                            buf.resize(CHUNK_LEN as usize, 0);
+                            continue;
+                        }
+                        Ok(n) => n,
+                        Err(e) => return Err(e),
+                    }
+                }
+                Some(_) => copy_buffered(src, dst, pos, &mut buf[..step as usize])?,
+            };
+            if n == 0 {
+                return Ok((strategy.unwrap_or(CopyStrategy::Buffered), Some(pos)));
+            }
+            done += n;
+            progress(pos + n)?;
+        }
+    }
+    Ok((strategy.unwrap_or(CopyStrategy::Buffered), None))
+}
+
+fn copy_buffered(mut src: &File, mut dst: &File, pos: u64, buf: &mut [u8]) -> Result<u64> {
+    src.seek(SeekFrom::Start(pos))?;
+    let n = src.read(buf)?;
+    dst.seek(SeekFrom::Start(pos))?;
+    dst.write_all(&buf[..n])?;
+    Ok(n as u64)
+}
+
+#[cfg(target_os = "linux")]
+fn copy_file_range(src: &File, dst: &File, pos: u64, len: u64) -> Result<u64> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut off_in = pos as libc::loff_t;
+    let mut off_out = pos as libc::loff_t;
+    let rc = unsafe {
+        libc::copy_file_range(
+            src.as_raw_fd(),
+            &mut off_in,
+            dst.as_raw_fd(),
+            &mut off_out,
+            len as usize,
+            0,
+        )
+    };
+    if rc < 0 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(rc as u64)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn copy_file_range(_src: &File, _dst: &File, _pos: u64, _len: u64) -> Result<u64> {
+    Err(std::io::Error::from(std::io::ErrorKind::Unsupported))
+}
+
+/// The `(offset, len)` extents of `file` which hold data, skipping holes.
+#[cfg(target_os = "linux")]
+fn data_extents(file: &File, len: u64) -> Result<Vec<(u64, u64)>> {
+    use std::os::unix::io::AsRawFd;
+
+    let seek = |pos: u64, whence| {
+        let rc = unsafe { libc::lseek(file.as_raw_fd(), pos as libc::off_t, whence) };
+        match rc {
+            -1 => Err(std::io::Error::last_os_error()),
+            rc => Ok(rc as u64),
+        }
+    };
+
+    let mut extents = vec![];
+    let mut pos = 0;
+    while pos < len {
+        let start = match seek(pos, libc::SEEK_DATA) {
+            Ok(start) => start,
+            // No data past `pos`:
+            Err(e) if e.raw_os_error() == Some(libc::ENXIO) => break,
+            // Hole detection is unsupported by this filesystem:
+            Err(e) if e.raw_os_error() == Some(libc::EINVAL) && pos == 0 => {
+                return Ok(vec![(0, len)]);
+            }
+            Err(e) => return Err(e),
+        };
+        let end = seek(start, libc::SEEK_HOLE)?.min(len);
+        if start < end {
+            extents.push((start, end - start));
+        }
+        pos = end;
+    }
+    Ok(extents)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn data_extents(_file: &File, len: u64) -> Result<Vec<(u64, u64)>> {
+    Ok(vec![(0, len)])
+}
+
+#[cfg(target_os = "linux")]
+fn copy_xattrs(from: &Path, to: &Path) -> Result<()> {
+    for name in ::xattr::list_deref(from)? {
+        if let Some(value) = ::xattr::get_deref(from, &name)? {
+            ::xattr::set(to, &name, &value).annotate_err_into("name", || name.to_string_lossy())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn copy_xattrs(_from: &Path, _to: &Path) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn same_file(a: &std::fs::Metadata, b: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    (a.dev(), a.ino()) == (b.dev(), b.ino())
+}
+
+#[cfg(not(unix))]
+fn same_file(_a: &std::fs::Metadata, _b: &std::fs::Metadata) -> bool {
+    false
+}
+
+/// Make `to` share the extents of `from` copy-on-write, on filesystems which support it such as
+/// btrfs and xfs.
+#[cfg(target_os = "linux")]
+pub(crate) fn reflink(from: &File, to: &File) -> Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let rc = unsafe { libc::ioctl(to.as_raw_fd(), libc::FICLONE, from.as_raw_fd()) };
+    if rc == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn reflink(_from: &File, _to: &File) -> Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "reflinks are unsupported on this platform",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::scratch;
+    use crate::{CopyOptions, CopyStrategy, PathExt};
+    use std::path::Path;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn short_source_is_not_padded() {
+        // Files in sysfs report a length of a page but read back only their contents:
+        let src = Path::new("/sys/devices/system/cpu/online");
+        let dst = scratch("copy-short-source").join("online");
+
+        let report = src.pe_copy_with(&dst, CopyOptions::default()).unwrap();
+        let contents = std::fs::read(&dst).unwrap();
+        assert_eq!(contents, std::fs::read(src).unwrap());
+        assert_eq!(report.len, contents.len() as u64);
+        assert!(!contents.contains(&0));
+        // copy_file_range copies nothing from sysfs, so the data goes through a buffer:
+        assert_eq!(report.strategy, CopyStrategy::Buffered);
+    }
+
+    #[test]
+    fn sparse_copy_keeps_trailing_hole() {
+        let dir = scratch("copy-trailing-hole");
+        let src = dir.join("src");
+        std::fs::write(&src, "head").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&src)
+            .unwrap()
+            .set_len(1 << 20)
+            .unwrap();
+
+        let options = CopyOptions {
+            reflink: false,
+            sparse: true,
+            ..CopyOptions::default()
+        };
+        let report = src.pe_copy_with(dir.join("dst"), options).unwrap();
+        assert_eq!(report.len, 1 << 20);
+        assert_eq!(
+            std::fs::read(dir.join("dst")).unwrap(),
+            std::fs::read(&src).unwrap()
+        );
+    }
+
+    #[test]
+    fn strategy_reports_what_copied_the_data() {
+        let dir = scratch("copy-strategy");
+        let src = dir.join("src");
+        std::fs::write(&src, vec![7u8; 3 * 1024 * 1024]).unwrap();
+        let unlinked = || CopyOptions {
+            reflink: false,
+            ..CopyOptions::default()
+        };
+
+        let report = src.pe_copy_with(dir.join("dst"), unlinked()).unwrap();
+        assert_eq!(report.len, 3 * 1024 * 1024);
+        if cfg!(target_os = "linux") {
+            assert_eq!(report.strategy, CopyStrategy::CopyFileRange);
+        } else {
+            assert_eq!(report.strategy, CopyStrategy::Buffered);
+        }
+        assert!(crate::pathmove::same_contents(&src, &dir.join("dst")).unwrap());
+
+        // A reflink is used where supported, and otherwise the copy falls back:
+        let report = src
+            .pe_copy_with(dir.join("dst"), CopyOptions::default())
+            .unwrap();
+        assert_eq!(report.len, 3 * 1024 * 1024);
+        assert!(crate::pathmove::same_contents(&src, &dir.join("dst")).unwrap());
+
+        // Without data, nothing goes through copy_file_range:
+        std::fs::write(&src, "").unwrap();
+        let report = src.pe_copy_with(dir.join("dst"), unlinked()).unwrap();
+        assert_eq!((report.strategy, report.len), (CopyStrategy::Buffered, 0));
+        std::fs::File::options()
+            .write(true)
+            .open(&src)
+            .unwrap()
+            .set_len(1 << 20)
+            .unwrap();
+        let options = CopyOptions {
+            sparse: true,
+            ..unlinked()
+        };
+        let report = src.pe_copy_with(dir.join("dst"), options).unwrap();
+        assert_eq!(
+            (report.strategy, report.len),
+            (CopyStrategy::Buffered, 1 << 20)
+        );
+        assert_eq!(std::fs::read(dir.join("dst")).unwrap(), vec![0; 1 << 20]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn permissions_option() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = scratch("copy-permissions");
+        let (src, dst) = (dir.join("src"), dir.join("dst"));
+        let mode = |p: &Path| std::fs::metadata(p).unwrap().permissions().mode() & 0o7777;
+        std::fs::write(&src, "x").unwrap();
+        std::fs::set_permissions(&src, PermissionsExt::from_mode(0o640)).unwrap();
+        std::fs::write(&dst, "old").unwrap();
+        std::fs::set_permissions(&dst, PermissionsExt::from_mode(0o604)).unwrap();
+
+        let options = CopyOptions {
+            permissions: false,
+            ..CopyOptions::default()
+        };
+        src.pe_copy_with(&dst, options).unwrap();
+        assert_eq!(mode(&dst), 0o604);
+        src.pe_copy_with(&dst, CopyOptions::default()).unwrap();
+        assert_eq!(mode(&dst), 0o640);
+        assert_eq!(std::fs::read(&dst).unwrap(), b"x");
+    }
+
+    #[test]
+    fn timestamps_option() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let dir = scratch("copy-timestamps");
+        let (src, dst) = (dir.join("src"), dir.join("dst"));
+        std::fs::write(&src, "x").unwrap();
+        let atime = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        let mtime = UNIX_EPOCH + Duration::from_secs(1_100_000_000);
+        src.pe_set_times(atime, mtime).unwrap();
+        src.pe_copy_with(&dst, CopyOptions::default()).unwrap();
+        assert_ne!(dst.metadata().unwrap().modified().unwrap(), mtime);
+
+        // Reading the source may have updated its access time, so set it again:
+        src.pe_set_times(atime, mtime).unwrap();
+
+        let options = CopyOptions {
+            timestamps: true,
+            ..CopyOptions::default()
+        };
+        src.pe_copy_with(&dst, options).unwrap();
+        let md = dst.metadata().unwrap();
+        assert_eq!(md.modified().unwrap(), mtime);
+        assert_eq!(md.accessed().unwrap(), atime);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn xattrs_option() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = scratch("copy-xattrs");
+        let (src, dst) = (dir.join("src"), dir.join("dst"));
+        std::fs::write(&src, "x").unwrap();
+        src.pe_xattr_set("user.origin", "src").unwrap();
+
+        src.pe_copy_with(&dst, CopyOptions::default()).unwrap();
+        assert!(dst.pe_xattr_list().unwrap().is_empty());
+
+        // Permissions are applied last, so a read-only source still gets its attributes copied:
+        std::fs::set_permissions(&src, PermissionsExt::from_mode(0o444)).unwrap();
+        let options = CopyOptions {
+            xattrs: true,
+            timestamps: true,
+            ..CopyOptions::default()
+        };
+        src.pe_copy_with(&dst, options).unwrap();
+        assert_eq!(dst.pe_xattr_get("user.origin").unwrap(), b"src");
+        assert_eq!(dst.metadata().unwrap().permissions().mode() & 0o777, 0o444);
+    }
+}
@@ -0,0 +1,140 @@
+use error_annotation::AnnotateResult;
+use std::fs::DirBuilder;
+use std::io::{ErrorKind, Result};
+use std::path::Path;
+
+/// Options for [PathExt::pe_create_dir_with](crate::PathExt::pe_create_dir_with).
+///
+/// The default creates only the final directory, fails if it already exists, and uses mode
+/// `0o777` filtered by the process umask, like [std::fs::create_dir].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DirOptions {
+    /// Create any missing parents as well.
+    pub recursive: bool,
+    /// Succeed if a directory already exists at the path.
+    pub exist_ok: bool,
+    /// The unix permission mode of each directory created, which the umask applies to. This is
+    /// ignored on other platforms.
+    pub mode: u32,
+    /// Apply this umask to `mode` in place of the process umask. The process umask itself is not
+    /// changed, since it is shared by all threads; instead the directory is created with the masked
+    /// mode, which the process umask may narrow further, and then set to exactly that mode.
+    pub umask: Option<u32>,
+}
+
+impl Default for DirOptions {
+    fn default() -> Self {
+        DirOptions {
+            recursive: false,
+            exist_ok: false,
+            mode: 0o777,
+            umask: None,
+        }
+    }
+}
+
+pub(crate) fn create_dir_with(path: &Path, options: DirOptions) -> Result<()> {
+    let missing = match options.recursive {
+        true => missing_ancestors(path)?,
+        false => vec![path],
+    };
+
+    if missing.is_empty() && !options.exist_ok {
+        return Err(crate::error::errno(ErrorKind::AlreadyExists))
+            .annotate_err_into("path", || path.display());
+    }
+
+    // Create the shallowest missing ancestor first:
+    for &dir in missing.iter().rev() {
+        let is_final = dir == path;
+        match create_one(dir, &options) {
+            Ok(()) => {}
+            // Parents may be created concurrently, and the final dir may already exist:
+            Err(e) if e.kind() == ErrorKind::AlreadyExists && (!is_final || options.exist_ok) => {
+                if !dir.is_dir() {
+                    return Err(e).annotate_err_into("path", || path.display());
+                }
+            }
+            Err(e) if is_final => return Err(e).annotate_err_into("path", || path.display()),
+            Err(e) => {
+                return Err(e)
+                    .annotate_err_into("path", || path.display())
+                    .annotate_err_into("component", || dir.display());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `path` and its ancestors up to but excluding the deepest existing directory, deepest first.
+fn missing_ancestors(path: &Path) -> Result<Vec<&Path>> {
+    let mut missing = vec![];
+    for dir in path.ancestors().filter(|p| !p.as_os_str().is_empty()) {
+        match dir.metadata() {
+            Ok(md) if md.is_dir() => break,
+            Ok(_) if dir == path => {
+                missing.push(dir);
+                break;
+            }
+            Ok(_) => {
+                return Err(other_error_fmt!("not a directory"))
+                    .annotate_err_into("path", || path.display())
+                    .annotate_err_into("component", || dir.display());
+            }
+            // A file ancestor makes its descendants `NotADirectory`; keep going up to name it:
+            Err(e) if matches!(e.kind(), ErrorKind::NotFound | ErrorKind::NotADirectory) => {
+                missing.push(dir)
+            }
+            Err(e) => {
+                return Err(e)
+                    .annotate_err_into("path", || path.display())
+                    .annotate_err_into("component", || dir.display());
+            }
+        }
+    }
+    Ok(missing)
+}
+
+#[cfg(unix)]
+fn create_one(dir: &Path, options: &DirOptions) -> Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+    // Never create the directory with bits the override masks, even momentarily:
+    let mode = options
+        .umask
+        .map_or(options.mode, |umask| options.mode & !umask);
+    DirBuilder::new().mode(mode).create(dir)?;
+    if options.umask.is_some() {
+        // Restore any bits the process umask removed:
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(mode))?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn create_one(dir: &Path, _options: &DirOptions) -> Result<()> {
+    DirBuilder::new().create(dir)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::{create_dir_with, DirOptions};
+    use crate::testing::scratch;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn umask_override_sets_exact_mode() {
+        let dir = scratch("createdir-umask");
+        for (umask, expected) in [(0o077, 0o700), (0o000, 0o777)] {
+            let path = dir.join(format!("{:03o}/sub", umask));
+            let options = DirOptions {
+                recursive: true,
+                umask: Some(umask),
+                ..DirOptions::default()
+            };
+            create_dir_with(&path, options).unwrap();
+            let mode = path.metadata().unwrap().permissions().mode() & 0o7777;
+            assert_eq!(mode, expected);
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use crate::hash::{sha256_file, sha256_prefix};
+use crate::pathmove::same_contents;
+use crate::tempname::{create_new_file, create_temp};
+use crate::PathExt;
+use error_annotation::AnnotateResult;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::Result;
+use std::path::{Path, PathBuf};
+
+/// The number of leading bytes hashed to split same-size candidates before full hashing.
+const PARTIAL_HASH_LEN: u64 = 4096;
+
+/// Find groups of regular files with identical contents under `roots`.
+///
+/// Candidates are grouped by size, then by a hash of their first 4 KiB, and only then by a hash
+/// of their full contents, so most files are never read in full. Symlinks are not followed,
+/// empty files are ignored, and on unix, paths which are hardlinks to an inode already seen are
+/// skipped, since they already share storage.
+///
+/// Each group has at least two paths in sorted order, and groups are ordered by decreasing file
+/// size so the largest savings come first. Errors are annotated with the offending path.
+pub fn find_duplicates<I, P>(roots: I) -> Result<Vec<Vec<PathBuf>>>
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<Path>,
+{
+    let mut by_size: BTreeMap<u64, Vec<PathBuf>> = BTreeMap::new();
+    let mut inodes = HashSet::new();
+
+    for root in roots {
+        for res in root.as_ref().pe_walk() {
+            let md = res?;
+            if !md.is_file() || md.is_empty() || !inodes.insert(inode(&md)) {
+                continue;
+            }
+            by_size
+                .entry(md.len())
+                .or_default()
+                .push(md.path().to_path_buf());
+        }
+    }
+
+    let mut groups = vec![];
+    for (size, paths) in by_size.into_iter().rev() {
+        if paths.len() < 2 {
+            continue;
+        }
+        for partial in group_by(paths, |p| sha256_prefix(p, PARTIAL_HASH_LEN))? {
+            if size <= PARTIAL_HASH_LEN {
+                groups.push(partial);
+            } else {
+                groups.extend(group_by(partial, sha256_file)?);
+            }
+        }
+    }
+    Ok(groups)
+}
+
+/// Split `paths` by `key`, keeping only groups of at least two sorted paths.
+fn group_by<F>(paths: Vec<PathBuf>, key: F) -> Result<Vec<Vec<PathBuf>>>
+where
+    F: Fn(&Path) -> Result<String>,
+{
+    let mut groups: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    for p in paths {
+        groups.entry(key(&p)?).or_default().push(p);
+    }
+
+    let mut groups: Vec<Vec<PathBuf>> = groups
+        .into_values()
+        .filter(|g| g.len() > 1)
+        .map(|mut g| {
+            g.sort();
+            g
+        })
+        .collect();
+    groups.sort();
+    Ok(groups)
+}
+
+#[cfg(unix)]
+fn inode(md: &crate::PathMetadata) -> (u64, u64) {
+    use std::os::unix::fs::MetadataExt;
+
+    (md.metadata().dev(), md.metadata().ino())
+}
+
+/// Without inode numbers, every path is treated as a distinct file.
+#[cfg(not(unix))]
+fn inode(md: &crate::PathMetadata) -> PathBuf {
+    md.path().to_path_buf()
+}
+
+/// How [replace_duplicates] makes duplicates share storage.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DedupeMethod {
+    /// Replace each duplicate with a hardlink, so they also share permissions and times.
+    Hardlink,
+    /// Replace each duplicate with a copy-on-write clone, keeping its own permissions and times.
+    /// This requires a filesystem with reflink support, such as btrfs or xfs.
+    Reflink,
+}
+
+/// The outcome of [replace_duplicates].
+#[derive(Debug, Default)]
+pub struct DedupeReport {
+    /// The duplicates which now share storage with the first path of their group.
+    pub replaced: Vec<PathBuf>,
+    /// The failures for individual duplicates, annotated with the `keep` and `duplicate` paths.
+    pub errors: Vec<std::io::Error>,
+}
+
+/// Replace all but the first path of each group from [find_duplicates] with a link to the first.
+///
+/// Each replacement is prepared beside the duplicate and renamed over it, so a failure leaves the
+/// duplicate intact. Just before the rename, the duplicate is compared byte for byte with the
+/// first path, and skipped with an error if either changed since [find_duplicates] ran.
+pub fn replace_duplicates(groups: &[Vec<PathBuf>], method: DedupeMethod) -> DedupeReport {
+    let mut report = DedupeReport::default();
+    for group in groups {
+        let Some((keep, dups)) = group.split_first() else {
+            continue;
+        };
+        for dup in dups {
+            match replace(keep, dup, method)
+                .annotate_err_into("keep", || keep.display())
+                .annotate_err_into("duplicate", || dup.display())
+            {
+                Ok(()) => report.replaced.push(dup.clone()),
+                Err(e) => report.errors.push(e),
+            }
+        }
+    }
+    report
+}
+
+fn replace(keep: &Path, dup: &Path, method: DedupeMethod) -> Result<()> {
+    let keepmd = keep.pe_metadata()?;
+    let dupmd = dup.pe_symlink_metadata()?;
+    if !dupmd.is_file() || dupmd.len() != keepmd.len() {
+        return Err(other_error_fmt!("file changed since it was scanned"));
+    }
+
+    // The temporary name is created exclusively, so only an entry made here is ever removed:
+    let (tmp, res) = match method {
+        DedupeMethod::Hardlink => {
+            let (tmp, ()) = create_temp(dup, "dedupe-tmp", |tmp| keep.pe_hard_link(tmp))?;
+            (tmp, Ok(()))
+        }
+        DedupeMethod::Reflink => {
+            let (tmp, dst) = create_temp(dup, "dedupe-tmp", create_new_file)?;
+            let res = clone_file(keep, &tmp, &dst, &dupmd);
+            (tmp, res)
+        }
+    };
+    // Renaming destroys the duplicate, so make sure it still holds the same contents:
+    let res = res
+        .and_then(|()| match same_contents(keep, dup)? {
+            true => Ok(()),
+            false => Err(other_error_fmt!("file changed since it was scanned")),
+        })
+        .and_then(|()| tmp.pe_rename(dup));
+    if res.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    res
+}
+
+/// Make `dst`, the new file at `to`, a reflink of `from` with the permissions and times of
+/// `dupmd`.
+fn clone_file(from: &Path, to: &Path, dst: &File, dupmd: &crate::PathMetadata) -> Result<()> {
+    let src = from.pe_open()?;
+    crate::copy::reflink(src.file(), dst)
+        .annotate_err_into("from", || from.display())
+        .annotate_err_into("to", || to.display())?;
+
+    dst.set_permissions(dupmd.permissions())
+        .annotate_err_into("path", || to.display())?;
+    to.pe_set_times(dupmd.accessed()?, dupmd.modified()?)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use crate::tempname::plan_temp_paths;
+    use crate::testing::scratch;
+    use crate::{find_duplicates, replace_duplicates, DedupeMethod};
+    use std::os::unix::fs::MetadataExt;
+
+    #[test]
+    fn existing_temp_name_is_untouched() {
+        let dir = scratch("dedupe-temp-collision");
+        let (a, b) = (dir.join("a"), dir.join("b"));
+        std::fs::write(&a, "same").unwrap();
+        std::fs::write(&b, "same").unwrap();
+        let planned = plan_temp_paths(&b, "dedupe-tmp", &[1]);
+        std::fs::write(&planned[0], "not ours").unwrap();
+
+        let report = replace_duplicates(&[vec![a.clone(), b.clone()]], DedupeMethod::Hardlink);
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert_eq!(a.metadata().unwrap().ino(), b.metadata().unwrap().ino());
+
+        assert_eq!(std::fs::read_to_string(&planned[0]).unwrap(), "not ours");
+        assert_eq!(dir.read_dir().unwrap().count(), 3);
+    }
+
+    #[test]
+    fn same_length_edit_after_scan_is_kept() {
+        let dir = scratch("dedupe-edited");
+        let (a, b) = (dir.join("a"), dir.join("b"));
+        std::fs::write(&a, "same").unwrap();
+        std::fs::write(&b, "same").unwrap();
+
+        let groups = find_duplicates([&dir]).unwrap();
+        assert_eq!(groups, vec![vec![a.clone(), b.clone()]]);
+        std::fs::write(&b, "edit").unwrap();
+
+        let report = replace_duplicates(&groups, DedupeMethod::Hardlink);
+        assert!(report.replaced.is_empty());
+        assert_eq!(report.errors.len(), 1);
+        let errstr = report.errors[0].to_string();
+        assert!(
+            errstr.starts_with("file changed since it was scanned"),
+            "{}",
+            errstr
+        );
+
+        assert_eq!(std::fs::read_to_string(&b).unwrap(), "edit");
+        assert_ne!(a.metadata().unwrap().ino(), b.metadata().unwrap().ino());
+        assert_eq!(dir.read_dir().unwrap().count(), 2);
+    }
+
+    #[test]
+    fn failed_replacement_leaves_no_temp_file() {
+        let dir = scratch("dedupe-failed-cleanup");
+        let (a, b) = (dir.join("a"), dir.join("b"));
+        std::fs::write(&a, "same").unwrap();
+        std::fs::write(&b, "same").unwrap();
+
+        // Reflinks are unsupported on many test filesystems, where this fails:
+        let report = replace_duplicates(&[vec![a, b.clone()]], DedupeMethod::Reflink);
+        match report.errors.len() {
+            0 => assert_eq!(report.replaced, vec![b.clone()]),
+            1 => assert!(report.replaced.is_empty()),
+            _ => panic!("{:?}", report.errors),
+        }
+        assert_eq!(std::fs::read_to_string(&b).unwrap(), "same");
+        assert_eq!(dir.read_dir().unwrap().count(), 2);
+    }
+}
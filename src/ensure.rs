@@ -0,0 +1,196 @@
+use crate::pathmove::{remove_tree, symlink};
+use crate::tempname::create_temp;
+use crate::PathExt;
+use error_annotation::AnnotateResult;
+use std::io::{ErrorKind, Result};
+use std::path::Path;
+
+/// Whether an `pe_ensure_…` operation of [PathExt] had to change the filesystem.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EnsureOutcome {
+    Changed,
+    /// The path was already in the desired state.
+    Unchanged,
+}
+
+impl EnsureOutcome {
+    pub fn is_changed(self) -> bool {
+        self == EnsureOutcome::Changed
+    }
+}
+
+use EnsureOutcome::*;
+
+/// Return the symlink metadata of `path`, or `None` if nothing exists there.
+fn existing(path: &Path) -> Result<Option<std::fs::Metadata>> {
+    match path.symlink_metadata() {
+        Ok(md) => Ok(Some(md)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).annotate_err_into("path", || path.display()),
+    }
+}
+
+fn wrong_type(path: &Path, expected: &str) -> std::io::Error {
+    Err::<(), _>(other_error_fmt!("exists but is not a {}", expected))
+        .annotate_err_into("path", || path.display())
+        .unwrap_err()
+}
+
+pub(crate) fn ensure_dir(path: &Path, mode: u32) -> Result<EnsureOutcome> {
+    let md = match std::fs::metadata(path) {
+        Ok(md) => md,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            path.pe_create_dir_all()?;
+            set_mode(path, mode)?;
+            return Ok(Changed);
+        }
+        Err(e) => return Err(e).annotate_err_into("path", || path.display()),
+    };
+    if !md.is_dir() {
+        return Err(wrong_type(path, "directory"));
+    }
+    match mode_of(&md).is_none_or(|m| m == mode & 0o7777) {
+        true => Ok(Unchanged),
+        false => set_mode(path, mode).map(|()| Changed),
+    }
+}
+
+#[cfg(unix)]
+fn mode_of(md: &std::fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+
+    Some(md.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn mode_of(_md: &std::fs::Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+        .annotate_err_into("path", || path.display())
+        .annotate_err_into("mode", || format!("{:o}", mode))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> Result<()> {
+    Ok(())
+}
+
+pub(crate) fn ensure_file(path: &Path, contents: &[u8]) -> Result<EnsureOutcome> {
+    if let Some(md) = existing(path)? {
+        if !md.is_file() {
+            return Err(wrong_type(path, "file"));
+        }
+        if md.len() == contents.len() as u64 && path.pe_read()? == contents {
+            return Ok(Unchanged);
+        }
+    }
+    path.pe_write(contents).map(|()| Changed)
+}
+
+pub(crate) fn ensure_symlink(path: &Path, target: &Path) -> Result<EnsureOutcome> {
+    let Some(md) = existing(path)? else {
+        return symlink(target, path).map(|()| Changed);
+    };
+    if !md.is_symlink() {
+        return Err(wrong_type(path, "symlink"));
+    }
+    if path.pe_read_link()? == target {
+        return Ok(Unchanged);
+    }
+
+    // Replace the link by renaming a new one over it, so it never goes missing:
+    let (tmp, ()) = create_temp(path, "ensure-tmp", |tmp| symlink(target, tmp))?;
+    let res = tmp.pe_rename(path);
+    if res.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    res.map(|()| Changed)
+}
+
+pub(crate) fn ensure_absent(path: &Path) -> Result<EnsureOutcome> {
+    match existing(path)? {
+        None => Ok(Unchanged),
+        Some(_) => remove_tree(path).map(|()| Changed),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::{ensure_absent, ensure_dir, ensure_file, ensure_symlink, EnsureOutcome::*};
+    use crate::tempname::plan_temp_paths;
+    use crate::testing::scratch;
+    use std::path::Path;
+
+    #[test]
+    fn ensure_file_reports_changes() {
+        let dir = scratch("ensure-file");
+        let f = dir.join("f");
+        assert_eq!(ensure_file(&f, b"one").unwrap(), Changed);
+        assert_eq!(ensure_file(&f, b"one").unwrap(), Unchanged);
+        assert_eq!(ensure_file(&f, b"two").unwrap(), Changed);
+        assert_eq!(std::fs::read(&f).unwrap(), b"two");
+        assert_eq!(ensure_absent(&f).unwrap(), Changed);
+        assert_eq!(ensure_absent(&f).unwrap(), Unchanged);
+    }
+
+    #[test]
+    fn ensure_symlink_replaces_target() {
+        let dir = scratch("ensure-symlink");
+        let link = dir.join("link");
+        assert_eq!(ensure_symlink(&link, Path::new("a")).unwrap(), Changed);
+        assert_eq!(ensure_symlink(&link, Path::new("a")).unwrap(), Unchanged);
+        assert_eq!(ensure_symlink(&link, Path::new("b")).unwrap(), Changed);
+        assert_eq!(std::fs::read_link(&link).unwrap(), Path::new("b"));
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn ensure_symlink_leaves_foreign_temp_names_alone() {
+        let dir = scratch("ensure-symlink-foreign-tmp");
+        let link = dir.join("link");
+        std::os::unix::fs::symlink("a", &link).unwrap();
+        // The first temporary name tried is taken, so the replacement must use another:
+        let foreign = plan_temp_paths(&link, "ensure-tmp", &[1]).remove(0);
+        std::fs::write(&foreign, "someone else's").unwrap();
+
+        assert_eq!(ensure_symlink(&link, Path::new("b")).unwrap(), Changed);
+        assert_eq!(std::fs::read_link(&link).unwrap(), Path::new("b"));
+        assert_eq!(std::fs::read(&foreign).unwrap(), b"someone else's");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn ensure_dir_sets_mode_on_final_component_only() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = scratch("ensure-dir-mode");
+        let leaf = dir.join("a/b/leaf");
+        let mode = |p: &Path| std::fs::metadata(p).unwrap().permissions().mode() & 0o7777;
+        assert_eq!(ensure_dir(&leaf, 0o700).unwrap(), Changed);
+        assert_eq!(mode(&leaf), 0o700);
+        // A plain new directory shows the default mode under the current umask:
+        std::fs::create_dir(dir.join("plain")).unwrap();
+        let default = mode(&dir.join("plain"));
+        assert_eq!(mode(&dir.join("a")), default);
+        assert_eq!(mode(&dir.join("a/b")), default);
+
+        assert_eq!(ensure_dir(&leaf, 0o700).unwrap(), Unchanged);
+        assert_eq!(ensure_dir(&leaf, 0o750).unwrap(), Changed);
+        assert_eq!(mode(&leaf), 0o750);
+        assert_eq!(mode(&dir.join("a/b")), default);
+    }
+
+    #[test]
+    fn ensure_symlink_rejects_other_types() {
+        let dir = scratch("ensure-symlink-wrong-type");
+        std::fs::write(dir.join("f"), "").unwrap();
+        let err = ensure_symlink(&dir.join("f"), Path::new("a")).unwrap_err();
+        assert!(err.to_string().starts_with("exists but is not a symlink"));
+    }
+}
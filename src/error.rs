@@ -1,9 +1,37 @@
-use std::io::{Error, ErrorKind::Other};
+use std::io::{Error, ErrorKind, ErrorKind::Other};
 
 pub(crate) fn other_error(msg: String) -> Error {
     Error::new(Other, msg)
 }
 
+/// An error of `kind` with the OS error code [OsFs](crate::OsFs) would report, where known, so
+/// that simulated errors read the same as real ones.
+#[cfg(unix)]
+pub(crate) fn errno(kind: ErrorKind) -> Error {
+    let code = match kind {
+        ErrorKind::NotFound => libc::ENOENT,
+        ErrorKind::PermissionDenied => libc::EACCES,
+        ErrorKind::AlreadyExists => libc::EEXIST,
+        ErrorKind::NotADirectory => libc::ENOTDIR,
+        ErrorKind::IsADirectory => libc::EISDIR,
+        ErrorKind::DirectoryNotEmpty => libc::ENOTEMPTY,
+        ErrorKind::StorageFull => libc::ENOSPC,
+        ErrorKind::ResourceBusy => libc::EBUSY,
+        ErrorKind::InvalidInput => libc::EINVAL,
+        ErrorKind::ReadOnlyFilesystem => libc::EROFS,
+        ErrorKind::CrossesDevices => libc::EXDEV,
+        ErrorKind::Interrupted => libc::EINTR,
+        ErrorKind::WouldBlock => libc::EAGAIN,
+        _ => return Error::from(kind),
+    };
+    Error::from_raw_os_error(code)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn errno(kind: ErrorKind) -> Error {
+    Error::from(kind)
+}
+
 macro_rules! other_error_fmt {
     ( $tmpl:expr ) => {
         crate::other_error($tmpl.to_string())
@@ -0,0 +1,162 @@
+use error_annotation::AnnotateResult;
+use std::ffi::OsString;
+use std::io::Result;
+use std::path::{Path, PathBuf};
+
+/// Look up a variable in the process environment.
+pub(crate) fn env_var(name: &str) -> Option<OsString> {
+    std::env::var_os(name)
+}
+
+/// The home directory from `HOME`, falling back to the user database on unix.
+pub(crate) fn home_dir<F>(lookup: &F) -> Option<PathBuf>
+where
+    F: Fn(&str) -> Option<OsString>,
+{
+    match lookup("HOME") {
+        Some(home) if !home.is_empty() => Some(PathBuf::from(home)),
+        _ => user_db_home(None),
+    }
+}
+
+#[cfg(unix)]
+fn user_db_home(name: Option<&str>) -> Option<PathBuf> {
+    match name {
+        None => crate::unixnames::current_home_dir(),
+        Some(name) => crate::unixnames::home_dir_of(name),
+    }
+}
+
+#[cfg(not(unix))]
+fn user_db_home(_name: Option<&str>) -> Option<PathBuf> {
+    None
+}
+
+pub(crate) fn expand<F>(path: &Path, lookup: F) -> Result<PathBuf>
+where
+    F: Fn(&str) -> Option<OsString>,
+{
+    let bytes = path.as_os_str().as_encoded_bytes();
+    let mut out = vec![];
+
+    let rest = expand_tilde(bytes, &lookup, &mut out)?;
+    expand_vars(rest, &lookup, &mut out)?;
+
+    // SAFETY: `out` only concatenates slices of `as_encoded_bytes` output from `path` or from
+    // looked up values, where every split occurs next to an ASCII byte.
+    let os = unsafe { OsString::from_encoded_bytes_unchecked(out) };
+    Ok(PathBuf::from(os))
+}
+
+/// Expand a leading `~` or `~user` component into `out`, returning the remaining input.
+fn expand_tilde<'a, F>(bytes: &'a [u8], lookup: &F, out: &mut Vec<u8>) -> Result<&'a [u8]>
+where
+    F: Fn(&str) -> Option<OsString>,
+{
+    let Some(after) = bytes.strip_prefix(b"~") else {
+        return Ok(bytes);
+    };
+
+    let end = after.iter().position(|&b| b == b'/').unwrap_or(after.len());
+    let (name, rest) = after.split_at(end);
+
+    let home = if name.is_empty() {
+        home_dir(lookup).ok_or_else(|| other_error_fmt!("unknown home directory"))?
+    } else {
+        let name = std::str::from_utf8(name).map_err(|_| other_error_fmt!("invalid utf8"))?;
+        user_db_home(Some(name))
+            .ok_or_else(|| other_error_fmt!("unknown user"))
+            .annotate_err_into("user", || name)?
+    };
+
+    out.extend_from_slice(home.as_os_str().as_encoded_bytes());
+    Ok(rest)
+}
+
+/// Expand `$VAR`, `${VAR}`, and `${VAR:-default}` references into `out`.
+fn expand_vars<F>(mut bytes: &[u8], lookup: &F, out: &mut Vec<u8>) -> Result<()>
+where
+    F: Fn(&str) -> Option<OsString>,
+{
+    while let Some(ix) = bytes.iter().position(|&b| b == b'$') {
+        out.extend_from_slice(&bytes[..ix]);
+        bytes = &bytes[ix + 1..];
+
+        if let Some(braced) = bytes.strip_prefix(b"{") {
+            let close =
+                matching_brace(braced).ok_or_else(|| other_error_fmt!("unterminated ${"))?;
+            let (name, default) = match find(&braced[..close], b":-") {
+                Some(dix) => (&braced[..dix], Some(&braced[dix + 2..close])),
+                None => (&braced[..close], None),
+            };
+            let name = var_name(name)?;
+            let value = lookup(name).filter(|v| default.is_none() || !v.is_empty());
+            match (value, default) {
+                (Some(value), _) => out.extend_from_slice(value.as_encoded_bytes()),
+                (None, Some(default)) => expand_vars(default, lookup, out)?,
+                (None, None) => return undefined(name),
+            }
+            bytes = &braced[close + 1..];
+        } else {
+            let len = bytes
+                .iter()
+                .enumerate()
+                .take_while(|&(i, &b)| {
+                    b == b'_' || b.is_ascii_alphabetic() || (i > 0 && b.is_ascii_digit())
+                })
+                .count();
+
+            if len == 0 {
+                out.push(b'$');
+            } else {
+                let name = var_name(&bytes[..len])?;
+                match lookup(name) {
+                    Some(value) => out.extend_from_slice(value.as_encoded_bytes()),
+                    None => return undefined(name),
+                }
+                bytes = &bytes[len..];
+            }
+        }
+    }
+
+    out.extend_from_slice(bytes);
+    Ok(())
+}
+
+fn undefined(name: &str) -> Result<()> {
+    Err(other_error_fmt!("undefined variable")).annotate_err_into("variable", || name)
+}
+
+fn var_name(bytes: &[u8]) -> Result<&str> {
+    let valid = |name: &&str| {
+        !name.is_empty()
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+            && name.bytes().all(|b| b == b'_' || b.is_ascii_alphanumeric())
+    };
+
+    std::str::from_utf8(bytes)
+        .ok()
+        .filter(valid)
+        .ok_or_else(|| other_error_fmt!("invalid variable name"))
+        .annotate_err_into("variable", || String::from_utf8_lossy(bytes))
+}
+
+/// Find the `}` which closes an already opened `${`, accounting for nested `${…}` defaults.
+fn matching_brace(bytes: &[u8]) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut prev = 0u8;
+    for (i, &b) in bytes.iter().enumerate() {
+        match b {
+            b'{' if prev == b'$' => depth += 1,
+            b'}' if depth == 0 => return Some(i),
+            b'}' => depth -= 1,
+            _ => {}
+        }
+        prev = b;
+    }
+    None
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
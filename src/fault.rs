@@ -0,0 +1,376 @@
+use crate::error::errno;
+use crate::glob::glob_match;
+use crate::{Filesystem, FsMetadata};
+use indoc::indoc;
+use std::fmt;
+use std::io::{ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::SystemTime;
+
+/// The operations of a [Filesystem], for selecting which ones a [Fault] applies to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FsOp {
+    Metadata,
+    SymlinkMetadata,
+    Read,
+    Write,
+    CreateDir,
+    CreateDirAll,
+    ReadDir,
+    RemoveFile,
+    RemoveDir,
+    RemoveDirAll,
+    Rename,
+    Copy,
+    Symlink,
+    ReadLink,
+    SetMode,
+    SetModified,
+}
+
+impl fmt::Display for FsOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use FsOp::*;
+
+        f.write_str(match self {
+            Metadata => "metadata",
+            SymlinkMetadata => "symlink_metadata",
+            Read => "read",
+            Write => "write",
+            CreateDir => "create_dir",
+            CreateDirAll => "create_dir_all",
+            ReadDir => "read_dir",
+            RemoveFile => "remove_file",
+            RemoveDir => "remove_dir",
+            RemoveDirAll => "remove_dir_all",
+            Rename => "rename",
+            Copy => "copy",
+            Symlink => "symlink",
+            ReadLink => "read_link",
+            SetMode => "set_mode",
+            SetModified => "set_modified",
+        })
+    }
+}
+
+/// A deterministic failure injected by [FaultFs] into calls of one [FsOp] on paths matching a
+/// glob.
+///
+/// The glob is matched against each path argument as given, where `*` and `?` match within one
+/// component, `[...]` matches a character set, and `**` matches any number of components. Both
+/// paths of [FsOp::Rename], [FsOp::Copy], and [FsOp::Symlink] are matched.
+#[derive(Clone, Debug)]
+pub struct Fault {
+    op: FsOp,
+    glob: String,
+    kind: ErrorKind,
+    short_write: Option<usize>,
+    on_call: Option<u64>,
+}
+
+impl Fault {
+    /// Fail `op` on paths matching `glob` with an error of `kind`.
+    ///
+    /// The error carries the OS error code for `kind` where one is known, so its message matches
+    /// a real failure.
+    pub fn error<G>(op: FsOp, glob: G, kind: ErrorKind) -> Self
+    where
+        G: Into<String>,
+    {
+        Fault {
+            op,
+            glob: glob.into(),
+            kind,
+            short_write: None,
+            on_call: None,
+        }
+    }
+
+    /// Make writes to paths matching `glob` store only their first `len` bytes, then fail with an
+    /// error of `kind`, as when a disk fills up part way through.
+    pub fn short_write<G>(glob: G, len: usize, kind: ErrorKind) -> Self
+    where
+        G: Into<String>,
+    {
+        Fault {
+            short_write: Some(len),
+            ..Fault::error(FsOp::Write, glob, kind)
+        }
+    }
+
+    /// Fail only the `n`th matching call, counting from 1, rather than every one.
+    pub fn on_call(self, n: u64) -> Self {
+        Fault {
+            on_call: Some(n),
+            ..self
+        }
+    }
+
+    fn matches(&self, op: FsOp, paths: &[&Path]) -> bool {
+        self.op == op
+            && paths
+                .iter()
+                .any(|p| glob_match(&self.glob, &p.to_string_lossy()))
+    }
+}
+
+#[derive(Debug)]
+struct FaultState {
+    fault: Fault,
+    calls: u64,
+}
+
+/// A [Filesystem] wrapper which makes chosen operations fail deterministically, for testing error
+/// handling.
+///
+/// Each call is checked against the [Fault]s in the order they were added, and the first which
+/// triggers determines the failure. Every matching fault counts the call, whether or not it
+/// triggers, so [Fault::on_call] counts are independent of each other. Calls which no fault
+/// triggers are passed through to the inner filesystem.
+///
+/// Faults only reach code which performs its operations through a [Filesystem], such as the
+/// [FilesystemExt](crate::FilesystemExt) methods; [PathExt](crate::PathExt) methods call the OS
+/// directly and cannot fail here. [PathExt::pe_write](crate::PathExt::pe_write),
+/// [PathExt::pe_rename](crate::PathExt::pe_rename), and
+/// [PathExt::pe_remove_dir_all](crate::PathExt::pe_remove_dir_all) are implemented with the
+/// [FilesystemExt](crate::FilesystemExt) methods of the same names on [OsFs](crate::OsFs), so a
+/// `FaultFs<OsFs>` reproduces their errors exactly.
+///
+#[cfg_attr(
+    target_os = "linux",
+    doc = indoc! {r#"
+        # Example
+
+        ```
+        use pathutil::{Fault, FaultFs, FilesystemExt, FsOp, MemFs};
+        use std::io::ErrorKind;
+
+        let fault = Fault::error(FsOp::Rename, "/data/**", ErrorKind::PermissionDenied);
+        let fs = FaultFs::new(MemFs::new()).with_fault(fault.on_call(2));
+
+        fs.pe_create_dir("/data").unwrap();
+        fs.pe_write("/data/a", "contents").unwrap();
+        fs.pe_rename("/data/a", "/data/b").unwrap();
+
+        let res = fs.pe_rename("/data/b", "/data/c");
+        assert!(res.is_err());
+
+        let errstr = res.err().unwrap().to_string();
+        assert_eq!(&errstr, "
+
+        Permission denied (os error 13)
+        -with from: /data/b
+        -with to: /data/c
+
+        ".trim());
+        ```
+    "#}
+)]
+#[derive(Debug)]
+pub struct FaultFs<F> {
+    inner: F,
+    faults: Mutex<Vec<FaultState>>,
+}
+
+impl<F> FaultFs<F>
+where
+    F: Filesystem,
+{
+    /// Wrap `inner` without any faults, so every call passes through until one is added.
+    pub fn new(inner: F) -> Self {
+        FaultFs {
+            inner,
+            faults: Mutex::new(vec![]),
+        }
+    }
+
+    /// Add `fault` after any existing ones.
+    pub fn with_fault(self, fault: Fault) -> Self {
+        self.add_fault(fault);
+        self
+    }
+
+    /// Add `fault` after any existing ones.
+    pub fn add_fault(&self, fault: Fault) {
+        self.lock().push(FaultState { fault, calls: 0 });
+    }
+
+    /// Remove all faults, so every call passes through.
+    pub fn clear_faults(&self) {
+        self.lock().clear();
+    }
+
+    /// Access the wrapped [Filesystem].
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+
+    /// Unwrap the wrapped [Filesystem].
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<FaultState>> {
+        self.faults.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Count a call against matching faults, returning the first which triggers.
+    fn trip(&self, op: FsOp, paths: &[&Path]) -> Option<Fault> {
+        let mut tripped = None;
+        for state in self.lock().iter_mut() {
+            if state.fault.matches(op, paths) {
+                state.calls += 1;
+                let triggers = state.fault.on_call.is_none_or(|n| n == state.calls);
+                if triggers && tripped.is_none() {
+                    tripped = Some(state.fault.clone());
+                }
+            }
+        }
+        tripped
+    }
+
+    fn check(&self, op: FsOp, paths: &[&Path]) -> Result<()> {
+        match self.trip(op, paths) {
+            Some(fault) => Err(errno(fault.kind)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<F> Filesystem for FaultFs<F>
+where
+    F: Filesystem,
+{
+    fn metadata(&self, path: &Path) -> Result<FsMetadata> {
+        self.check(FsOp::Metadata, &[path])?;
+        self.inner.metadata(path)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> Result<FsMetadata> {
+        self.check(FsOp::SymlinkMetadata, &[path])?;
+        self.inner.symlink_metadata(path)
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        self.check(FsOp::Read, &[path])?;
+        self.inner.read(path)
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        match self.trip(FsOp::Write, &[path]) {
+            None => self.inner.write(path, contents),
+            Some(fault) => {
+                if let Some(len) = fault.short_write {
+                    self.inner
+                        .write(path, &contents[..len.min(contents.len())])?;
+                }
+                Err(errno(fault.kind))
+            }
+        }
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<()> {
+        self.check(FsOp::CreateDir, &[path])?;
+        self.inner.create_dir(path)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<()> {
+        self.check(FsOp::CreateDirAll, &[path])?;
+        self.inner.create_dir_all(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<PathBuf>> {
+        self.check(FsOp::ReadDir, &[path])?;
+        self.inner.read_dir(path)
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<()> {
+        self.check(FsOp::RemoveFile, &[path])?;
+        self.inner.remove_file(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> Result<()> {
+        self.check(FsOp::RemoveDir, &[path])?;
+        self.inner.remove_dir(path)
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> Result<()> {
+        self.check(FsOp::RemoveDirAll, &[path])?;
+        self.inner.remove_dir_all(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        self.check(FsOp::Rename, &[from, to])?;
+        self.inner.rename(from, to)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<u64> {
+        self.check(FsOp::Copy, &[from, to])?;
+        self.inner.copy(from, to)
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> Result<()> {
+        self.check(FsOp::Symlink, &[target, link])?;
+        self.inner.symlink(target, link)
+    }
+
+    fn read_link(&self, path: &Path) -> Result<PathBuf> {
+        self.check(FsOp::ReadLink, &[path])?;
+        self.inner.read_link(path)
+    }
+
+    fn set_mode(&self, path: &Path, mode: u32) -> Result<()> {
+        self.check(FsOp::SetMode, &[path])?;
+        self.inner.set_mode(path, mode)
+    }
+
+    fn set_modified(&self, path: &Path, modified: SystemTime) -> Result<()> {
+        self.check(FsOp::SetModified, &[path])?;
+        self.inner.set_modified(path, modified)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use crate::testing::scratch;
+    use crate::{Fault, FaultFs, FilesystemExt, FsOp, OsFs, PathExt};
+    use std::io::ErrorKind;
+
+    #[test]
+    fn injected_errors_match_path_ext_errors() {
+        let dir = scratch("fault-matches-pathext");
+        let (missing, to) = (dir.join("missing"), dir.join("to"));
+        let fs = FaultFs::new(OsFs)
+            .with_fault(Fault::error(
+                FsOp::Write,
+                "**/missing/f",
+                ErrorKind::NotFound,
+            ))
+            .with_fault(Fault::error(
+                FsOp::Rename,
+                "**/missing",
+                ErrorKind::NotFound,
+            ))
+            .with_fault(Fault::error(
+                FsOp::RemoveDirAll,
+                "**/missing",
+                ErrorKind::NotFound,
+            ));
+
+        let real = missing.join("f").pe_write("x").unwrap_err().to_string();
+        assert_eq!(
+            fs.pe_write(missing.join("f"), "x").unwrap_err().to_string(),
+            real
+        );
+
+        let real = missing.pe_rename(&to).unwrap_err().to_string();
+        assert_eq!(fs.pe_rename(&missing, &to).unwrap_err().to_string(), real);
+
+        let real = missing.pe_remove_dir_all().unwrap_err().to_string();
+        assert_eq!(
+            fs.pe_remove_dir_all(&missing).unwrap_err().to_string(),
+            real
+        );
+    }
+}
@@ -0,0 +1,427 @@
+use crate::FileTypeEnum;
+use error_annotation::AnnotateResult;
+use indoc::indoc;
+use std::fs::Metadata;
+use std::io::Result;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// The metadata reported by a [Filesystem], independent of any backend.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FsMetadata {
+    pub file_type: FileTypeEnum,
+    pub len: u64,
+    /// The unix permission bits; on other platforms, `0o444` or `0o644` plus `0o111` for
+    /// directories, depending on the readonly flag.
+    pub mode: u32,
+    pub modified: SystemTime,
+}
+
+impl FsMetadata {
+    pub fn is_dir(&self) -> bool {
+        self.file_type == FileTypeEnum::Dir
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.file_type == FileTypeEnum::File
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.file_type == FileTypeEnum::Symlink
+    }
+
+    pub(crate) fn from_std(md: &Metadata) -> Result<Self> {
+        let ft = md.file_type();
+        if !(ft.is_dir() || ft.is_file() || ft.is_symlink()) {
+            return Err(other_error_fmt!("unsupported file type"));
+        }
+
+        Ok(FsMetadata {
+            file_type: FileTypeEnum::from(ft),
+            len: md.len(),
+            mode: std_mode(md),
+            modified: md.modified()?,
+        })
+    }
+}
+
+#[cfg(unix)]
+fn std_mode(md: &Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+
+    md.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn std_mode(md: &Metadata) -> u32 {
+    let base = if md.permissions().readonly() {
+        0o444
+    } else {
+        0o644
+    };
+    if md.is_dir() {
+        base | 0o111
+    } else {
+        base
+    }
+}
+
+/// The filesystem operations underlying [FilesystemExt], so that code may run against either the
+/// OS via [OsFs] or an in-memory [MemFs](crate::MemFs) in tests.
+///
+/// These methods mirror [std::fs] and, like it, return errors without the path. Callers should
+/// normally use the annotating `pe_…` methods of [FilesystemExt] instead.
+pub trait Filesystem {
+    /// Query metadata, following symlinks.
+    fn metadata(&self, path: &Path) -> Result<FsMetadata>;
+
+    /// Query metadata without following a final symlink.
+    fn symlink_metadata(&self, path: &Path) -> Result<FsMetadata>;
+
+    /// Read the entire contents of a file.
+    fn read(&self, path: &Path) -> Result<Vec<u8>>;
+
+    /// Create or truncate a file and write `contents` to it.
+    fn write(&self, path: &Path, contents: &[u8]) -> Result<()>;
+
+    /// Create a new, empty directory.
+    fn create_dir(&self, path: &Path) -> Result<()>;
+
+    /// Create a directory and all of its missing parents.
+    fn create_dir_all(&self, path: &Path) -> Result<()>;
+
+    /// List the paths of a directory's entries, each joined onto `path`.
+    fn read_dir(&self, path: &Path) -> Result<Vec<PathBuf>>;
+
+    /// Remove a file or symlink.
+    fn remove_file(&self, path: &Path) -> Result<()>;
+
+    /// Remove an empty directory.
+    fn remove_dir(&self, path: &Path) -> Result<()>;
+
+    /// Remove a directory after removing all its contents.
+    fn remove_dir_all(&self, path: &Path) -> Result<()>;
+
+    /// Rename a file or directory, replacing `to` if it exists.
+    fn rename(&self, from: &Path, to: &Path) -> Result<()>;
+
+    /// Copy a file's contents and permissions, returning the number of bytes copied.
+    fn copy(&self, from: &Path, to: &Path) -> Result<u64>;
+
+    /// Create a symlink at `link` pointing to `target`.
+    fn symlink(&self, target: &Path, link: &Path) -> Result<()>;
+
+    /// Read the target of a symlink.
+    fn read_link(&self, path: &Path) -> Result<PathBuf>;
+
+    /// Set the unix permission bits, following symlinks.
+    fn set_mode(&self, path: &Path, mode: u32) -> Result<()>;
+
+    /// Set the modification time, following symlinks.
+    fn set_modified(&self, path: &Path, modified: SystemTime) -> Result<()>;
+}
+
+/// The [Filesystem] of the operating system, implemented with [std::fs].
+#[derive(Copy, Clone, Debug, Default)]
+pub struct OsFs;
+
+impl Filesystem for OsFs {
+    fn metadata(&self, path: &Path) -> Result<FsMetadata> {
+        FsMetadata::from_std(&std::fs::metadata(path)?)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> Result<FsMetadata> {
+        FsMetadata::from_std(&std::fs::symlink_metadata(path)?)
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        std::fs::read(path)
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        std::fs::write(path, contents)
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<()> {
+        std::fs::create_dir(path)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<()> {
+        std::fs::create_dir_all(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<PathBuf>> {
+        std::fs::read_dir(path)?
+            .map(|res| res.map(|de| de.path()))
+            .collect()
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<()> {
+        std::fs::remove_file(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> Result<()> {
+        std::fs::remove_dir(path)
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> Result<()> {
+        std::fs::remove_dir_all(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        std::fs::rename(from, to)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<u64> {
+        std::fs::copy(from, to)
+    }
+
+    #[cfg(unix)]
+    fn symlink(&self, target: &Path, link: &Path) -> Result<()> {
+        std::os::unix::fs::symlink(target, link)
+    }
+
+    #[cfg(not(unix))]
+    fn symlink(&self, _target: &Path, _link: &Path) -> Result<()> {
+        Err(other_error_fmt!(
+            "symlinks are unsupported on this platform"
+        ))
+    }
+
+    fn read_link(&self, path: &Path) -> Result<PathBuf> {
+        std::fs::read_link(path)
+    }
+
+    #[cfg(unix)]
+    fn set_mode(&self, path: &Path, mode: u32) -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+    }
+
+    #[cfg(not(unix))]
+    fn set_mode(&self, path: &Path, mode: u32) -> Result<()> {
+        let mut perms = std::fs::metadata(path)?.permissions();
+        perms.set_readonly(mode & 0o222 == 0);
+        std::fs::set_permissions(path, perms)
+    }
+
+    fn set_modified(&self, path: &Path, modified: SystemTime) -> Result<()> {
+        filetime::set_file_mtime(path, filetime::FileTime::from_system_time(modified))
+    }
+}
+
+/// Extend every [Filesystem] with `pe_…` methods which annotate errors with the paths involved,
+/// in the same manner as [PathExt](crate::PathExt).
+///
+#[cfg_attr(
+    target_os = "linux",
+    doc = indoc! {r#"
+        # Example
+
+        ```
+        use pathutil::{FilesystemExt, MemFs};
+        use std::io::ErrorKind;
+
+        let fs = MemFs::new();
+        fs.pe_create_dir("/data").unwrap();
+        fs.inject_error("/data/out", ErrorKind::PermissionDenied);
+
+        let res = fs.pe_write("/data/out", "contents");
+        assert!(res.is_err());
+
+        let errstr = res.err().unwrap().to_string();
+        assert_eq!(&errstr, "
+
+        Permission denied (os error 13)
+        -with path: /data/out
+
+        ".trim());
+        ```
+    "#}
+)]
+pub trait FilesystemExt: Filesystem {
+    /// Return the [FsMetadata], following symlinks, or include the path in the error.
+    fn pe_metadata<P>(&self, path: P) -> Result<FsMetadata>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.metadata(path)
+            .annotate_err_into("path", || path.display())
+    }
+
+    /// Return the symlink's own [FsMetadata] or include the path in the error.
+    fn pe_symlink_metadata<P>(&self, path: P) -> Result<FsMetadata>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.symlink_metadata(path)
+            .annotate_err_into("path", || path.display())
+    }
+
+    /// Read the entire contents of the file or include the path in the error.
+    fn pe_read<P>(&self, path: P) -> Result<Vec<u8>>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.read(path).annotate_err_into("path", || path.display())
+    }
+
+    /// Read the entire contents of the file as utf8 or include the path in the error.
+    fn pe_read_to_string<P>(&self, path: P) -> Result<String>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.read(path)
+            .and_then(|bytes| {
+                String::from_utf8(bytes)
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+            })
+            .annotate_err_into("path", || path.display())
+    }
+
+    /// Create or truncate the file and write `contents` to it, or include the path in the error.
+    fn pe_write<P, C>(&self, path: P, contents: C) -> Result<()>
+    where
+        P: AsRef<Path>,
+        C: AsRef<[u8]>,
+    {
+        let path = path.as_ref();
+        self.write(path, contents.as_ref())
+            .annotate_err_into("path", || path.display())
+    }
+
+    /// Create a new, empty directory or include the path in the error.
+    fn pe_create_dir<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.create_dir(path)
+            .annotate_err_into("path", || path.display())
+    }
+
+    /// Create a directory and all of its missing parents, or include the path in the error.
+    fn pe_create_dir_all<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.create_dir_all(path)
+            .annotate_err_into("path", || path.display())
+    }
+
+    /// List the paths of the directory's entries or include the path in the error.
+    fn pe_read_dir<P>(&self, path: P) -> Result<Vec<PathBuf>>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.read_dir(path)
+            .annotate_err_into("path", || path.display())
+    }
+
+    /// Remove a file or symlink, or include the path in the error.
+    fn pe_remove_file<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.remove_file(path)
+            .annotate_err_into("path", || path.display())
+    }
+
+    /// Remove an empty directory or include the path in the error.
+    fn pe_remove_dir<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.remove_dir(path)
+            .annotate_err_into("path", || path.display())
+    }
+
+    /// Remove a directory after removing all its contents, or include the path in the error.
+    fn pe_remove_dir_all<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.remove_dir_all(path)
+            .annotate_err_into("path", || path.display())
+    }
+
+    /// Rename `from` to `to`, or include both paths in the error.
+    fn pe_rename<P, Q>(&self, from: P, to: Q) -> Result<()>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let (from, to) = (from.as_ref(), to.as_ref());
+        self.rename(from, to)
+            .annotate_err_into("from", || from.display())
+            .annotate_err_into("to", || to.display())
+    }
+
+    /// Copy a file's contents and permissions, or include both paths in the error.
+    fn pe_copy<P, Q>(&self, from: P, to: Q) -> Result<u64>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let (from, to) = (from.as_ref(), to.as_ref());
+        self.copy(from, to)
+            .annotate_err_into("from", || from.display())
+            .annotate_err_into("to", || to.display())
+    }
+
+    /// Create a symlink at `link` pointing to `target`, or include both paths in the error.
+    fn pe_symlink<P, Q>(&self, target: P, link: Q) -> Result<()>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let (target, link) = (target.as_ref(), link.as_ref());
+        self.symlink(target, link)
+            .annotate_err_into("target", || target.display())
+            .annotate_err_into("link", || link.display())
+    }
+
+    /// Return the symlink's referent path or include the path in the error.
+    fn pe_read_link<P>(&self, path: P) -> Result<PathBuf>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.read_link(path)
+            .annotate_err_into("path", || path.display())
+    }
+
+    /// Set the unix permission bits, or include the path and mode in the error.
+    fn pe_set_mode<P>(&self, path: P, mode: u32) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.set_mode(path, mode)
+            .annotate_err_into("path", || path.display())
+            .annotate_err_into("mode", || format!("{:o}", mode))
+    }
+
+    /// Set the modification time, or include the path and time in the error.
+    fn pe_set_modified<P>(&self, path: P, modified: SystemTime) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.set_modified(path, modified)
+            .annotate_err_into("path", || path.display())
+            .annotate_err_into("modified", || crate::Timestamp(modified))
+    }
+}
+
+impl<F> FilesystemExt for F where F: Filesystem + ?Sized {}
@@ -1,6 +1,11 @@
 use std::fs::FileType;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "json",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
 pub enum FileTypeEnum {
     Dir,
     File,
@@ -22,3 +27,16 @@ impl From<FileType> for FileTypeEnum {
         }
     }
 }
+
+impl FileTypeEnum {
+    /// The `ls -l` type character: `d`, `-`, or `l`.
+    pub fn type_char(&self) -> char {
+        use FileTypeEnum::*;
+
+        match self {
+            Dir => 'd',
+            File => '-',
+            Symlink => 'l',
+        }
+    }
+}
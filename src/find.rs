@@ -0,0 +1,531 @@
+use crate::glob::glob_match;
+use crate::{FileTypeEnum, PathExt, PathMetadata, PathWalk};
+use std::io::Result;
+use std::ops::{Bound, RangeBounds};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// A query over the tree rooted at a path in the manner of `find(1)`, created by
+/// [PathExt::pe_find].
+///
+/// Each builder method adds a test, and the iterator yields the [PathMetadata] of every entry
+/// which passes all of them, in the order of [PathWalk], starting with the root itself. Symlinks
+/// are not followed. Errors are annotated with the offending path, and the search continues past
+/// them.
+///
+/// Name globs are matched against the file name, where `*` and `?` match any characters and
+/// `[...]` matches a character set.
+///
+/// # Example
+///
+/// ```
+/// use pathutil::{FileTypeEnum, PathExt};
+///
+/// let root = std::env::temp_dir().join("pathutil-find-doctest");
+/// let _ = std::fs::remove_dir_all(&root);
+/// std::fs::create_dir_all(root.join("src/target")).unwrap();
+/// std::fs::write(root.join("src/lib.rs"), "// lib").unwrap();
+/// std::fs::write(root.join("src/empty.rs"), "").unwrap();
+/// std::fs::write(root.join("src/target/gen.rs"), "// generated").unwrap();
+///
+/// let found: Vec<_> = root
+///     .pe_find()
+///     .name("*.rs")
+///     .file_type(FileTypeEnum::File)
+///     .size(1..)
+///     .prune("target")
+///     .map(|res| res.unwrap().path().strip_prefix(&root).unwrap().to_path_buf())
+///     .collect();
+/// assert_eq!(found, [std::path::Path::new("src/lib.rs")]);
+/// ```
+#[derive(Debug)]
+pub struct Find {
+    walk: PathWalk,
+    #[cfg(unix)]
+    root_dev: Option<u64>,
+    names: Vec<String>,
+    #[cfg(feature = "regex")]
+    name_regexes: Vec<regex::Regex>,
+    prunes: Vec<String>,
+    file_type: Option<FileTypeEnum>,
+    size: Option<(Bound<u64>, Bound<u64>)>,
+    modified: Option<(Bound<SystemTime>, Bound<SystemTime>)>,
+    accessed: Option<(Bound<SystemTime>, Bound<SystemTime>)>,
+    #[cfg(unix)]
+    perm_all: Option<u32>,
+    #[cfg(unix)]
+    perm_any: Option<u32>,
+    #[cfg(unix)]
+    owner: Option<u32>,
+    #[cfg(unix)]
+    group: Option<u32>,
+    empty: bool,
+    min_depth: usize,
+    xdev: bool,
+}
+
+impl Find {
+    pub fn new<P>(root: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Find {
+            walk: PathWalk::new(root),
+            #[cfg(unix)]
+            root_dev: None,
+            names: vec![],
+            #[cfg(feature = "regex")]
+            name_regexes: vec![],
+            prunes: vec![],
+            file_type: None,
+            size: None,
+            modified: None,
+            accessed: None,
+            #[cfg(unix)]
+            perm_all: None,
+            #[cfg(unix)]
+            perm_any: None,
+            #[cfg(unix)]
+            owner: None,
+            #[cfg(unix)]
+            group: None,
+            empty: false,
+            min_depth: 0,
+            xdev: false,
+        }
+    }
+
+    /// Match entries whose file name matches `glob`, like `-name`.
+    pub fn name<G>(mut self, glob: G) -> Self
+    where
+        G: Into<String>,
+    {
+        self.names.push(glob.into());
+        self
+    }
+
+    /// Match entries whose file name matches `re`, like `-regex` but on the file name only.
+    #[cfg(feature = "regex")]
+    pub fn name_regex(mut self, re: regex::Regex) -> Self {
+        self.name_regexes.push(re);
+        self
+    }
+
+    /// Neither yield nor descend into entries below the root whose file name matches `glob`,
+    /// like `-name glob -prune -o`.
+    pub fn prune<G>(mut self, glob: G) -> Self
+    where
+        G: Into<String>,
+    {
+        self.prunes.push(glob.into());
+        self
+    }
+
+    /// Match entries of `file_type`, like `-type`.
+    pub fn file_type(self, file_type: FileTypeEnum) -> Self {
+        Find {
+            file_type: Some(file_type),
+            ..self
+        }
+    }
+
+    /// Match entries whose length in bytes is within `range`, like `-size`.
+    pub fn size<R>(self, range: R) -> Self
+    where
+        R: RangeBounds<u64>,
+    {
+        Find {
+            size: Some(bounds(&range)),
+            ..self
+        }
+    }
+
+    /// Match entries whose modification time is within `range`, like `-newermt`.
+    pub fn modified<R>(self, range: R) -> Self
+    where
+        R: RangeBounds<SystemTime>,
+    {
+        Find {
+            modified: Some(bounds(&range)),
+            ..self
+        }
+    }
+
+    /// Match entries whose access time is within `range`, like `-newerat`.
+    pub fn accessed<R>(self, range: R) -> Self
+    where
+        R: RangeBounds<SystemTime>,
+    {
+        Find {
+            accessed: Some(bounds(&range)),
+            ..self
+        }
+    }
+
+    /// Match entries with all of the permission `bits` set, like `-perm -bits`.
+    #[cfg(unix)]
+    pub fn perm_all(self, bits: u32) -> Self {
+        Find {
+            perm_all: Some(bits),
+            ..self
+        }
+    }
+
+    /// Match entries with any of the permission `bits` set, like `-perm /bits`.
+    #[cfg(unix)]
+    pub fn perm_any(self, bits: u32) -> Self {
+        Find {
+            perm_any: Some(bits),
+            ..self
+        }
+    }
+
+    /// Match entries owned by the user `uid`, like `-uid`.
+    #[cfg(unix)]
+    pub fn owner(self, uid: u32) -> Self {
+        Find {
+            owner: Some(uid),
+            ..self
+        }
+    }
+
+    /// Match entries owned by the group `gid`, like `-gid`.
+    #[cfg(unix)]
+    pub fn group(self, gid: u32) -> Self {
+        Find {
+            group: Some(gid),
+            ..self
+        }
+    }
+
+    /// Match empty files and directories, like `-empty`.
+    pub fn empty(self) -> Self {
+        Find {
+            empty: true,
+            ..self
+        }
+    }
+
+    /// Do not yield entries shallower than `min_depth`, where the root is depth 0.
+    pub fn min_depth(self, min_depth: usize) -> Self {
+        Find { min_depth, ..self }
+    }
+
+    /// Do not descend into directories deeper than `max_depth`, where the root is depth 0.
+    pub fn max_depth(self, max_depth: usize) -> Self {
+        Find {
+            walk: self.walk.max_depth(max_depth),
+            ..self
+        }
+    }
+
+    /// Do not descend into directories on other filesystems than the root, like `-xdev`. The
+    /// mount points themselves are still tested.
+    #[cfg(unix)]
+    pub fn xdev(self) -> Self {
+        Find { xdev: true, ..self }
+    }
+
+    /// Whether `md` passes every test; only `-empty` on a directory needs to read it.
+    fn matches(&self, md: &PathMetadata, depth: usize) -> Result<bool> {
+        let name = file_name(md.path());
+        let found = depth >= self.min_depth
+            && self.names.iter().all(|g| glob_match(g, &name))
+            && self.matches_regexes(&name)
+            && self.file_type.is_none_or(|ft| match ft {
+                FileTypeEnum::Dir => md.is_dir(),
+                FileTypeEnum::File => md.is_file(),
+                FileTypeEnum::Symlink => md.is_symlink(),
+            })
+            && self.size.is_none_or(|r| r.contains(&md.len()))
+            && self.matches_time(self.modified, md.modified())?
+            && self.matches_time(self.accessed, md.accessed())?
+            && self.matches_unix(md);
+        if !found || !self.empty {
+            return Ok(found);
+        }
+        match md.is_dir() {
+            true => Ok(md.path().pe_read_dir()?.next().is_none()),
+            false => Ok(md.is_file() && md.is_empty()),
+        }
+    }
+
+    #[cfg(feature = "regex")]
+    fn matches_regexes(&self, name: &str) -> bool {
+        self.name_regexes.iter().all(|re| re.is_match(name))
+    }
+
+    #[cfg(not(feature = "regex"))]
+    fn matches_regexes(&self, _name: &str) -> bool {
+        true
+    }
+
+    fn matches_time(
+        &self,
+        range: Option<(Bound<SystemTime>, Bound<SystemTime>)>,
+        time: Result<SystemTime>,
+    ) -> Result<bool> {
+        match range {
+            Some(r) => Ok(r.contains(&time?)),
+            None => Ok(true),
+        }
+    }
+
+    #[cfg(unix)]
+    fn matches_unix(&self, md: &PathMetadata) -> bool {
+        use std::os::unix::fs::MetadataExt;
+
+        let md = md.metadata();
+        self.perm_all.is_none_or(|bits| md.mode() & bits == bits)
+            && self.perm_any.is_none_or(|bits| md.mode() & bits != 0)
+            && self.owner.is_none_or(|uid| md.uid() == uid)
+            && self.group.is_none_or(|gid| md.gid() == gid)
+    }
+
+    #[cfg(not(unix))]
+    fn matches_unix(&self, _md: &PathMetadata) -> bool {
+        true
+    }
+
+    /// Skip the contents of the directory `md` if it is on another filesystem than the root.
+    #[cfg(unix)]
+    fn check_xdev(&mut self, md: &PathMetadata) {
+        use std::os::unix::fs::MetadataExt;
+
+        let dev = md.metadata().dev();
+        match self.root_dev {
+            None => self.root_dev = Some(dev),
+            Some(root_dev) if root_dev != dev => self.walk.skip_current_dir(),
+            Some(_) => {}
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn check_xdev(&mut self, _md: &PathMetadata) {}
+}
+
+impl Iterator for Find {
+    type Item = Result<PathMetadata<'static>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let md = match self.walk.next()? {
+                Ok(md) => md,
+                Err(e) => return Some(Err(e)),
+            };
+            let depth = self.walk.depth();
+
+            let name = file_name(md.path());
+            if depth > 0 && self.prunes.iter().any(|g| glob_match(g, &name)) {
+                self.walk.skip_current_dir();
+                continue;
+            }
+            if self.xdev && md.is_dir() {
+                self.check_xdev(&md);
+            }
+
+            match self.matches(&md, depth) {
+                Ok(true) => return Some(Ok(md)),
+                Ok(false) => {}
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+fn bounds<T, R>(range: &R) -> (Bound<T>, Bound<T>)
+where
+    T: Copy,
+    R: RangeBounds<T>,
+{
+    (range.start_bound().cloned(), range.end_bound().cloned())
+}
+
+/// The file name of `path` for matching, or the whole path for a root such as `/`.
+fn file_name(path: &Path) -> String {
+    match path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => path.to_string_lossy().into_owned(),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use crate::testing::scratch;
+    use crate::{Find, PathExt};
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    /// The sorted paths found relative to `root`, with `.` for the root itself.
+    fn found(root: &Path, find: Find) -> Vec<String> {
+        let mut found: Vec<_> = find
+            .map(|res| {
+                let md = res.unwrap();
+                match md.path().strip_prefix(root).unwrap().to_str().unwrap() {
+                    "" => ".".to_string(),
+                    rel => rel.to_string(),
+                }
+            })
+            .collect();
+        found.sort();
+        found
+    }
+
+    /// A tree of `a` (mode 0o644), `b` (mode 0o750), and `sub/c` (mode 0o4755).
+    fn tree(name: &str) -> std::path::PathBuf {
+        let root = scratch(name);
+        std::fs::create_dir(root.join("sub")).unwrap();
+        for (rel, mode) in [("a", 0o644), ("b", 0o750), ("sub/c", 0o4755)] {
+            let path = root.join(rel);
+            std::fs::write(&path, rel).unwrap();
+            std::fs::set_permissions(&path, PermissionsExt::from_mode(mode)).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn modified_and_accessed_ranges() {
+        let root = tree("find-times");
+        let t = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+        root.join("a").pe_set_times(t(3000), t(1000)).unwrap();
+        root.join("b").pe_set_times(t(1000), t(2000)).unwrap();
+        root.join("sub/c").pe_set_times(t(2000), t(3000)).unwrap();
+        let files = || root.pe_find().file_type(crate::FileTypeEnum::File);
+
+        assert_eq!(found(&root, files().modified(t(2000)..)), ["b", "sub/c"]);
+        assert_eq!(found(&root, files().modified(..t(2000))), ["a"]);
+        assert_eq!(
+            found(&root, files().modified(t(1000)..=t(2000))),
+            ["a", "b"]
+        );
+        assert_eq!(found(&root, files().accessed(t(2000)..)), ["a", "sub/c"]);
+        assert_eq!(found(&root, files().accessed(..=t(1000))), ["b"]);
+        let both = files().modified(t(2000)..).accessed(t(2000)..);
+        assert_eq!(found(&root, both), ["sub/c"]);
+    }
+
+    #[test]
+    fn perm_all_and_any() {
+        let root = tree("find-perm");
+        let files = || root.pe_find().file_type(crate::FileTypeEnum::File);
+
+        assert_eq!(found(&root, files().perm_all(0o644)), ["a", "sub/c"]);
+        assert_eq!(found(&root, files().perm_all(0o750)), ["b", "sub/c"]);
+        assert_eq!(found(&root, files().perm_all(0o4000)), ["sub/c"]);
+        assert_eq!(found(&root, files().perm_any(0o111)), ["b", "sub/c"]);
+        assert_eq!(found(&root, files().perm_any(0o022)), Vec::<String>::new());
+        assert_eq!(found(&root, files().perm_any(0o4004)), ["a", "sub/c"]);
+    }
+
+    #[test]
+    fn owner_and_group() {
+        let root = tree("find-owner");
+        // Changing ownership to another user needs privileges, so reassign only when possible:
+        let other = std::os::unix::fs::chown(root.join("b"), Some(4321), Some(8765)).is_ok();
+        let md = root.pe_symlink_metadata().unwrap();
+        let (uid, gid) = {
+            use std::os::unix::fs::MetadataExt;
+            (md.metadata().uid(), md.metadata().gid())
+        };
+        let mine = match other {
+            true => vec![".", "a", "sub", "sub/c"],
+            false => vec![".", "a", "b", "sub", "sub/c"],
+        };
+
+        assert_eq!(found(&root, root.pe_find().owner(uid)), mine);
+        assert_eq!(found(&root, root.pe_find().group(gid)), mine);
+        if other {
+            assert_eq!(found(&root, root.pe_find().owner(4321)), ["b"]);
+            assert_eq!(found(&root, root.pe_find().group(8765)), ["b"]);
+            let mixed = root.pe_find().owner(4321).group(gid);
+            assert_eq!(found(&root, mixed), Vec::<String>::new());
+        }
+    }
+
+    #[test]
+    fn empty_files_and_dirs() {
+        let root = tree("find-empty");
+        std::fs::create_dir(root.join("hollow")).unwrap();
+        std::fs::write(root.join("sub/nothing"), "").unwrap();
+
+        // `sub` holds files, empty or not, so it is not itself empty:
+        let empty = root.pe_find().empty();
+        assert_eq!(found(&root, empty), ["hollow", "sub/nothing"]);
+        let empty_dirs = root.pe_find().empty().file_type(crate::FileTypeEnum::Dir);
+        assert_eq!(found(&root, empty_dirs), ["hollow"]);
+    }
+
+    #[test]
+    fn min_and_max_depth() {
+        let root = tree("find-depth");
+        std::fs::create_dir_all(root.join("sub/deeper")).unwrap();
+        std::fs::write(root.join("sub/deeper/d"), "d").unwrap();
+
+        let all = [".", "a", "b", "sub", "sub/c", "sub/deeper", "sub/deeper/d"];
+        assert_eq!(found(&root, root.pe_find()), all);
+        assert_eq!(found(&root, root.pe_find().max_depth(0)), ["."]);
+        assert_eq!(
+            found(&root, root.pe_find().max_depth(1)),
+            [".", "a", "b", "sub"]
+        );
+        assert_eq!(
+            found(&root, root.pe_find().min_depth(2)),
+            ["sub/c", "sub/deeper", "sub/deeper/d"]
+        );
+        let band = root.pe_find().min_depth(1).max_depth(2);
+        assert_eq!(found(&root, band), ["a", "b", "sub", "sub/c", "sub/deeper"]);
+        let inverted = root.pe_find().min_depth(2).max_depth(1);
+        assert_eq!(found(&root, inverted), Vec::<String>::new());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn xdev_stops_at_mount_points() {
+        use std::os::unix::fs::MetadataExt;
+
+        // `/dev/pts` and `/dev/shm` are separate filesystems on practically every linux system:
+        let root = Path::new("/dev");
+        let dev = root.metadata().unwrap().dev();
+        let mounts: Vec<_> = ["pts", "shm"]
+            .iter()
+            .map(|name| root.join(name))
+            .filter(|p| p.metadata().is_ok_and(|md| md.dev() != dev))
+            .collect();
+        assert!(!mounts.is_empty(), "no mount points under /dev");
+
+        let paths = |find: Find| -> Vec<_> {
+            find.filter_map(|res| res.ok().map(|md| md.path().to_path_buf()))
+                .collect()
+        };
+        let crossing = paths(root.pe_find().max_depth(2));
+        let staying = paths(root.pe_find().max_depth(2).xdev());
+        for mount in &mounts {
+            assert!(staying.contains(mount), "{} missing", mount.display());
+            assert!(staying.iter().all(|p| !p.starts_with(mount) || p == mount));
+        }
+        // Without xdev, the walk enters the mounts, such as `/dev/pts/ptmx`:
+        let entered = |p: &&std::path::PathBuf| mounts.iter().any(|m| p.starts_with(m) && *p != m);
+        assert!(crossing.iter().any(|p| entered(&p)));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn name_regex() {
+        let root = tree("find-regex");
+        let re = |s| regex::Regex::new(s).unwrap();
+
+        let single = root.pe_find().name_regex(re("^[ab]$"));
+        assert_eq!(found(&root, single), ["a", "b"]);
+        // The regex sees only the file name, so `sub/` is not part of what it matches:
+        let anchored = root.pe_find().name_regex(re("^c$"));
+        assert_eq!(found(&root, anchored), ["sub/c"]);
+        let nested = root.pe_find().name_regex(re("^sub/c$"));
+        assert_eq!(found(&root, nested), Vec::<String>::new());
+        // Several regexes must all match, and combine with globs:
+        let all = root.pe_find().name_regex(re("[a-c]")).name_regex(re("^.$"));
+        assert_eq!(found(&root, all), ["a", "b", "sub/c"]);
+        let with_glob = root.pe_find().name_regex(re("^.$")).name("[bc]");
+        assert_eq!(found(&root, with_glob), ["b", "sub/c"]);
+    }
+}
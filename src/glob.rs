@@ -0,0 +1,76 @@
+//! Shell-style glob matching of `/`-separated paths.
+//!
+//! Within a component, `*` matches any run of characters, `?` any one character, and `[...]` one
+//! character from a set such as `[abc]`, `[a-z]`, or the negated `[!abc]`. A component which is
+//! exactly `**` matches any number of whole components, including none.
+
+/// Whether `text` matches the glob `pattern`.
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    let pats: Vec<&str> = pattern.split('/').collect();
+    let parts: Vec<&str> = text.split('/').collect();
+    match_components(&pats, &parts)
+}
+
+fn match_components(pats: &[&str], parts: &[&str]) -> bool {
+    match pats.split_first() {
+        None => parts.is_empty(),
+        Some((&"**", rest)) => (0..=parts.len()).any(|i| match_components(rest, &parts[i..])),
+        Some((pat, rest)) => match parts.split_first() {
+            Some((part, parts)) => {
+                let pat: Vec<char> = pat.chars().collect();
+                let part: Vec<char> = part.chars().collect();
+                match_component(&pat, &part) && match_components(rest, parts)
+            }
+            None => false,
+        },
+    }
+}
+
+fn match_component(pat: &[char], text: &[char]) -> bool {
+    match pat.split_first() {
+        None => text.is_empty(),
+        Some(('*', rest)) => (0..=text.len()).any(|i| match_component(rest, &text[i..])),
+        Some(('?', rest)) => !text.is_empty() && match_component(rest, &text[1..]),
+        Some(('[', rest)) => match (class_end(rest), text.split_first()) {
+            (Some(end), Some((&c, text))) => {
+                in_class(&rest[..end], c) && match_component(&rest[end + 1..], text)
+            }
+            (Some(_), None) => false,
+            // An unterminated `[` is literal:
+            (None, _) => text.first() == Some(&'[') && match_component(rest, &text[1..]),
+        },
+        Some((&p, rest)) => text.first() == Some(&p) && match_component(rest, &text[1..]),
+    }
+}
+
+/// The index of the `]` closing a class which starts at `rest`, where a leading `]` is literal.
+fn class_end(rest: &[char]) -> Option<usize> {
+    let start = match rest.first() {
+        Some('!') => 1,
+        _ => 0,
+    };
+    rest.iter()
+        .skip(start + 1)
+        .position(|&c| c == ']')
+        .map(|i| i + start + 1)
+}
+
+fn in_class(class: &[char], c: char) -> bool {
+    let (negated, class) = match class.split_first() {
+        Some(('!', rest)) => (true, rest),
+        _ => (false, class),
+    };
+
+    let mut found = false;
+    let mut i = 0;
+    while i < class.len() {
+        if i + 2 < class.len() && class[i + 1] == '-' {
+            found |= class[i] <= c && c <= class[i + 2];
+            i += 3;
+        } else {
+            found |= class[i] == c;
+            i += 1;
+        }
+    }
+    found != negated
+}
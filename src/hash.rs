@@ -0,0 +1,64 @@
+use crate::progress::{check_cancelled, Quiet};
+use crate::{Observer, PathExt, ProgressEvent};
+use error_annotation::AnnotateResult;
+use sha2::{Digest, Sha256};
+use std::io::{Read, Result};
+use std::path::Path;
+
+/// The lowercase hex sha256 digest of the contents of the file at `path`.
+pub(crate) fn sha256_file(path: &Path) -> Result<String> {
+    sha256_file_observed(path, &mut Quiet)
+}
+
+/// [sha256_file], reporting progress to `observer` and stopping between chunks if it is
+/// cancelled.
+pub(crate) fn sha256_file_observed(path: &Path, observer: &mut dyn Observer) -> Result<String> {
+    let check = |observer: &dyn Observer| {
+        check_cancelled(observer).annotate_err_into("path", || path.display())
+    };
+
+    observer.event(ProgressEvent::Entry(path));
+    check(observer)?;
+    let total = path.pe_metadata()?.len();
+    sha256_reader(path.pe_open()?, |done| {
+        observer.event(ProgressEvent::Bytes { path, done, total });
+        check(observer)
+    })
+}
+
+/// The lowercase hex sha256 digest of at most the first `len` bytes of the file at `path`.
+pub(crate) fn sha256_prefix(path: &Path, len: u64) -> Result<String> {
+    sha256_reader(path.pe_open()?.take(len), |_| Ok(()))
+}
+
+/// Hash the contents of `r`, calling `tick` with the number of bytes read so far after each
+/// chunk.
+fn sha256_reader<R, F>(mut r: R, mut tick: F) -> Result<String>
+where
+    R: Read,
+    F: FnMut(u64) -> Result<()>,
+{
+    let mut done = 0;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = r.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        done += n as u64;
+        tick(done)?;
+    }
+    Ok(hex(&hasher.finalize()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    use std::fmt::Write;
+
+    let mut s = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        write!(s, "{:02x}", b).unwrap();
+    }
+    s
+}
@@ -0,0 +1,400 @@
+use crate::PathExt;
+use error_annotation::AnnotateResult;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{ErrorKind, Result};
+use std::path::{Component, Path, PathBuf};
+
+/// The maximum number of symlinks followed in one resolution, matching linux `MAXSYMLINKS`.
+const MAX_SYMLINKS: usize = 40;
+
+/// Resolve untrusted relative paths beneath a root directory.
+///
+/// Resolution rejects absolute components, `..` components which would climb above the root,
+/// and symlinks whose targets lie outside the root. Errors name the offending component, and
+/// when the escape happens through a symlink, the symlink as well.
+///
+/// [PathJail::resolve] inspects the filesystem one component at a time without following
+/// symlinks, so it is subject to races with concurrent modifications of the tree. On Linux,
+/// [PathJail::open] instead relies on the kernel via `openat2(…, RESOLVE_BENEATH)`, and
+/// elsewhere, or without `openat2`, it opens each component with `openat(…, O_NOFOLLOW)`.
+///
+/// # Example
+///
+/// ```
+/// use pathutil::PathJail;
+///
+/// let jail = PathJail::new("/").unwrap();
+/// assert!(jail.resolve("tmp/upload.txt").is_ok());
+///
+/// let res = jail.resolve("tmp/../../etc/passwd");
+/// assert!(res.is_err());
+///
+/// let errstr = res.err().unwrap().to_string();
+/// assert_eq!(&errstr, "
+///
+/// path escapes root
+/// -with component: ..
+/// -with root: /
+/// -with path: tmp/../../etc/passwd
+///
+/// ".trim());
+/// ```
+#[derive(Clone, Debug)]
+pub struct PathJail {
+    root: PathBuf,
+}
+
+impl PathJail {
+    /// Create a `PathJail` for `root`, which is canonicalized so symlink targets can be checked
+    /// against it.
+    pub fn new<P>(root: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let root = root.as_ref().pe_canonicalize()?;
+        Ok(PathJail { root })
+    }
+
+    /// Access the canonical root [Path].
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Resolve `untrusted` beneath the root, following symlinks which stay within the root.
+    ///
+    /// Components which do not exist yet are joined lexically, so the result may name a new file
+    /// to be created.
+    ///
+    /// This is not race-safe: each component is checked with `lstat` and the returned path is
+    /// only a snapshot, so a concurrent writer may swap a component for a symlink out of the root
+    /// before the caller uses it. Use [PathJail::open] where the tree may be modified concurrently.
+    pub fn resolve<P>(&self, untrusted: P) -> Result<PathBuf>
+    where
+        P: AsRef<Path>,
+    {
+        let untrusted = untrusted.as_ref();
+        resolve(&self.root, untrusted)
+            .annotate_err_into("root", || self.root.display())
+            .annotate_err_into("path", || untrusted.display())
+    }
+
+    /// Open `untrusted` beneath the root for reading, without ever following a symlink out of the
+    /// root.
+    ///
+    /// On Linux the kernel rejects traversing any absolute symlink here, even one which
+    /// [PathJail::resolve] accepts because its target lies within the root.
+    pub fn open<P>(&self, untrusted: P) -> Result<File>
+    where
+        P: AsRef<Path>,
+    {
+        let untrusted = untrusted.as_ref();
+        open(self, untrusted)
+            .annotate_err_into("root", || self.root.display())
+            .annotate_err_into("path", || untrusted.display())
+    }
+}
+
+/// A component awaiting resolution, along with the symlink which introduced it, if any.
+struct Pending {
+    component: PendingComponent,
+    via: Option<PathBuf>,
+}
+
+enum PendingComponent {
+    Cur,
+    Parent,
+    Name(OsString),
+}
+
+fn resolve(root: &Path, untrusted: &Path) -> Result<PathBuf> {
+    let mut pending = vec![];
+    push_components(&mut pending, untrusted, None)?;
+
+    let mut resolved = PathBuf::new();
+    let mut links = 0;
+
+    while let Some(Pending { component, via }) = pending.pop() {
+        match component {
+            PendingComponent::Cur => {}
+            PendingComponent::Parent => {
+                if !resolved.pop() {
+                    return escape("..", via.as_deref());
+                }
+            }
+            PendingComponent::Name(name) => {
+                let candidate = resolved.join(&name);
+                let full = root.join(&candidate);
+                let md = match full.symlink_metadata() {
+                    Ok(md) => md,
+                    Err(e) if e.kind() == ErrorKind::NotFound => {
+                        resolved = candidate;
+                        continue;
+                    }
+                    Err(e) => return Err(e).annotate_err_into("component", || candidate.display()),
+                };
+
+                if !md.file_type().is_symlink() {
+                    resolved = candidate;
+                    continue;
+                }
+
+                links += 1;
+                if links > MAX_SYMLINKS {
+                    return Err(other_error_fmt!("too many levels of symlinks"))
+                        .annotate_err_into("component", || candidate.display());
+                }
+
+                let target = full.pe_read_link()?;
+                if target.is_absolute() {
+                    match target.strip_prefix(root) {
+                        Ok(within) => {
+                            resolved = PathBuf::new();
+                            push_components(&mut pending, within, Some(&candidate))?;
+                        }
+                        Err(_) => return escape(&target.display().to_string(), Some(&candidate)),
+                    }
+                } else {
+                    push_components(&mut pending, &target, Some(&candidate))?;
+                }
+            }
+        }
+    }
+
+    Ok(root.join(resolved))
+}
+
+/// Push the components of `path` so they pop off `pending` in order.
+fn push_components(pending: &mut Vec<Pending>, path: &Path, via: Option<&Path>) -> Result<()> {
+    let mut components = vec![];
+    for c in path.components() {
+        let component = match c {
+            Component::Prefix(_) | Component::RootDir => {
+                return Err(other_error_fmt!("absolute path component"))
+                    .annotate_err_into("component", || path.display());
+            }
+            Component::CurDir => PendingComponent::Cur,
+            Component::ParentDir => PendingComponent::Parent,
+            Component::Normal(name) => PendingComponent::Name(name.to_os_string()),
+        };
+        components.push(Pending {
+            component,
+            via: via.map(Path::to_path_buf),
+        });
+    }
+
+    pending.extend(components.into_iter().rev());
+    Ok(())
+}
+
+fn escape(component: &str, via: Option<&Path>) -> Result<PathBuf> {
+    let res =
+        Err(other_error_fmt!("path escapes root")).annotate_err_into("component", || component);
+    match via {
+        Some(link) => res.annotate_err_into("symlink", || link.display()),
+        None => res,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn open(jail: &PathJail, untrusted: &Path) -> Result<File> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+
+    let rootdir = File::open(&jail.root).annotate_err_into("path", || jail.root.display())?;
+    let cpath = CString::new(untrusted.as_os_str().as_bytes())
+        .map_err(|_| other_error_fmt!("path contains a nul byte"))?;
+
+    let mut how: libc::open_how = unsafe { std::mem::zeroed() };
+    how.flags = (libc::O_RDONLY | libc::O_CLOEXEC) as u64;
+    how.resolve = libc::RESOLVE_BENEATH | libc::RESOLVE_NO_MAGICLINKS;
+
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_openat2,
+            rootdir.as_raw_fd(),
+            cpath.as_ptr(),
+            &how as *const libc::open_how,
+            std::mem::size_of::<libc::open_how>(),
+        )
+    };
+
+    if fd >= 0 {
+        return Ok(unsafe { File::from_raw_fd(fd as libc::c_int) });
+    }
+
+    let err = std::io::Error::last_os_error();
+    match err.raw_os_error() {
+        // Kernels before 5.6 lack openat2, and seccomp filters may deny it:
+        Some(libc::ENOSYS) | Some(libc::EPERM) => open_beneath(jail, untrusted),
+        // The kernel detected an escape, so describe which component escaped if possible:
+        Some(libc::EXDEV) => resolve(&jail.root, untrusted).and(Err(other_error_fmt!(
+            "path escapes root, or traverses an absolute symlink"
+        ))),
+        _ => Err(err),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open(jail: &PathJail, untrusted: &Path) -> Result<File> {
+    open_beneath(jail, untrusted)
+}
+
+/// Open the [resolve]d path one component at a time from the root with `O_NOFOLLOW`, so a
+/// symlink swapped in after resolution fails rather than being followed.
+#[cfg(unix)]
+fn open_beneath(jail: &PathJail, untrusted: &Path) -> Result<File> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+
+    let resolved = resolve(&jail.root, untrusted)?;
+    let within = resolved.strip_prefix(&jail.root).unwrap_or(Path::new(""));
+    let mut dir = File::open(&jail.root).annotate_err_into("path", || jail.root.display())?;
+
+    let mut components = within.components().peekable();
+    while let Some(c) = components.next() {
+        let last = components.peek().is_none();
+        let name = CString::new(c.as_os_str().as_bytes())
+            .map_err(|_| other_error_fmt!("path contains a nul byte"))?;
+        let mut flags = libc::O_RDONLY | libc::O_CLOEXEC | libc::O_NOFOLLOW;
+        if !last {
+            flags |= libc::O_DIRECTORY;
+        }
+
+        let fd = unsafe { libc::openat(dir.as_raw_fd(), name.as_ptr(), flags) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error())
+                .annotate_err_into("component", || c.as_os_str().to_string_lossy())
+                .annotate_err_into("resolved", || resolved.display());
+        }
+        dir = unsafe { File::from_raw_fd(fd) };
+    }
+    Ok(dir)
+}
+
+#[cfg(not(unix))]
+fn open_beneath(jail: &PathJail, untrusted: &Path) -> Result<File> {
+    let resolved = resolve(&jail.root, untrusted)?;
+    File::open(&resolved).annotate_err_into("resolved", || resolved.display())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::{open_beneath, PathJail};
+    use crate::testing::scratch;
+    use std::io::Read;
+
+    #[test]
+    fn open_beneath_follows_symlinks_within_root() {
+        let dir = scratch("jail-open-beneath");
+        std::fs::create_dir(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("sub/f"), "inside").unwrap();
+        std::os::unix::fs::symlink("sub", dir.join("link")).unwrap();
+
+        let jail = PathJail::new(&dir).unwrap();
+        let mut s = String::new();
+        open_beneath(&jail, "link/f".as_ref())
+            .unwrap()
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, "inside");
+    }
+
+    #[test]
+    fn open_beneath_rejects_escapes() {
+        let dir = scratch("jail-open-beneath-escape");
+        std::fs::create_dir(dir.join("root")).unwrap();
+        std::fs::write(dir.join("secret"), "outside").unwrap();
+        std::os::unix::fs::symlink("../secret", dir.join("root/link")).unwrap();
+
+        let jail = PathJail::new(dir.join("root")).unwrap();
+        assert!(open_beneath(&jail, "link".as_ref()).is_err());
+        assert!(open_beneath(&jail, "../secret".as_ref()).is_err());
+    }
+
+    /// A jail at `dir/root` holding `sub/f`, beside a `dir/secret` file outside it.
+    fn jail_with_secret(name: &str) -> (std::path::PathBuf, PathJail) {
+        let dir = scratch(name);
+        std::fs::create_dir_all(dir.join("root/sub")).unwrap();
+        std::fs::write(dir.join("root/sub/f"), "inside").unwrap();
+        std::fs::write(dir.join("secret"), "outside").unwrap();
+        let jail = PathJail::new(dir.join("root")).unwrap();
+        (dir, jail)
+    }
+
+    fn assert_escape(jail: &PathJail, path: &str, first_line: &str, annotations: &[String]) {
+        let err = jail.open(path).unwrap_err().to_string();
+        assert_eq!(err.lines().next(), Some(first_line), "{}", err);
+        for a in annotations {
+            assert!(err.contains(&format!("\n-with {}", a)), "{}: {}", a, err);
+        }
+        let suffix = format!(
+            "-with root: {}\n-with path: {}",
+            jail.root().display(),
+            path
+        );
+        assert!(err.ends_with(&suffix), "{}", err);
+    }
+
+    #[test]
+    fn open_rejects_parent_escape() {
+        let (_, jail) = jail_with_secret("jail-open-parent");
+        let mut s = String::new();
+        jail.open("sub/../sub/f")
+            .unwrap()
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, "inside");
+
+        let annotations = ["component: ..".to_string()];
+        assert_escape(&jail, "../secret", "path escapes root", &annotations);
+        assert_escape(&jail, "sub/../../secret", "path escapes root", &annotations);
+    }
+
+    #[test]
+    fn open_rejects_absolute_path() {
+        let (dir, jail) = jail_with_secret("jail-open-absolute-path");
+        let secret = dir.join("secret").display().to_string();
+        let annotations = [format!("component: {}", secret)];
+        assert_escape(&jail, &secret, "absolute path component", &annotations);
+    }
+
+    #[test]
+    fn open_rejects_absolute_symlinks() {
+        let (dir, jail) = jail_with_secret("jail-open-absolute-symlink");
+        let root = jail.root().to_path_buf();
+        std::os::unix::fs::symlink(dir.join("secret"), root.join("out")).unwrap();
+        std::os::unix::fs::symlink(root.join("sub/f"), root.join("in")).unwrap();
+
+        let annotations = [
+            format!("component: {}", dir.join("secret").display()),
+            "symlink: out".to_string(),
+        ];
+        assert_escape(&jail, "out", "path escapes root", &annotations);
+
+        // The lexical resolution accepts a target within the root, but openat2 refuses it:
+        assert_eq!(jail.resolve("in").unwrap(), root.join("sub/f"));
+        #[cfg(target_os = "linux")]
+        assert_escape(
+            &jail,
+            "in",
+            "path escapes root, or traverses an absolute symlink",
+            &[],
+        );
+    }
+
+    #[test]
+    fn open_rejects_escaping_relative_symlink() {
+        let (_, jail) = jail_with_secret("jail-open-relative-symlink");
+        let root = jail.root().to_path_buf();
+        std::os::unix::fs::symlink("../../secret", root.join("sub/up")).unwrap();
+        std::os::unix::fs::symlink("sub/up", root.join("chain")).unwrap();
+
+        let annotations = ["component: ..".to_string(), "symlink: sub/up".to_string()];
+        assert_escape(&jail, "sub/up", "path escapes root", &annotations);
+        // The error names the symlink which escaped, even when reached through another:
+        assert_escape(&jail, "chain", "path escapes root", &annotations);
+    }
+}
@@ -3,16 +3,96 @@
 #[macro_use]
 mod error;
 
+#[cfg(any(feature = "tar", feature = "zip"))]
+mod archive;
+
+mod batch;
+mod components;
+mod compress;
+mod copy;
+mod createdir;
+mod dedupe;
 mod direntry;
+mod ensure;
+mod expand;
+mod fault;
+mod filesystem;
 mod filetype;
+mod find;
+mod glob;
+mod hash;
+mod jail;
+mod listing;
+mod lock;
+mod manifest;
+mod memfs;
 mod metadata;
 mod pathext;
+mod pathfile;
+mod pathmove;
+mod pathtypes;
+mod portable;
+mod progress;
 mod readdir;
+mod retry;
+mod sync;
+mod tempname;
+mod timestamp;
+mod walk;
+
+#[cfg(feature = "mmap")]
+mod mmap;
+
+#[cfg(feature = "tar")]
+mod tarball;
+
+#[cfg(test)]
+mod testing;
+
+#[cfg(unix)]
+mod unixnames;
 
+#[cfg(target_os = "linux")]
+mod xattrs;
+
+#[cfg(feature = "zip")]
+mod ziparchive;
+
+pub mod xdg;
+
+pub use self::batch::{copy_all, remove_all, MultiPathError};
+pub use self::compress::{Compression, PathDecoder, PathEncoder};
+pub use self::copy::{CopyOptions, CopyReport, CopyStrategy};
+pub use self::createdir::DirOptions;
+pub use self::dedupe::{find_duplicates, replace_duplicates, DedupeMethod, DedupeReport};
 pub use self::direntry::PathDirEntry;
+pub use self::ensure::EnsureOutcome;
+pub use self::fault::{Fault, FaultFs, FsOp};
+pub use self::filesystem::{Filesystem, FilesystemExt, FsMetadata, OsFs};
 pub use self::filetype::FileTypeEnum;
-pub use self::metadata::PathMetadata;
+pub use self::find::Find;
+pub use self::jail::PathJail;
+pub use self::listing::{LsLine, StatDump, TimeStyle};
+pub use self::lock::{LockFile, LockHolder, LockMode, PathLock};
+pub use self::manifest::{Manifest, ManifestEntry, Mismatch, MismatchKind};
+pub use self::memfs::MemFs;
+pub use self::metadata::{PathMetadata, XattrMap};
 pub use self::pathext::PathExt;
+pub use self::pathfile::PathFile;
+pub use self::pathmove::MovePhase;
+pub use self::pathtypes::{AbsPathBuf, NormalizedPathBuf, RelPathBuf, Utf8PathBuf};
+pub use self::portable::{sanitize_file_name, Target};
+pub use self::progress::{is_cancelled, CancelToken, Observer, ProgressEvent};
 pub use self::readdir::PathReadDir;
+pub use self::retry::RetryPolicy;
+pub use self::sync::{SyncAction, SyncCompare, SyncOptions, SyncReport};
+pub use self::timestamp::Timestamp;
+pub use self::walk::{ObservedWalk, PathWalk};
+
+#[cfg(any(feature = "tar", feature = "zip"))]
+pub use self::archive::ArchiveOptions;
+
+#[cfg(feature = "mmap")]
+pub use self::mmap::{PathMmap, PathMmapMut};
 
 use self::error::other_error;
@@ -0,0 +1,433 @@
+use crate::{FileTypeEnum, PathMetadata, Timestamp};
+use std::fmt;
+use std::fs::FileType;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How [LsLine] renders the modification time.
+///
+/// All styles render in UTC, unlike `ls`, which uses the local time zone, so the same tree lists
+/// the same on every machine.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TimeStyle {
+    /// The `ls -l` default: `Nov 14 22:13` for recent times, or `Nov 14  2023` for times more
+    /// than six months from now.
+    #[default]
+    Ls,
+    /// `2023-11-14 22:13`
+    Iso,
+    /// RFC 3339, as rendered by [Timestamp]: `2023-11-14T22:13:20Z`
+    FullIso,
+    /// Seconds since the unix epoch: `1700000000`
+    Unix,
+}
+
+/// An `ls -l` style rendering of a [PathMetadata], created by [PathMetadata::ls].
+///
+/// Times are rendered in UTC as described for [TimeStyle]. The `Display` impl of [PathMetadata]
+/// renders this with the default settings.
+#[derive(Debug)]
+pub struct LsLine<'a> {
+    md: &'a PathMetadata<'a>,
+    time_style: TimeStyle,
+    human: bool,
+}
+
+impl<'a> LsLine<'a> {
+    pub(crate) fn new(md: &'a PathMetadata<'a>) -> Self {
+        LsLine {
+            md,
+            time_style: TimeStyle::default(),
+            human: true,
+        }
+    }
+
+    /// Select the modification time rendering.
+    pub fn time_style(self, time_style: TimeStyle) -> Self {
+        LsLine { time_style, ..self }
+    }
+
+    /// Render sizes with `K`/`M`/`G`… suffixes (the default), or else as exact byte counts.
+    pub fn human_sizes(self, human: bool) -> Self {
+        LsLine { human, ..self }
+    }
+}
+
+impl<'a> fmt::Display for LsLine<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let md = self.md;
+        let size = if self.human {
+            human_size(md.len())
+        } else {
+            md.len().to_string()
+        };
+
+        write!(
+            f,
+            "{}{} {} {} {} {:>5} ",
+            type_char(md.file_type()),
+            SymbolicMode(mode(md)),
+            nlink(md),
+            owner(md),
+            group(md),
+            size,
+        )?;
+        match md.modified() {
+            Ok(t) => write_time(f, t, self.time_style)?,
+            Err(_) => f.write_str("?")?,
+        }
+        write!(f, " {}", md.path().display())
+    }
+}
+
+/// A long-form `stat`-like rendering of a [PathMetadata], created by [PathMetadata::stat].
+///
+/// Times are rendered in UTC as RFC 3339 by [Timestamp], whereas `stat` uses the local time zone.
+#[derive(Debug)]
+pub struct StatDump<'a> {
+    md: &'a PathMetadata<'a>,
+}
+
+impl<'a> StatDump<'a> {
+    pub(crate) fn new(md: &'a PathMetadata<'a>) -> Self {
+        StatDump { md }
+    }
+}
+
+impl<'a> fmt::Display for StatDump<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let md = self.md;
+        let mode = mode(md);
+        let time = |r: std::io::Result<SystemTime>| match r {
+            Ok(t) => Timestamp(t).to_string(),
+            Err(_) => "-".to_string(),
+        };
+
+        writeln!(f, "  File: {}", md.path().display())?;
+        writeln!(f, "  Type: {}", type_name(md.file_type()))?;
+        writeln!(f, "  Size: {} ({})", md.len(), human_size(md.len()))?;
+        writeln!(
+            f,
+            "  Mode: {:04o} ({}{})",
+            mode & 0o7777,
+            type_char(md.file_type()),
+            SymbolicMode(mode)
+        )?;
+        writeln!(f, " Links: {}", nlink(md))?;
+        writeln!(f, " Owner: {}", owner(md))?;
+        writeln!(f, " Group: {}", group(md))?;
+        write_device_inode(f, md)?;
+        writeln!(f, "Access: {}", time(md.accessed()))?;
+        writeln!(f, "Modify: {}", time(md.modified()))?;
+        writeln!(f, "Change: {}", time(changed(md)))?;
+        writeln!(f, " Birth: {}", time(md.created()))?;
+        if let Some(xattrs) = md.xattrs() {
+            for name in xattrs.keys() {
+                writeln!(f, " Xattr: {}", name.to_string_lossy())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> fmt::Display for PathMetadata<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.ls().fmt(f)
+    }
+}
+
+fn type_char(ft: FileType) -> char {
+    if ft.is_dir() || ft.is_file() || ft.is_symlink() {
+        return FileTypeEnum::from(ft).type_char();
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+
+        if ft.is_block_device() {
+            return 'b';
+        } else if ft.is_char_device() {
+            return 'c';
+        } else if ft.is_fifo() {
+            return 'p';
+        } else if ft.is_socket() {
+            return 's';
+        }
+    }
+
+    '?'
+}
+
+fn type_name(ft: FileType) -> &'static str {
+    match type_char(ft) {
+        'd' => "Dir",
+        '-' => "File",
+        'l' => "Symlink",
+        'b' => "BlockDevice",
+        'c' => "CharDevice",
+        'p' => "Fifo",
+        's' => "Socket",
+        _ => "Unknown",
+    }
+}
+
+/// Render the permission bits of a unix mode as `rwxr-xr-x`, including setuid, setgid, and
+/// sticky bits.
+struct SymbolicMode(u32);
+
+impl fmt::Display for SymbolicMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let m = self.0;
+        let bit = |mask: u32, c: char| if m & mask != 0 { c } else { '-' };
+        let exec = |xmask: u32, smask: u32, set: char| match (m & xmask != 0, m & smask != 0) {
+            (true, true) => set,
+            (false, true) => set.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        };
+
+        let chars = [
+            bit(0o400, 'r'),
+            bit(0o200, 'w'),
+            exec(0o100, 0o4000, 's'),
+            bit(0o040, 'r'),
+            bit(0o020, 'w'),
+            exec(0o010, 0o2000, 's'),
+            bit(0o004, 'r'),
+            bit(0o002, 'w'),
+            exec(0o001, 0o1000, 't'),
+        ];
+        chars.iter().try_for_each(|c| write!(f, "{}", c))
+    }
+}
+
+fn human_size(len: u64) -> String {
+    const UNITS: [&str; 6] = ["K", "M", "G", "T", "P", "E"];
+
+    if len < 1024 {
+        return len.to_string();
+    }
+
+    // Compare after rounding, so 1048575 bytes is `1.0M` rather than `1024K`:
+    let mut value = len as f64;
+    let mut unit = "";
+    for u in UNITS {
+        if value.round() < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = u;
+    }
+
+    if (value * 10.0).round() < 100.0 {
+        format!("{:.1}{}", value, unit)
+    } else {
+        format!("{:.0}{}", value, unit)
+    }
+}
+
+fn write_time(f: &mut fmt::Formatter, t: SystemTime, style: TimeStyle) -> fmt::Result {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    const HALF_YEAR: Duration = Duration::from_secs(365 * 86400 / 2);
+
+    let ts = Timestamp(t);
+    let (y, mo, d, h, mi, _, _) = ts.civil();
+    match style {
+        TimeStyle::Ls => {
+            let now = SystemTime::now();
+            let recent = match now.duration_since(t) {
+                Ok(age) => age < HALF_YEAR,
+                Err(e) => e.duration() < HALF_YEAR,
+            };
+            let month = MONTHS[mo as usize - 1];
+            if recent {
+                write!(f, "{} {:>2} {:02}:{:02}", month, d, h, mi)
+            } else {
+                write!(f, "{} {:>2} {:>5}", month, d, y)
+            }
+        }
+        TimeStyle::Iso => write!(f, "{:04}-{:02}-{:02} {:02}:{:02}", y, mo, d, h, mi),
+        TimeStyle::FullIso => write!(f, "{}", ts),
+        TimeStyle::Unix => match t.duration_since(UNIX_EPOCH) {
+            Ok(d) => write!(f, "{}", d.as_secs()),
+            Err(e) => write!(f, "-{}", e.duration().as_secs()),
+        },
+    }
+}
+
+#[cfg(unix)]
+fn mode(md: &PathMetadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+
+    md.permissions().mode()
+}
+
+#[cfg(not(unix))]
+fn mode(md: &PathMetadata) -> u32 {
+    if md.permissions().readonly() {
+        0o444
+    } else {
+        0o644
+    }
+}
+
+#[cfg(unix)]
+fn nlink(md: &PathMetadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+
+    md.metadata().nlink()
+}
+
+#[cfg(not(unix))]
+fn nlink(_md: &PathMetadata) -> u64 {
+    1
+}
+
+#[cfg(unix)]
+fn owner(md: &PathMetadata) -> String {
+    use std::os::unix::fs::MetadataExt;
+
+    let uid = md.metadata().uid();
+    crate::unixnames::user_name(uid).unwrap_or_else(|| uid.to_string())
+}
+
+#[cfg(not(unix))]
+fn owner(_md: &PathMetadata) -> String {
+    "-".to_string()
+}
+
+#[cfg(unix)]
+fn group(md: &PathMetadata) -> String {
+    use std::os::unix::fs::MetadataExt;
+
+    let gid = md.metadata().gid();
+    crate::unixnames::group_name(gid).unwrap_or_else(|| gid.to_string())
+}
+
+#[cfg(not(unix))]
+fn group(_md: &PathMetadata) -> String {
+    "-".to_string()
+}
+
+#[cfg(unix)]
+fn write_device_inode(f: &mut fmt::Formatter, md: &PathMetadata) -> fmt::Result {
+    use std::os::unix::fs::MetadataExt;
+
+    let m = md.metadata();
+    writeln!(f, "Device: {:x}h", m.dev())?;
+    writeln!(f, " Inode: {}", m.ino())
+}
+
+#[cfg(unix)]
+fn changed(md: &PathMetadata) -> std::io::Result<SystemTime> {
+    use std::os::unix::fs::MetadataExt;
+
+    let (secs, nsecs) = (md.metadata().ctime(), md.metadata().ctime_nsec());
+    let nanos = Duration::from_nanos(nsecs as u64);
+    Ok(if secs >= 0 {
+        UNIX_EPOCH + Duration::from_secs(secs as u64) + nanos
+    } else {
+        UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs()) + nanos
+    })
+}
+
+#[cfg(not(unix))]
+fn changed(_md: &PathMetadata) -> std::io::Result<SystemTime> {
+    Err(other_error_fmt!("change time unsupported on this platform"))
+}
+
+#[cfg(not(unix))]
+fn write_device_inode(_f: &mut fmt::Formatter, _md: &PathMetadata) -> fmt::Result {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{human_size, SymbolicMode};
+
+    #[test]
+    fn symbolic_modes() {
+        let cases = [
+            (0o000, "---------"),
+            (0o644, "rw-r--r--"),
+            (0o755, "rwxr-xr-x"),
+            (0o4755, "rwsr-xr-x"),
+            (0o4644, "rwSr--r--"),
+            (0o2755, "rwxr-sr-x"),
+            (0o2745, "rwxr-Sr-x"),
+            (0o1777, "rwxrwxrwt"),
+            (0o1776, "rwxrwxrwT"),
+            (0o7000, "--S--S--T"),
+            (0o7777, "rwsrwsrwt"),
+        ];
+        for (mode, expected) in cases {
+            assert_eq!(SymbolicMode(mode).to_string(), expected, "{:o}", mode);
+        }
+    }
+
+    #[test]
+    fn human_size_boundaries() {
+        let cases = [
+            (0, "0"),
+            (1023, "1023"),
+            (1024, "1.0K"),
+            (1536, "1.5K"),
+            (10188, "9.9K"),
+            (10239, "10K"),
+            (10240, "10K"),
+            (1024 * 1024 - 1, "1.0M"),
+            (1024 * 1024, "1.0M"),
+            (5 << 30, "5.0G"),
+            (u64::MAX, "16E"),
+        ];
+        for (len, expected) in cases {
+            assert_eq!(human_size(len), expected, "{}", len);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ls_and_stat_output() {
+        use crate::testing::scratch;
+        use crate::{PathExt, TimeStyle, Timestamp};
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let path = scratch("listing-output").join("f");
+        std::fs::write(&path, "hello").unwrap();
+        std::fs::set_permissions(&path, PermissionsExt::from_mode(0o4640)).unwrap();
+        let mtime = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        path.pe_set_mtime(mtime).unwrap();
+
+        let md = path.pe_symlink_metadata().unwrap();
+        let (owner, group) = (super::owner(&md), super::group(&md));
+
+        let line = md.ls().time_style(TimeStyle::Iso).to_string();
+        let expected = format!(
+            "-rwSr----- 1 {} {}     5 2023-11-14 22:13 {}",
+            owner,
+            group,
+            path.display()
+        );
+        assert_eq!(line, expected);
+        let line = md.ls().time_style(TimeStyle::Unix).to_string();
+        assert!(line.contains(" 1700000000 "), "{}", line);
+
+        let stat = md.stat().to_string();
+        let lines: Vec<_> = stat.lines().collect();
+        let expected = [
+            format!("  File: {}", path.display()),
+            "  Type: File".to_string(),
+            "  Size: 5 (5)".to_string(),
+            "  Mode: 4640 (-rwSr-----)".to_string(),
+            " Links: 1".to_string(),
+            format!(" Owner: {}", owner),
+            format!(" Group: {}", group),
+        ];
+        assert_eq!(lines[..7], expected, "{}", stat);
+        assert_eq!(lines[8], format!(" Inode: {}", md.metadata().ino()));
+        assert_eq!(lines[10], format!("Modify: {}", Timestamp(mtime)));
+    }
+}
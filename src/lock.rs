@@ -0,0 +1,445 @@
+use crate::PathFile;
+use error_annotation::AnnotateResult;
+use std::fmt;
+use std::fs::{OpenOptions, TryLockError};
+use std::io::{ErrorKind, Read, Result, Seek, SeekFrom, Write};
+use std::ops::Deref;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Whether an advisory lock is shared with other readers or held exclusively.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LockMode {
+    Shared,
+    Exclusive,
+}
+
+impl fmt::Display for LockMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            LockMode::Shared => "shared",
+            LockMode::Exclusive => "exclusive",
+        })
+    }
+}
+
+/// An advisory lock on a [PathFile], which is unlocked when dropped.
+///
+/// Locks are advisory: they only exclude other processes which also lock the same file.
+#[derive(Debug)]
+pub struct PathLock {
+    pf: PathFile,
+    mode: LockMode,
+}
+
+impl PathLock {
+    /// The mode this lock is held in.
+    pub fn mode(&self) -> LockMode {
+        self.mode
+    }
+}
+
+impl Deref for PathLock {
+    type Target = PathFile;
+
+    fn deref(&self) -> &PathFile {
+        &self.pf
+    }
+}
+
+impl Drop for PathLock {
+    fn drop(&mut self) {
+        // Closing the file releases the lock anyway, so an unlock failure is harmless:
+        let _ = self.pf.file().unlock();
+    }
+}
+
+/// Open the lock file at `path`, creating it if necessary.
+fn open(path: &Path) -> Result<PathFile> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .annotate_err_into("path", || path.display())
+        .map(|f| PathFile::new(path, f))
+}
+
+pub(crate) fn lock(path: &Path, mode: LockMode) -> Result<PathLock> {
+    open(path)?.lock(mode)
+}
+
+pub(crate) fn try_lock(path: &Path, mode: LockMode) -> Result<PathLock> {
+    try_lock_file(open(path)?, mode).map_err(|(_, e)| e)
+}
+
+/// Try to lock `pf`, returning it with the error on failure so callers may retry.
+///
+/// When another process holds a conflicting lock, the error is annotated with its `holder`.
+fn try_lock_file(
+    mut pf: PathFile,
+    mode: LockMode,
+) -> std::result::Result<PathLock, (PathFile, std::io::Error)> {
+    let res = match mode {
+        LockMode::Shared => pf.file().try_lock_shared(),
+        LockMode::Exclusive => pf.file().try_lock(),
+    };
+
+    let err = match res {
+        Ok(()) => return Ok(PathLock { pf, mode }),
+        Err(TryLockError::WouldBlock) => {
+            let err = std::io::Error::new(ErrorKind::WouldBlock, "lock is held by another process");
+            let holders = describe_holders(&mut pf);
+            Err::<(), _>(err)
+                .annotate_err_into("holder", || holders)
+                .unwrap_err()
+        }
+        Err(TryLockError::Error(e)) => e,
+    };
+
+    let err = Err::<(), _>(err)
+        .annotate_err_into("path", || pf.path().display())
+        .annotate_err_into("mode", || mode)
+        .unwrap_err();
+
+    Err((pf, err))
+}
+
+pub(crate) fn lock_timeout(path: &Path, mode: LockMode, timeout: Duration) -> Result<PathLock> {
+    const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+    let deadline = Instant::now() + timeout;
+    let mut pf = open(path)?;
+    loop {
+        match try_lock_file(pf, mode) {
+            Ok(lock) => return Ok(lock),
+            Err((mut retry, e)) if e.kind() == ErrorKind::WouldBlock => {
+                let now = Instant::now();
+                if now >= deadline {
+                    return Err(std::io::Error::new(
+                        ErrorKind::TimedOut,
+                        "timed out waiting for lock",
+                    ))
+                    .annotate_err_into("holder", || describe_holders(&mut retry))
+                    .annotate_err_into("path", || path.display())
+                    .annotate_err_into("mode", || mode)
+                    .annotate_err_into("timeout", || format!("{:?}", timeout));
+                }
+                std::thread::sleep(POLL_INTERVAL.min(deadline - now));
+                pf = retry;
+            }
+            Err((_, e)) => return Err(e),
+        }
+    }
+}
+
+/// The process recorded as holding a [LockFile].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LockHolder {
+    pub pid: u32,
+    pub hostname: String,
+}
+
+impl LockHolder {
+    fn current() -> Self {
+        LockHolder {
+            pid: std::process::id(),
+            hostname: hostname(),
+        }
+    }
+
+    /// Parse the `pid` and `hostname` lines written by [LockFile::acquire].
+    fn parse(contents: &str) -> Option<Self> {
+        let mut lines = contents.lines();
+        let pid = lines.next()?.trim().parse().ok()?;
+        let hostname = lines.next()?.trim().to_string();
+        Some(LockHolder { pid, hostname })
+    }
+
+    /// Whether this holder is a process on this host which no longer exists.
+    fn is_dead_local_process(&self) -> bool {
+        self.hostname == hostname() && !process_exists(self.pid)
+    }
+}
+
+impl fmt::Display for LockHolder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "pid {} on {}", self.pid, self.hostname)
+    }
+}
+
+/// A pidfile-style exclusive lock which records the holder's pid and hostname in the file.
+///
+/// The lock itself is an advisory [LockMode::Exclusive] lock, so it is released by the OS when the
+/// holding process exits. A file still containing holder information from such a process is
+/// stale, and is taken over by [LockFile::acquire], which reports it via [LockFile::stale_holder].
+///
+/// On drop, the holder information is cleared and the lock released. The file is not removed,
+/// since removing it would race with processes waiting to lock it.
+///
+/// # Example
+///
+/// ```
+/// use pathutil::LockFile;
+///
+/// let path = std::env::temp_dir().join("pathutil-doc-lockfile.pid");
+/// let lock = LockFile::acquire(&path).unwrap();
+///
+/// let errstr = LockFile::acquire(&path).err().unwrap().to_string();
+/// let holder = format!("-with holder: pid {} on ", std::process::id());
+/// assert!(errstr.starts_with("lock is held by another process"));
+/// assert!(errstr.contains(&holder));
+///
+/// drop(lock);
+/// assert!(LockFile::acquire(&path).is_ok());
+/// ```
+#[derive(Debug)]
+pub struct LockFile {
+    lock: PathLock,
+    stale: Option<LockHolder>,
+}
+
+impl LockFile {
+    /// Acquire the lock at `path` without blocking, or the error names the current holder.
+    pub fn acquire<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let lock = try_lock(path.as_ref(), LockMode::Exclusive)?;
+
+        let mut lockfile = LockFile { lock, stale: None };
+        lockfile.stale = read_holder(&mut lockfile.lock.pf)?;
+        lockfile.write_holder(Some(&LockHolder::current()))?;
+        Ok(lockfile)
+    }
+
+    /// Access the lock's [Path].
+    pub fn path(&self) -> &Path {
+        self.lock.path()
+    }
+
+    /// The previous holder which exited without releasing the lock file, if any.
+    pub fn stale_holder(&self) -> Option<&LockHolder> {
+        self.stale.as_ref()
+    }
+
+    /// Whether the stale holder is known to be dead, as opposed to a process on another host.
+    pub fn stale_holder_is_dead(&self) -> bool {
+        self.stale
+            .as_ref()
+            .map(LockHolder::is_dead_local_process)
+            .unwrap_or(false)
+    }
+
+    fn write_holder(&mut self, holder: Option<&LockHolder>) -> Result<()> {
+        let pf = &mut self.lock.pf;
+        pf.set_len(0)?;
+        pf.seek(SeekFrom::Start(0))?;
+        if let Some(holder) = holder {
+            write!(pf, "{}\n{}\n", holder.pid, holder.hostname)?;
+        }
+        pf.flush()
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        let _ = self.write_holder(None);
+    }
+}
+
+fn read_holder(pf: &mut PathFile) -> Result<Option<LockHolder>> {
+    let mut contents = String::new();
+    pf.seek(SeekFrom::Start(0))?;
+    pf.read_to_string(&mut contents)?;
+    Ok(LockHolder::parse(&contents))
+}
+
+/// Describe the holders of a conflicting lock on `pf`: the holder recorded by a [LockFile], or
+/// else on Linux the processes `/proc/locks` lists. Plain advisory locks record nothing, so
+/// elsewhere they are `unknown`.
+fn describe_holders(pf: &mut PathFile) -> String {
+    let holders = match read_holder(pf) {
+        Ok(Some(holder)) => vec![holder],
+        _ => lock_holders(pf.file()),
+    };
+    match holders.is_empty() {
+        true => "unknown".to_string(),
+        false => holders
+            .iter()
+            .map(LockHolder::to_string)
+            .collect::<Vec<_>>()
+            .join(", "),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn lock_holders(file: &std::fs::File) -> Vec<LockHolder> {
+    use std::os::unix::fs::MetadataExt;
+
+    let (Ok(md), Ok(locks)) = (file.metadata(), std::fs::read_to_string("/proc/locks")) else {
+        return vec![];
+    };
+    let id = format!(
+        "{:02x}:{:02x}:{}",
+        libc::major(md.dev()),
+        libc::minor(md.dev()),
+        md.ino()
+    );
+
+    // Lines are `1: FLOCK  ADVISORY  WRITE <pid> <major>:<minor>:<inode> 0 EOF`, and waiters
+    // are listed after them as `1: -> FLOCK …`. Pids outside our namespace are not positive.
+    let mut pids: Vec<u32> = locks
+        .lines()
+        .filter_map(|line| match *line.split_whitespace().collect::<Vec<_>>() {
+            [_, kind, _, _, pid, file, ..] if kind != "->" && file == id => pid.parse().ok(),
+            _ => None,
+        })
+        .filter(|&pid| pid > 0)
+        .collect();
+    pids.dedup();
+    pids.into_iter()
+        .map(|pid| LockHolder {
+            pid,
+            hostname: hostname(),
+        })
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn lock_holders(_file: &std::fs::File) -> Vec<LockHolder> {
+    vec![]
+}
+
+#[cfg(unix)]
+fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    let rc = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+    if rc != 0 {
+        return "unknown".to_string();
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+#[cfg(not(unix))]
+fn hostname() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_else(|_| "unknown".to_string())
+}
+
+#[cfg(unix)]
+fn process_exists(pid: u32) -> bool {
+    let rc = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    rc == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn process_exists(_pid: u32) -> bool {
+    true
+}
+
+impl PathFile {
+    /// Block until this file is locked in `mode`, returning a guard which unlocks it on drop.
+    pub fn lock(self, mode: LockMode) -> Result<PathLock> {
+        let path = self.path().to_path_buf();
+        match mode {
+            LockMode::Shared => self.file().lock_shared(),
+            LockMode::Exclusive => self.file().lock(),
+        }
+        .annotate_err_into("path", || path.display())
+        .annotate_err_into("mode", || mode)?;
+
+        Ok(PathLock { pf: self, mode })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hostname, LockHolder};
+    use crate::testing::scratch;
+    use crate::{LockFile, LockMode, PathExt};
+    use std::io::ErrorKind;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn shared_lock_blocks_exclusive_try_lock() {
+        let path = scratch("lock-shared").join("lock");
+        let shared = path.pe_lock_shared().unwrap();
+        assert_eq!(shared.mode(), LockMode::Shared);
+
+        let err = path.pe_try_lock(LockMode::Exclusive).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+        let also_shared = path.pe_try_lock(LockMode::Shared).unwrap();
+
+        drop((shared, also_shared));
+        assert!(path.pe_try_lock(LockMode::Exclusive).is_ok());
+    }
+
+    #[test]
+    fn dropping_the_guard_unlocks() {
+        let path = scratch("lock-drop").join("lock");
+        let lock = path.pe_lock_exclusive().unwrap();
+        assert!(path.pe_try_lock(LockMode::Shared).is_err());
+        drop(lock);
+        assert!(path.pe_try_lock(LockMode::Shared).is_ok());
+    }
+
+    #[test]
+    fn timeout_expires_while_held() {
+        let path = scratch("lock-timeout").join("lock");
+        let _held = path.pe_lock_exclusive().unwrap();
+
+        let start = Instant::now();
+        let timeout = Duration::from_millis(50);
+        let err = path.pe_lock_timeout(LockMode::Shared, timeout).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TimedOut);
+        assert!(start.elapsed() >= timeout);
+        assert!(err.to_string().contains("-with holder: "), "{}", err);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn plain_lock_holder_is_found_in_proc_locks() {
+        let path = scratch("lock-proc-holder").join("lock");
+        let _held = path.pe_lock_exclusive().unwrap();
+
+        let err = path.pe_try_lock(LockMode::Exclusive).unwrap_err();
+        let holder = LockHolder {
+            pid: std::process::id(),
+            hostname: hostname(),
+        };
+        let expected = format!("-with holder: {}\n", holder);
+        assert!(err.to_string().contains(&expected), "{}", err);
+    }
+
+    #[test]
+    fn lock_file_names_its_holder() {
+        let path = scratch("lock-file-holder").join("lock");
+        let _held = LockFile::acquire(&path).unwrap();
+
+        let err = LockFile::acquire(&path).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+        let expected = format!("-with holder: {}\n", LockHolder::current());
+        assert!(err.to_string().contains(&expected), "{}", err);
+    }
+
+    #[test]
+    fn lock_file_takes_over_from_a_dead_holder() {
+        let path = scratch("lock-file-stale").join("lock");
+        let dead = LockHolder {
+            pid: 999_999_999,
+            hostname: hostname(),
+        };
+        std::fs::write(&path, format!("{}\n{}\n", dead.pid, dead.hostname)).unwrap();
+
+        let lock = LockFile::acquire(&path).unwrap();
+        assert_eq!(lock.stale_holder(), Some(&dead));
+        assert!(lock.stale_holder_is_dead());
+        let current = LockHolder::parse(&std::fs::read_to_string(&path).unwrap());
+        assert_eq!(current, Some(LockHolder::current()));
+
+        drop(lock);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+    }
+}
@@ -0,0 +1,634 @@
+use crate::{FileTypeEnum, FsMetadata, PathExt};
+use error_annotation::AnnotateResult;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{ErrorKind, Result};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The first line of the line format written by [Manifest::to_lines].
+const LINES_HEADER: &str = "#pathutil manifest v1";
+
+/// A snapshot of a directory tree's entries and their metadata, which can be stored and later
+/// used to [verify](Manifest::verify) a tree.
+///
+/// Entries are sorted by their relative `/`-separated path and exclude the root itself. A
+/// manifest serializes to a compact mtree-like line format with [Manifest::to_lines], and to
+/// JSON with [Manifest::to_json] given the `json` feature.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct Manifest {
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// The recorded state of one entry of a [Manifest].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct ManifestEntry {
+    /// The path relative to the manifest root with `/` separators.
+    pub path: String,
+    pub file_type: FileTypeEnum,
+    /// The size of files and symlinks, and 0 for directories.
+    pub size: u64,
+    pub mode: u32,
+    #[cfg_attr(feature = "json", serde(with = "json_time"))]
+    pub mtime: SystemTime,
+    /// The lowercase hex sha256 digest of a file's contents, if hashes were requested.
+    #[cfg_attr(
+        feature = "json",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub sha256: Option<String>,
+    /// The target of a symlink.
+    #[cfg_attr(
+        feature = "json",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub target: Option<String>,
+}
+
+impl Manifest {
+    /// Walk the tree at `root` without following symlinks, recording sha256 digests of files if
+    /// `hash` is set.
+    ///
+    /// Errors are annotated with the offending path. Entries whose names are not valid utf8 are
+    /// rejected, since the serialized formats cannot represent them. Entries of other types than
+    /// directories, files, and symlinks, such as fifos and sockets, are skipped.
+    pub fn build<P>(root: P, hash: bool) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let root = root.as_ref();
+        let mut entries = vec![];
+        for res in root.pe_walk().skip(1) {
+            let md = res?;
+            let path = md.path();
+            let ft = md.metadata().file_type();
+            if !(ft.is_dir() || ft.is_file() || ft.is_symlink()) {
+                continue;
+            }
+            let name = crate::walk::relative_name(root, path)?;
+            let fsmd =
+                FsMetadata::from_std(md.metadata()).annotate_err_into("path", || path.display())?;
+
+            let target = match fsmd.file_type {
+                FileTypeEnum::Symlink => Some(path.pe_read_link()?.pe_to_str()?.to_string()),
+                _ => None,
+            };
+            let sha256 = match fsmd.file_type {
+                FileTypeEnum::File if hash => Some(crate::hash::sha256_file(path)?),
+                _ => None,
+            };
+
+            entries.push(ManifestEntry {
+                path: name,
+                file_type: fsmd.file_type,
+                size: match fsmd.file_type {
+                    FileTypeEnum::Dir => 0,
+                    _ => fsmd.len,
+                },
+                mode: fsmd.mode,
+                mtime: fsmd.modified,
+                sha256,
+                target,
+            });
+        }
+        Ok(Manifest { entries })
+    }
+
+    /// Whether any entry records a sha256 digest.
+    pub fn has_hashes(&self) -> bool {
+        self.entries.iter().any(|e| e.sha256.is_some())
+    }
+
+    /// Compare the tree at `root` against this manifest, returning every difference.
+    ///
+    /// File contents are only compared if this manifest has hashes. Callers which do not care
+    /// about some differences, such as directory mtimes, can filter them by [MismatchKind].
+    pub fn verify<P>(&self, root: P) -> Result<Vec<Mismatch>>
+    where
+        P: AsRef<Path>,
+    {
+        let actual = Manifest::build(root, self.has_hashes())?;
+        Ok(self.diff(&actual))
+    }
+
+    /// The differences of `actual` from this manifest, in path order.
+    pub fn diff(&self, actual: &Manifest) -> Vec<Mismatch> {
+        let mut merged: BTreeMap<&str, (Option<&ManifestEntry>, Option<&ManifestEntry>)> =
+            BTreeMap::new();
+        for e in &self.entries {
+            merged.entry(&e.path).or_default().0 = Some(e);
+        }
+        for e in &actual.entries {
+            merged.entry(&e.path).or_default().1 = Some(e);
+        }
+
+        let mut mismatches = vec![];
+        for (path, pair) in merged {
+            let mut push = |kind| {
+                mismatches.push(Mismatch {
+                    path: path.to_string(),
+                    kind,
+                })
+            };
+
+            let (exp, act) = match pair {
+                (Some(exp), Some(act)) => (exp, act),
+                (Some(_), None) => {
+                    push(MismatchKind::Missing);
+                    continue;
+                }
+                (None, _) => {
+                    push(MismatchKind::Extra);
+                    continue;
+                }
+            };
+
+            if exp.file_type != act.file_type {
+                push(MismatchKind::FileType(exp.file_type, act.file_type));
+                continue;
+            }
+            if exp.size != act.size {
+                push(MismatchKind::Size(exp.size, act.size));
+            }
+            if exp.mode != act.mode {
+                push(MismatchKind::Mode(exp.mode, act.mode));
+            }
+            if exp.mtime != act.mtime {
+                push(MismatchKind::Mtime(exp.mtime, act.mtime));
+            }
+            if exp.sha256.is_some() && exp.sha256 != act.sha256 {
+                push(MismatchKind::Sha256(
+                    exp.sha256.clone().unwrap_or_default(),
+                    act.sha256.clone().unwrap_or_default(),
+                ));
+            }
+            if exp.target != act.target {
+                push(MismatchKind::Target(
+                    exp.target.clone().unwrap_or_default(),
+                    act.target.clone().unwrap_or_default(),
+                ));
+            }
+        }
+        mismatches
+    }
+
+    /// Serialize to the line format, one entry per line after a header line.
+    ///
+    /// Each line is the entry path followed by `key=value` fields, as in `a/b.txt type=file
+    /// size=5 mode=644 time=1700000000.000000000 sha256=…`. Whitespace, `\`, `=`, `#`, and
+    /// control characters in paths and targets are escaped as `\` and three octal digits.
+    pub fn to_lines(&self) -> String {
+        let mut out = format!("{}\n", LINES_HEADER);
+        for e in &self.entries {
+            out.push_str(&e.to_line());
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Parse the line format written by [Manifest::to_lines], annotating errors with the line
+    /// number.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pathutil::{FileTypeEnum, Manifest};
+    ///
+    /// let text = "#pathutil manifest v1\nmy\\040notes.txt type=file size=5 mode=644 time=0.5\n";
+    /// let manifest = Manifest::from_lines(text).unwrap();
+    /// assert_eq!(manifest.entries[0].path, "my notes.txt");
+    /// assert_eq!(manifest.entries[0].file_type, FileTypeEnum::File);
+    /// assert_eq!(manifest.to_lines(), text.replace("0.5", "0.500000000"));
+    /// ```
+    pub fn from_lines(text: &str) -> Result<Self> {
+        let mut lines = text.lines().enumerate();
+        match lines.next() {
+            Some((_, LINES_HEADER)) => {}
+            _ => {
+                return Err(invalid_data("missing manifest header"))
+                    .annotate_err_into("line", || 1);
+            }
+        }
+
+        let mut entries = vec![];
+        for (i, line) in lines {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            entries.push(ManifestEntry::from_line(line).annotate_err_into("line", || i + 1)?);
+        }
+        Ok(Manifest { entries })
+    }
+
+    /// Serialize to pretty-printed JSON.
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("manifests always serialize")
+    }
+
+    /// Parse the JSON written by [Manifest::to_json].
+    #[cfg(feature = "json")]
+    pub fn from_json(text: &str) -> Result<Self> {
+        serde_json::from_str(text).map_err(std::io::Error::from)
+    }
+}
+
+impl ManifestEntry {
+    fn to_line(&self) -> String {
+        let mut line = format!(
+            "{} type={} size={} mode={:o} time={}",
+            escape(&self.path),
+            type_name(self.file_type),
+            self.size,
+            self.mode,
+            format_time(self.mtime),
+        );
+        if let Some(sha256) = &self.sha256 {
+            line.push_str(&format!(" sha256={}", sha256));
+        }
+        if let Some(target) = &self.target {
+            line.push_str(&format!(" link={}", escape(target)));
+        }
+        line
+    }
+
+    fn from_line(line: &str) -> Result<Self> {
+        let mut fields = line.split_ascii_whitespace();
+        let path = unescape(fields.next().unwrap_or_default())?;
+
+        let (mut file_type, mut size, mut mode, mut mtime) = (None, None, None, None);
+        let (mut sha256, mut target) = (None, None);
+        for field in fields {
+            let (key, value) = field
+                .split_once('=')
+                .ok_or_else(|| invalid_data("field without `=`"))
+                .annotate_err_into("field", || field)?;
+            let parsed = match key {
+                "type" => parse_type(value).map(|t| file_type = Some(t)),
+                "size" => parse_num(value, 10).map(|n| size = Some(n)),
+                "mode" => parse_num(value, 8).map(|n| mode = Some(n as u32)),
+                "time" => parse_time(value).map(|t| mtime = Some(t)),
+                "sha256" => {
+                    sha256 = Some(value.to_string());
+                    Ok(())
+                }
+                "link" => unescape(value).map(|t| target = Some(t)),
+                _ => Err(invalid_data("unknown key")),
+            };
+            parsed.annotate_err_into("field", || field)?;
+        }
+
+        let missing = |key: &str| invalid_data(&format!("missing `{}` field", key));
+        Ok(ManifestEntry {
+            path,
+            file_type: file_type.ok_or_else(|| missing("type"))?,
+            size: size.ok_or_else(|| missing("size"))?,
+            mode: mode.ok_or_else(|| missing("mode"))?,
+            mtime: mtime.ok_or_else(|| missing("time"))?,
+            sha256,
+            target,
+        })
+    }
+}
+
+/// One difference found by [Manifest::verify] or [Manifest::diff].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    /// The entry path relative to the manifest root.
+    pub path: String,
+    pub kind: MismatchKind,
+}
+
+/// The kind of a [Mismatch], holding the expected and actual values where both exist.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MismatchKind {
+    /// In the manifest but not the tree.
+    Missing,
+    /// In the tree but not the manifest.
+    Extra,
+    FileType(FileTypeEnum, FileTypeEnum),
+    Size(u64, u64),
+    Mode(u32, u32),
+    Mtime(SystemTime, SystemTime),
+    Sha256(String, String),
+    Target(String, String),
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use MismatchKind::*;
+
+        let path = &self.path;
+        match &self.kind {
+            Missing => write!(f, "{}: missing", path),
+            Extra => write!(f, "{}: extra", path),
+            FileType(e, a) => write!(
+                f,
+                "{}: expected type {}, found {}",
+                path,
+                type_name(*e),
+                type_name(*a)
+            ),
+            Size(e, a) => write!(f, "{}: expected size {}, found {}", path, e, a),
+            Mode(e, a) => write!(f, "{}: expected mode {:o}, found {:o}", path, e, a),
+            Mtime(e, a) => write!(
+                f,
+                "{}: expected mtime {}, found {}",
+                path,
+                crate::Timestamp(*e),
+                crate::Timestamp(*a)
+            ),
+            Sha256(e, a) => write!(f, "{}: expected sha256 {}, found {}", path, e, a),
+            Target(e, a) => write!(f, "{}: expected link {}, found {}", path, e, a),
+        }
+    }
+}
+
+fn invalid_data(msg: &str) -> std::io::Error {
+    std::io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+fn type_name(ft: FileTypeEnum) -> &'static str {
+    match ft {
+        FileTypeEnum::Dir => "dir",
+        FileTypeEnum::File => "file",
+        FileTypeEnum::Symlink => "link",
+    }
+}
+
+fn parse_type(s: &str) -> Result<FileTypeEnum> {
+    match s {
+        "dir" => Ok(FileTypeEnum::Dir),
+        "file" => Ok(FileTypeEnum::File),
+        "link" => Ok(FileTypeEnum::Symlink),
+        _ => Err(invalid_data("unknown type")),
+    }
+}
+
+fn parse_num(s: &str, radix: u32) -> Result<u64> {
+    u64::from_str_radix(s, radix).map_err(|e| invalid_data(&e.to_string()))
+}
+
+/// Format as decimal seconds relative to the unix epoch with nanosecond precision.
+fn format_time(t: SystemTime) -> String {
+    let (sign, d) = match t.duration_since(UNIX_EPOCH) {
+        Ok(d) => ("", d),
+        Err(e) => ("-", e.duration()),
+    };
+    format!("{}{}.{:09}", sign, d.as_secs(), d.subsec_nanos())
+}
+
+fn parse_time(s: &str) -> Result<SystemTime> {
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    let (secs, nanos) = s.split_once('.').unwrap_or((s, "0"));
+    if nanos.len() > 9 {
+        return Err(invalid_data("more than 9 fractional digits"));
+    }
+    let nanos = parse_num(nanos, 10)? * 10u64.pow(9 - nanos.len() as u32);
+    let d = Duration::new(parse_num(secs, 10)?, nanos as u32);
+    let t = match negative {
+        true => UNIX_EPOCH.checked_sub(d),
+        false => UNIX_EPOCH.checked_add(d),
+    };
+    t.ok_or_else(|| invalid_data("time out of range"))
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c.is_whitespace() || c.is_control() || matches!(c, '\\' | '=' | '#') {
+            let mut buf = [0u8; 4];
+            for b in c.encode_utf8(&mut buf).bytes() {
+                out.push_str(&format!("\\{:03o}", b));
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn unescape(s: &str) -> Result<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'\\' {
+            let code = tail
+                .get(..3)
+                .and_then(|oct| std::str::from_utf8(oct).ok())
+                .and_then(|oct| u8::from_str_radix(oct, 8).ok())
+                .ok_or_else(|| invalid_data("invalid escape"))?;
+            bytes.push(code);
+            rest = &tail[3..];
+        } else {
+            bytes.push(b);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).map_err(|e| invalid_data(&e.to_string()))
+}
+
+#[cfg(feature = "json")]
+mod json_time {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::SystemTime;
+
+    pub fn serialize<S>(t: &SystemTime, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        s.serialize_str(&super::format_time(*t))
+    }
+
+    pub fn deserialize<'de, D>(d: D) -> Result<SystemTime, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(d)?;
+        super::parse_time(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Manifest, ManifestEntry, Mismatch, MismatchKind};
+    use crate::testing::scratch;
+    use crate::{FileTypeEnum, PathExt};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn entry(path: &str, file_type: FileTypeEnum, target: Option<&str>) -> ManifestEntry {
+        ManifestEntry {
+            path: path.to_string(),
+            file_type,
+            size: 3,
+            mode: 0o644,
+            mtime: UNIX_EPOCH + Duration::new(1_700_000_000, 123),
+            sha256: None,
+            target: target.map(str::to_string),
+        }
+    }
+
+    fn escaped_names() -> Manifest {
+        let mut hashed = entry("plain/é", FileTypeEnum::File, None);
+        hashed.sha256 = Some("ab".repeat(32));
+        Manifest {
+            entries: vec![
+                entry("new\nline", FileTypeEnum::File, None),
+                entry("back\\slash", FileTypeEnum::File, None),
+                entry("sp ace=#\ttab", FileTypeEnum::Dir, None),
+                entry("wide\u{3000}space", FileTypeEnum::File, None),
+                entry("link", FileTypeEnum::Symlink, Some("../a b\\c\n")),
+                hashed,
+            ],
+        }
+    }
+
+    #[test]
+    fn lines_round_trip_escaped_names() {
+        let manifest = escaped_names();
+        let text = manifest.to_lines();
+        assert_eq!(text.lines().count(), manifest.entries.len() + 1);
+        assert!(text.contains("new\\012line type=file"), "{}", text);
+        assert!(text.contains("back\\134slash "), "{}", text);
+        assert!(text.contains("wide\\343\\200\\200space "), "{}", text);
+        assert_eq!(Manifest::from_lines(&text).unwrap(), manifest);
+    }
+
+    #[test]
+    fn non_utf8_escape_is_rejected_with_its_line() {
+        let text = [
+            "#pathutil manifest v1",
+            "ok type=dir size=0 mode=755 time=0",
+            "\\377 type=dir size=0 mode=755 time=0",
+        ]
+        .join("\n");
+        let errstr = Manifest::from_lines(&text).unwrap_err().to_string();
+        assert!(errstr.contains("-with line: 3"), "{}", errstr);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_name_is_rejected_with_its_path() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = scratch("manifest-non-utf8");
+        let name = std::ffi::OsStr::from_bytes(b"bad\xff");
+        std::fs::write(dir.join(name), "").unwrap();
+        let errstr = Manifest::build(&dir, false).unwrap_err().to_string();
+        assert!(errstr.contains("bad"), "{}", errstr);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_round_trip() {
+        let manifest = escaped_names();
+        let json = manifest.to_json();
+        assert_eq!(Manifest::from_json(&json).unwrap(), manifest);
+    }
+
+    #[test]
+    fn diff_reports_every_kind_of_difference() {
+        let expected = Manifest {
+            entries: vec![
+                entry("a", FileTypeEnum::File, None),
+                entry("b", FileTypeEnum::File, None),
+                entry("l", FileTypeEnum::Symlink, Some("a")),
+            ],
+        };
+        let mut actual = expected.clone();
+        actual.entries[0].size = 4;
+        actual.entries[0].mode = 0o600;
+        actual.entries[1].file_type = FileTypeEnum::Dir;
+        actual.entries[2].target = Some("b".to_string());
+        actual.entries.push(entry("z", FileTypeEnum::File, None));
+
+        let kinds: Vec<_> = expected
+            .diff(&actual)
+            .into_iter()
+            .map(|m| (m.path, m.kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                ("a".to_string(), MismatchKind::Size(3, 4)),
+                ("a".to_string(), MismatchKind::Mode(0o644, 0o600)),
+                (
+                    "b".to_string(),
+                    MismatchKind::FileType(FileTypeEnum::File, FileTypeEnum::Dir)
+                ),
+                (
+                    "l".to_string(),
+                    MismatchKind::Target("a".to_string(), "b".to_string())
+                ),
+                ("z".to_string(), MismatchKind::Extra),
+            ]
+        );
+        assert!(actual.diff(&actual).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn verify_finds_changes_to_a_tree() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = scratch("manifest-verify");
+        let root = dir.join("root");
+        std::fs::create_dir_all(root.join("d")).unwrap();
+        for (name, contents) in [("a", "aaa"), ("d/b", "bbb")] {
+            std::fs::write(root.join(name), contents).unwrap();
+            std::fs::set_permissions(root.join(name), PermissionsExt::from_mode(0o644)).unwrap();
+        }
+        std::os::unix::fs::symlink("a", root.join("l")).unwrap();
+
+        let manifest = Manifest::build(&root, true).unwrap();
+        assert!(manifest.has_hashes());
+        let paths: Vec<_> = manifest.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["a", "d", "d/b", "l"]);
+        assert!(manifest.verify(&root).unwrap().is_empty());
+
+        // Same length and mtime, so only the hash can tell:
+        let mtime = root.join("a").pe_metadata().unwrap().modified().unwrap();
+        std::fs::write(root.join("a"), "AAA").unwrap();
+        root.join("a").pe_set_mtime(mtime).unwrap();
+        std::fs::set_permissions(root.join("d/b"), PermissionsExt::from_mode(0o600)).unwrap();
+        std::fs::remove_file(root.join("l")).unwrap();
+        std::fs::write(root.join("c"), "").unwrap();
+
+        let mismatches = manifest.verify(&root).unwrap();
+        let described: Vec<_> = mismatches.iter().map(Mismatch::to_string).collect();
+        assert_eq!(described.len(), 4, "{:?}", described);
+        assert!(
+            described[0].starts_with("a: expected sha256 "),
+            "{:?}",
+            described
+        );
+        assert_eq!(
+            &described[1..],
+            [
+                "c: extra",
+                "d/b: expected mode 644, found 600",
+                "l: missing"
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn build_skips_fifos() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = scratch("manifest-fifo");
+        std::fs::write(dir.join("f"), "").unwrap();
+        let fifo = std::ffi::CString::new(dir.join("pipe").as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o644) }, 0);
+
+        let manifest = Manifest::build(&dir, true).unwrap();
+        let paths: Vec<_> = manifest.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["f"]);
+    }
+}
@@ -0,0 +1,736 @@
+use crate::error::errno;
+use crate::{FileTypeEnum, Filesystem, FsMetadata};
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::SystemTime;
+
+/// The limit on symlinks traversed while resolving one path, as on Linux.
+const MAX_SYMLINK_HOPS: usize = 40;
+
+/// An in-memory [Filesystem] for tests, with files, directories, symlinks, and permissions.
+///
+/// Paths are resolved from `/`, so relative paths are relative to the root. Permission checks
+/// use only the owner bits: reading needs `0o400`, writing a file needs `0o200`, and creating,
+/// removing, or renaming entries needs `0o200` on the parent directory.
+///
+/// Failures can be simulated with [MemFs::inject_error], which fails every operation naming a
+/// path, and [MemFs::set_capacity], which fails writes beyond a total size as a full disk would.
+/// Errors carry the same OS error codes as [OsFs](crate::OsFs) on unix, so messages match.
+#[derive(Debug)]
+pub struct MemFs {
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    nodes: BTreeMap<PathBuf, Node>,
+    injected: HashMap<PathBuf, ErrorKind>,
+    capacity: Option<u64>,
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    kind: NodeKind,
+    mode: u32,
+    modified: SystemTime,
+}
+
+#[derive(Clone, Debug)]
+enum NodeKind {
+    File(Vec<u8>),
+    Dir,
+    Symlink(PathBuf),
+}
+
+impl Node {
+    fn new(kind: NodeKind, mode: u32) -> Self {
+        Node {
+            kind,
+            mode,
+            modified: SystemTime::now(),
+        }
+    }
+
+    fn is_dir(&self) -> bool {
+        matches!(self.kind, NodeKind::Dir)
+    }
+
+    fn len(&self) -> u64 {
+        match &self.kind {
+            NodeKind::File(bytes) => bytes.len() as u64,
+            NodeKind::Dir => 0,
+            NodeKind::Symlink(target) => target.as_os_str().len() as u64,
+        }
+    }
+
+    fn metadata(&self) -> FsMetadata {
+        FsMetadata {
+            file_type: match self.kind {
+                NodeKind::File(_) => FileTypeEnum::File,
+                NodeKind::Dir => FileTypeEnum::Dir,
+                NodeKind::Symlink(_) => FileTypeEnum::Symlink,
+            },
+            len: self.len(),
+            mode: self.mode,
+            modified: self.modified,
+        }
+    }
+}
+
+impl Default for MemFs {
+    fn default() -> Self {
+        MemFs::new()
+    }
+}
+
+impl MemFs {
+    /// Create a filesystem containing only an empty root directory.
+    pub fn new() -> Self {
+        let mut nodes = BTreeMap::new();
+        nodes.insert(PathBuf::from("/"), Node::new(NodeKind::Dir, 0o755));
+        MemFs {
+            state: Mutex::new(State {
+                nodes,
+                injected: HashMap::new(),
+                capacity: None,
+            }),
+        }
+    }
+
+    /// Fail every subsequent operation on `path` with an error of `kind`.
+    ///
+    /// The path matches whether it is named directly, reached through a symlink, or is inside a
+    /// tree removed by [Filesystem::remove_dir_all].
+    pub fn inject_error<P>(&self, path: P, kind: ErrorKind)
+    where
+        P: AsRef<Path>,
+    {
+        self.lock().injected.insert(lexical(path.as_ref()), kind);
+    }
+
+    /// Remove all errors added by [MemFs::inject_error].
+    pub fn clear_injected_errors(&self) {
+        self.lock().injected.clear();
+    }
+
+    /// Limit the total size of file contents, or remove the limit with `None`.
+    pub fn set_capacity(&self, capacity: Option<u64>) {
+        self.lock().capacity = capacity;
+    }
+
+    /// The total size of file contents.
+    pub fn used(&self) -> u64 {
+        self.lock().used()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // A panic while locked cannot leave the state inconsistent, since each operation checks
+        // everything before mutating:
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl State {
+    fn used(&self) -> u64 {
+        self.nodes
+            .values()
+            .filter(|n| matches!(n.kind, NodeKind::File(_)))
+            .map(Node::len)
+            .sum()
+    }
+
+    /// Fail if an error is injected for `path` as named or as resolved.
+    fn check_injected(&self, path: &Path, resolved: &Path) -> Result<()> {
+        for p in [lexical(path).as_path(), resolved] {
+            if let Some(&kind) = self.injected.get(p) {
+                return Err(errno(kind));
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolve symlinks in `path`, including the final component if `follow` is set.
+    ///
+    /// The final component need not exist, but all of its ancestors must be directories.
+    fn resolve(&self, path: &Path, follow: bool) -> Result<PathBuf> {
+        let mut rest = segments(path);
+        let mut cur = PathBuf::from("/");
+        let mut hops = 0;
+
+        while let Some(seg) = rest.pop() {
+            let name = match seg {
+                Segment::Root => {
+                    cur = PathBuf::from("/");
+                    continue;
+                }
+                Segment::Parent => {
+                    cur.pop();
+                    continue;
+                }
+                Segment::Name(name) => name,
+            };
+
+            let next = cur.join(&name);
+            let last = rest.is_empty();
+            match self.nodes.get(&next).map(|n| &n.kind) {
+                Some(NodeKind::Symlink(target)) if follow || !last => {
+                    hops += 1;
+                    if hops > MAX_SYMLINK_HOPS {
+                        return Err(symlink_loop());
+                    }
+                    rest.extend(segments(target));
+                }
+                Some(NodeKind::Dir) => cur = next,
+                Some(_) if !last => return Err(errno(ErrorKind::NotADirectory)),
+                None if !last => return Err(errno(ErrorKind::NotFound)),
+                _ => cur = next,
+            }
+        }
+        Ok(cur)
+    }
+
+    /// Resolve `path` and check it for injected errors.
+    fn resolve_checked(&self, path: &Path, follow: bool) -> Result<PathBuf> {
+        let resolved = self.resolve(path, follow)?;
+        self.check_injected(path, &resolved)?;
+        Ok(resolved)
+    }
+
+    fn get(&self, resolved: &Path) -> Result<&Node> {
+        self.nodes
+            .get(resolved)
+            .ok_or_else(|| errno(ErrorKind::NotFound))
+    }
+
+    fn get_mut(&mut self, resolved: &Path) -> Result<&mut Node> {
+        self.nodes
+            .get_mut(resolved)
+            .ok_or_else(|| errno(ErrorKind::NotFound))
+    }
+
+    /// Check that entries may be created in or removed from the parent of `resolved`.
+    fn check_parent_writable(&self, resolved: &Path) -> Result<()> {
+        let parent = match resolved.parent() {
+            Some(parent) => parent,
+            None => return Err(errno(ErrorKind::ResourceBusy)),
+        };
+        let node = self.get(parent)?;
+        if !node.is_dir() {
+            Err(errno(ErrorKind::NotADirectory))
+        } else if node.mode & 0o200 == 0 {
+            Err(errno(ErrorKind::PermissionDenied))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn children<'a>(&'a self, dir: &'a Path) -> impl Iterator<Item = &'a PathBuf> + 'a {
+        self.nodes
+            .range(dir.to_path_buf()..)
+            .skip(1)
+            .map(|(p, _)| p)
+            .take_while(move |p| p.starts_with(dir))
+    }
+
+    fn has_children(&self, dir: &Path) -> bool {
+        self.children(dir).next().is_some()
+    }
+
+    fn create_dir(&mut self, path: &Path) -> Result<()> {
+        let resolved = self.resolve_checked(path, false)?;
+        if self.nodes.contains_key(&resolved) {
+            return Err(errno(ErrorKind::AlreadyExists));
+        }
+        self.check_parent_writable(&resolved)?;
+        self.nodes.insert(resolved, Node::new(NodeKind::Dir, 0o755));
+        Ok(())
+    }
+
+    fn create_dir_all(&mut self, path: &Path) -> Result<()> {
+        match self.resolve(path, true) {
+            Ok(resolved) => {
+                self.check_injected(path, &resolved)?;
+                match self.nodes.get(&resolved) {
+                    Some(node) if node.is_dir() => return Ok(()),
+                    Some(_) => return Err(errno(ErrorKind::AlreadyExists)),
+                    None => {}
+                }
+            }
+            Err(_) => self.check_injected(path, &lexical(path))?,
+        }
+        if let Some(parent) = path.parent() {
+            self.create_dir_all(parent)?;
+        }
+        self.create_dir(path)
+    }
+
+    fn write(&mut self, path: &Path, contents: &[u8]) -> Result<()> {
+        let resolved = self.resolve_checked(path, true)?;
+        let (mode, oldlen) = match self.nodes.get(&resolved) {
+            Some(node) if node.is_dir() => return Err(errno(ErrorKind::IsADirectory)),
+            Some(node) if node.mode & 0o200 == 0 => {
+                return Err(errno(ErrorKind::PermissionDenied));
+            }
+            Some(node) => (node.mode, node.len()),
+            None => {
+                self.check_parent_writable(&resolved)?;
+                (0o644, 0)
+            }
+        };
+
+        if let Some(capacity) = self.capacity {
+            if self.used() - oldlen + contents.len() as u64 > capacity {
+                return Err(errno(ErrorKind::StorageFull));
+            }
+        }
+
+        self.nodes
+            .insert(resolved, Node::new(NodeKind::File(contents.to_vec()), mode));
+        Ok(())
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> Result<()> {
+        let from = self.resolve_checked(from, false)?;
+        let to = self.resolve_checked(to, false)?;
+        let from_is_dir = self.get(&from)?.is_dir();
+        self.check_parent_writable(&from)?;
+        self.check_parent_writable(&to)?;
+
+        if from == to {
+            return Ok(());
+        }
+        if to.starts_with(&from) {
+            return Err(errno(ErrorKind::InvalidInput));
+        }
+        match self.nodes.get(&to) {
+            Some(node) if node.is_dir() && !from_is_dir => {
+                return Err(errno(ErrorKind::IsADirectory));
+            }
+            Some(node) if !node.is_dir() && from_is_dir => {
+                return Err(errno(ErrorKind::NotADirectory));
+            }
+            Some(_) if self.has_children(&to) => {
+                return Err(errno(ErrorKind::DirectoryNotEmpty));
+            }
+            _ => {}
+        }
+
+        let moved: Vec<PathBuf> = std::iter::once(&from)
+            .chain(self.children(&from))
+            .cloned()
+            .collect();
+        for old in moved {
+            let node = self.nodes.remove(&old).unwrap();
+            let new = to.join(old.strip_prefix(&from).unwrap());
+            self.nodes.insert(new, node);
+        }
+        Ok(())
+    }
+}
+
+impl Filesystem for MemFs {
+    fn metadata(&self, path: &Path) -> Result<FsMetadata> {
+        let state = self.lock();
+        let resolved = state.resolve_checked(path, true)?;
+        state.get(&resolved).map(Node::metadata)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> Result<FsMetadata> {
+        let state = self.lock();
+        let resolved = state.resolve_checked(path, false)?;
+        state.get(&resolved).map(Node::metadata)
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        let state = self.lock();
+        let resolved = state.resolve_checked(path, true)?;
+        let node = state.get(&resolved)?;
+        match &node.kind {
+            NodeKind::Dir => Err(errno(ErrorKind::IsADirectory)),
+            _ if node.mode & 0o400 == 0 => Err(errno(ErrorKind::PermissionDenied)),
+            NodeKind::File(bytes) => Ok(bytes.clone()),
+            NodeKind::Symlink(_) => unreachable!("resolve follows final symlinks"),
+        }
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        self.lock().write(path, contents)
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<()> {
+        self.lock().create_dir(path)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<()> {
+        self.lock().create_dir_all(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<PathBuf>> {
+        let state = self.lock();
+        let resolved = state.resolve_checked(path, true)?;
+        let node = state.get(&resolved)?;
+        if !node.is_dir() {
+            return Err(errno(ErrorKind::NotADirectory));
+        } else if node.mode & 0o400 == 0 {
+            return Err(errno(ErrorKind::PermissionDenied));
+        }
+
+        Ok(state
+            .children(&resolved)
+            .filter(|p| p.parent() == Some(resolved.as_path()))
+            .map(|p| path.join(p.file_name().unwrap()))
+            .collect())
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<()> {
+        let mut state = self.lock();
+        let resolved = state.resolve_checked(path, false)?;
+        if state.get(&resolved)?.is_dir() {
+            return Err(errno(ErrorKind::IsADirectory));
+        }
+        state.check_parent_writable(&resolved)?;
+        state.nodes.remove(&resolved);
+        Ok(())
+    }
+
+    fn remove_dir(&self, path: &Path) -> Result<()> {
+        let mut state = self.lock();
+        let resolved = state.resolve_checked(path, false)?;
+        if !state.get(&resolved)?.is_dir() {
+            return Err(errno(ErrorKind::NotADirectory));
+        } else if state.has_children(&resolved) {
+            return Err(errno(ErrorKind::DirectoryNotEmpty));
+        }
+        state.check_parent_writable(&resolved)?;
+        state.nodes.remove(&resolved);
+        Ok(())
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> Result<()> {
+        let mut state = self.lock();
+        let resolved = state.resolve_checked(path, false)?;
+        if !state.get(&resolved)?.is_dir() {
+            return Err(errno(ErrorKind::NotADirectory));
+        }
+        state.check_parent_writable(&resolved)?;
+
+        let doomed: Vec<PathBuf> = state.children(&resolved).cloned().collect();
+        for p in &doomed {
+            state.check_injected(p, p)?;
+            state.check_parent_writable(p)?;
+        }
+        for p in doomed {
+            state.nodes.remove(&p);
+        }
+        state.nodes.remove(&resolved);
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        self.lock().rename(from, to)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<u64> {
+        let contents = self.read(from)?;
+        let mode = self.metadata(from)?.mode;
+        self.write(to, &contents)?;
+        self.set_mode(to, mode)?;
+        Ok(contents.len() as u64)
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> Result<()> {
+        let mut state = self.lock();
+        let resolved = state.resolve_checked(link, false)?;
+        if state.nodes.contains_key(&resolved) {
+            return Err(errno(ErrorKind::AlreadyExists));
+        }
+        state.check_parent_writable(&resolved)?;
+        state.nodes.insert(
+            resolved,
+            Node::new(NodeKind::Symlink(target.to_path_buf()), 0o777),
+        );
+        Ok(())
+    }
+
+    fn read_link(&self, path: &Path) -> Result<PathBuf> {
+        let state = self.lock();
+        let resolved = state.resolve_checked(path, false)?;
+        match &state.get(&resolved)?.kind {
+            NodeKind::Symlink(target) => Ok(target.clone()),
+            _ => Err(errno(ErrorKind::InvalidInput)),
+        }
+    }
+
+    fn set_mode(&self, path: &Path, mode: u32) -> Result<()> {
+        let mut state = self.lock();
+        let resolved = state.resolve_checked(path, true)?;
+        state.get_mut(&resolved)?.mode = mode & 0o7777;
+        Ok(())
+    }
+
+    fn set_modified(&self, path: &Path, modified: SystemTime) -> Result<()> {
+        let mut state = self.lock();
+        let resolved = state.resolve_checked(path, true)?;
+        state.get_mut(&resolved)?.modified = modified;
+        Ok(())
+    }
+}
+
+enum Segment {
+    Root,
+    Parent,
+    Name(OsString),
+}
+
+/// The segments of `path` in reverse order, for popping while resolving.
+fn segments(path: &Path) -> Vec<Segment> {
+    let mut segs: Vec<Segment> = path
+        .components()
+        .filter_map(|c| match c {
+            Component::Prefix(_) | Component::RootDir => Some(Segment::Root),
+            Component::CurDir => None,
+            Component::ParentDir => Some(Segment::Parent),
+            Component::Normal(name) => Some(Segment::Name(name.to_os_string())),
+        })
+        .collect();
+    segs.reverse();
+    segs
+}
+
+/// Absolutize and normalize `path` without resolving symlinks.
+fn lexical(path: &Path) -> PathBuf {
+    let mut out = PathBuf::from("/");
+    for seg in segments(path).into_iter().rev() {
+        match seg {
+            Segment::Root => out = PathBuf::from("/"),
+            Segment::Parent => {
+                out.pop();
+            }
+            Segment::Name(name) => out.push(name),
+        }
+    }
+    out
+}
+
+#[cfg(unix)]
+fn symlink_loop() -> Error {
+    Error::from_raw_os_error(libc::ELOOP)
+}
+
+#[cfg(not(unix))]
+fn symlink_loop() -> Error {
+    other_error_fmt!("too many levels of symbolic links")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MemFs;
+    use crate::Filesystem;
+    use std::io::{ErrorKind, Result};
+    use std::path::{Path, PathBuf};
+
+    /// A filesystem with `/d/f` containing `data`, an empty dir `/d/sub`, and `/link` to `/d`.
+    fn sample() -> MemFs {
+        let fs = MemFs::new();
+        fs.create_dir_all(Path::new("/d/sub")).unwrap();
+        fs.write(Path::new("/d/f"), b"data").unwrap();
+        fs.symlink(Path::new("/d"), Path::new("/link")).unwrap();
+        fs
+    }
+
+    fn kind<T>(res: Result<T>) -> ErrorKind {
+        res.err().map(|e| e.kind()).expect("an error")
+    }
+
+    #[test]
+    fn resolves_relative_parent_and_symlink_paths() {
+        let fs = sample();
+        for path in [
+            "d/f",
+            "/d/sub/../f",
+            "/link/f",
+            "link/sub/../f",
+            "/../d/./f",
+        ] {
+            assert_eq!(fs.read(Path::new(path)).unwrap(), b"data", "{}", path);
+        }
+        assert!(fs
+            .symlink_metadata(Path::new("/link"))
+            .unwrap()
+            .is_symlink());
+        assert!(fs.metadata(Path::new("/link")).unwrap().is_dir());
+        assert_eq!(kind(fs.read(Path::new("/d/f/x"))), ErrorKind::NotADirectory);
+        assert_eq!(kind(fs.read(Path::new("/nope/f"))), ErrorKind::NotFound);
+
+        fs.symlink(Path::new("/loop"), Path::new("/loop")).unwrap();
+        let err = fs.read(Path::new("/loop")).unwrap_err();
+        #[cfg(unix)]
+        assert_eq!(err.raw_os_error(), Some(libc::ELOOP));
+        #[cfg(not(unix))]
+        assert!(err.to_string().contains("symbolic links"));
+    }
+
+    #[test]
+    fn read_dir_names_children_under_the_given_path() {
+        let fs = sample();
+        let mut names = fs.read_dir(Path::new("/link")).unwrap();
+        names.sort();
+        assert_eq!(
+            names,
+            [PathBuf::from("/link/f"), PathBuf::from("/link/sub")]
+        );
+    }
+
+    #[test]
+    fn rename_over_existing_entries() {
+        let fs = sample();
+        fs.write(Path::new("/g"), b"other").unwrap();
+        fs.rename(Path::new("/g"), Path::new("/d/f")).unwrap();
+        assert_eq!(fs.read(Path::new("/d/f")).unwrap(), b"other");
+        assert_eq!(kind(fs.metadata(Path::new("/g"))), ErrorKind::NotFound);
+
+        let rename = |from: &str, to: &str| fs.rename(Path::new(from), Path::new(to));
+        assert_eq!(kind(rename("/d/f", "/d/sub")), ErrorKind::IsADirectory);
+        assert_eq!(kind(rename("/d/sub", "/d/f")), ErrorKind::NotADirectory);
+        assert_eq!(
+            kind(rename("/d/sub", "/d/sub/inner")),
+            ErrorKind::InvalidInput
+        );
+
+        fs.create_dir(Path::new("/e")).unwrap();
+        assert_eq!(kind(rename("/e", "/d")), ErrorKind::DirectoryNotEmpty);
+        rename("/d", "/e").unwrap();
+        assert_eq!(fs.read(Path::new("/e/f")).unwrap(), b"other");
+        assert!(fs.metadata(Path::new("/e/sub")).unwrap().is_dir());
+        assert_eq!(kind(fs.metadata(Path::new("/d"))), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn remove_dir_requires_an_empty_dir() {
+        let fs = sample();
+        assert_eq!(
+            kind(fs.remove_dir(Path::new("/d"))),
+            ErrorKind::DirectoryNotEmpty
+        );
+        assert_eq!(
+            kind(fs.remove_dir(Path::new("/d/f"))),
+            ErrorKind::NotADirectory
+        );
+        assert_eq!(
+            kind(fs.remove_file(Path::new("/d/sub"))),
+            ErrorKind::IsADirectory
+        );
+        fs.remove_dir(Path::new("/d/sub")).unwrap();
+        fs.remove_dir_all(Path::new("/d")).unwrap();
+        assert_eq!(kind(fs.metadata(Path::new("/d"))), ErrorKind::NotFound);
+        assert!(fs.symlink_metadata(Path::new("/link")).is_ok());
+    }
+
+    #[test]
+    fn permissions_use_owner_bits() {
+        let fs = sample();
+        fs.set_mode(Path::new("/d/f"), 0o200).unwrap();
+        assert_eq!(
+            kind(fs.read(Path::new("/d/f"))),
+            ErrorKind::PermissionDenied
+        );
+        fs.set_mode(Path::new("/d/f"), 0o444).unwrap();
+        assert_eq!(
+            kind(fs.write(Path::new("/d/f"), b"x")),
+            ErrorKind::PermissionDenied
+        );
+        assert_eq!(fs.metadata(Path::new("/d/f")).unwrap().mode, 0o444);
+
+        fs.set_mode(Path::new("/d"), 0o555).unwrap();
+        let denied = [
+            fs.write(Path::new("/d/new"), b"x"),
+            fs.create_dir(Path::new("/d/new")),
+            fs.remove_file(Path::new("/d/f")),
+            fs.remove_dir(Path::new("/d/sub")),
+            fs.rename(Path::new("/d/f"), Path::new("/f")),
+            fs.symlink(Path::new("f"), Path::new("/d/new")),
+        ];
+        for res in denied {
+            assert_eq!(kind(res), ErrorKind::PermissionDenied);
+        }
+
+        fs.set_mode(Path::new("/d"), 0o300).unwrap();
+        assert_eq!(
+            kind(fs.read_dir(Path::new("/d"))),
+            ErrorKind::PermissionDenied
+        );
+    }
+
+    #[test]
+    fn capacity_limits_file_contents() {
+        let fs = sample();
+        fs.set_capacity(Some(10));
+        fs.write(Path::new("/d/g"), b"123456").unwrap();
+        assert_eq!(fs.used(), 10);
+        assert_eq!(
+            kind(fs.write(Path::new("/d/h"), b"1")),
+            ErrorKind::StorageFull
+        );
+        fs.write(Path::new("/d/f"), b"").unwrap();
+        fs.write(Path::new("/d/h"), b"1234").unwrap();
+    }
+
+    #[test]
+    fn injected_errors_fail_every_operation() {
+        type Op = fn(&MemFs) -> Result<()>;
+        let ops: &[(&str, Op)] = &[
+            ("metadata", |fs| fs.metadata(Path::new("/d/f")).map(drop)),
+            ("symlink_metadata", |fs| {
+                fs.symlink_metadata(Path::new("/d/f")).map(drop)
+            }),
+            ("read", |fs| fs.read(Path::new("/link/f")).map(drop)),
+            ("write", |fs| fs.write(Path::new("/d/f"), b"x")),
+            ("create_dir", |fs| fs.create_dir(Path::new("/d/new"))),
+            ("create_dir_all existing", |fs| {
+                fs.create_dir_all(Path::new("/d/sub"))
+            }),
+            ("create_dir_all missing", |fs| {
+                fs.create_dir_all(Path::new("/d/new/deeper"))
+            }),
+            ("read_dir", |fs| fs.read_dir(Path::new("/d/sub")).map(drop)),
+            ("remove_file", |fs| fs.remove_file(Path::new("/d/f"))),
+            ("remove_dir", |fs| fs.remove_dir(Path::new("/d/sub"))),
+            ("remove_dir_all", |fs| fs.remove_dir_all(Path::new("/d"))),
+            ("rename from", |fs| {
+                fs.rename(Path::new("/d/f"), Path::new("/g"))
+            }),
+            ("rename to", |fs| {
+                fs.rename(Path::new("/g"), Path::new("/d/f"))
+            }),
+            ("copy", |fs| {
+                fs.copy(Path::new("/g"), Path::new("/d/f")).map(drop)
+            }),
+            ("symlink", |fs| {
+                fs.symlink(Path::new("f"), Path::new("/d/new"))
+            }),
+            ("read_link", |fs| fs.read_link(Path::new("/d/f")).map(drop)),
+            ("set_mode", |fs| fs.set_mode(Path::new("/d/f"), 0o600)),
+            ("set_modified", |fs| {
+                fs.set_modified(Path::new("/d/f"), std::time::UNIX_EPOCH)
+            }),
+        ];
+        let injected = ["/d/f", "/d/new", "/d/new/deeper", "/d/sub"];
+
+        for &(name, op) in ops {
+            let fs = sample();
+            fs.write(Path::new("/g"), b"g").unwrap();
+            for path in injected {
+                fs.inject_error(path, ErrorKind::ReadOnlyFilesystem);
+            }
+            assert_eq!(kind(op(&fs)), ErrorKind::ReadOnlyFilesystem, "{}", name);
+
+            fs.clear_injected_errors();
+            if name != "read_link" {
+                op(&fs).unwrap_or_else(|e| panic!("{}: {}", name, e));
+            }
+        }
+    }
+}
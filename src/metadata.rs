@@ -1,11 +1,16 @@
-use crate::FileTypeEnum;
+use crate::{FileTypeEnum, LsLine, StatDump};
 use error_annotation::AnnotateResult;
 use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
 use std::fs::{FileType, Metadata, Permissions};
 use std::io::Result;
 use std::path::Path;
 use std::time::SystemTime;
 
+/// Extended attribute names mapped to their values, as optionally carried by [PathMetadata].
+pub type XattrMap = BTreeMap<OsString, Vec<u8>>;
+
 /// Extend [Metadata] with the originating [Path] for improved error messages.
 ///
 /// This enables [std::io::Error] results to be annotated with the offending path.
@@ -13,6 +18,7 @@ use std::time::SystemTime;
 pub struct PathMetadata<'a> {
     path: Cow<'a, Path>,
     md: Metadata,
+    xattrs: Option<XattrMap>,
 }
 
 impl<'a> PathMetadata<'a> {
@@ -22,7 +28,22 @@ impl<'a> PathMetadata<'a> {
         Cow<'a, Path>: From<P>,
     {
         let path = Cow::from(path);
-        PathMetadata { path, md }
+        PathMetadata {
+            path,
+            md,
+            xattrs: None,
+        }
+    }
+
+    /// Attach extended attributes to this `PathMetadata`.
+    ///
+    /// [PathExt::pe_metadata_with_xattrs](crate::PathExt::pe_metadata_with_xattrs) loads and
+    /// attaches them in one step.
+    pub fn with_xattrs(self, xattrs: XattrMap) -> Self {
+        PathMetadata {
+            xattrs: Some(xattrs),
+            ..self
+        }
     }
 
     /// Access associated [Path].
@@ -37,6 +58,11 @@ impl<'a> PathMetadata<'a> {
         &self.md
     }
 
+    /// Access the extended attributes, if they were loaded.
+    pub fn xattrs(&self) -> Option<&XattrMap> {
+        self.xattrs.as_ref()
+    }
+
     /// Unwrap the underlying [Metadata].
     pub fn unwrap(self) -> Metadata {
         self.md
@@ -98,6 +124,30 @@ impl<'a> PathMetadata<'a> {
             .annotate_err_into("path", || self.path.display())
     }
 
+    /// Render an `ls -l` style line, configurable via [LsLine] methods.
+    ///
+    /// The `Display` impl renders the same line with default settings.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pathutil::{PathExt, TimeStyle};
+    ///
+    /// let pb = std::path::Path::new("/");
+    /// let md = pb.pe_metadata().unwrap();
+    /// let line = md.ls().time_style(TimeStyle::Iso).to_string();
+    /// assert!(line.starts_with('d'));
+    /// assert!(line.ends_with(" /"));
+    /// ```
+    pub fn ls(&self) -> LsLine<'_> {
+        LsLine::new(self)
+    }
+
+    /// Render a long-form multi-line description like `stat(1)`.
+    pub fn stat(&self) -> StatDump<'_> {
+        StatDump::new(self)
+    }
+
     /// Return an error if the filetype does not match the expectation.
     ///
     /// # Example
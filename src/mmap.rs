@@ -0,0 +1,136 @@
+use error_annotation::AnnotateResult;
+use memmap2::{Mmap, MmapMut};
+use std::fs::{File, OpenOptions};
+use std::io::Result;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+
+/// A read-only memory mapping of a file, created by
+/// [PathExt::pe_mmap](crate::PathExt::pe_mmap), which derefs to the file contents.
+///
+/// Empty files cannot be mapped, so they are represented without a mapping as an empty slice.
+///
+/// The mapping reflects concurrent modifications of the file by other processes, and truncating
+/// the file while it is mapped causes `SIGBUS` on access, which is why creating one is `unsafe`.
+#[derive(Debug)]
+pub struct PathMmap {
+    path: PathBuf,
+    map: Option<Mmap>,
+}
+
+impl PathMmap {
+    /// Access associated [Path].
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Deref for PathMmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.map.as_deref().unwrap_or(&[])
+    }
+}
+
+/// A writable shared memory mapping of a file, created by
+/// [PathExt::pe_mmap_mut](crate::PathExt::pe_mmap_mut), which derefs to the file contents.
+///
+/// As with [PathMmap], empty mappings are represented without a mapping.
+#[derive(Debug)]
+pub struct PathMmapMut {
+    path: PathBuf,
+    map: Option<MmapMut>,
+}
+
+impl PathMmapMut {
+    /// Access associated [Path].
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Flush modifications to the file, annotating errors with the path.
+    pub fn flush(&self) -> Result<()> {
+        match &self.map {
+            Some(map) => map
+                .flush()
+                .annotate_err_into("path", || self.path.display()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Deref for PathMmapMut {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.map.as_deref().unwrap_or(&[])
+    }
+}
+
+impl DerefMut for PathMmapMut {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.map.as_deref_mut().unwrap_or(&mut [])
+    }
+}
+
+/// # Safety
+///
+/// See [PathExt::pe_mmap](crate::PathExt::pe_mmap).
+pub(crate) unsafe fn map(path: &Path) -> Result<PathMmap> {
+    let f = File::open(path).annotate_err_into("path", || path.display())?;
+    let len = f
+        .metadata()
+        .annotate_err_into("path", || path.display())?
+        .len();
+
+    let map = check_len(len)
+        // SAFETY: The caller upholds the obligations of `PathExt::pe_mmap`.
+        .and_then(|()| (len > 0).then(|| unsafe { Mmap::map(&f) }).transpose())
+        .annotate_err_into("path", || path.display())
+        .annotate_err_into("length", || len)?;
+
+    Ok(PathMmap {
+        path: path.to_path_buf(),
+        map,
+    })
+}
+
+/// # Safety
+///
+/// See [PathExt::pe_mmap_mut](crate::PathExt::pe_mmap_mut).
+pub(crate) unsafe fn map_mut(path: &Path, len: u64) -> Result<PathMmapMut> {
+    let map = unsafe { open_and_map_mut(path, len) }
+        .annotate_err_into("path", || path.display())
+        .annotate_err_into("length", || len)?;
+
+    Ok(PathMmapMut {
+        path: path.to_path_buf(),
+        map,
+    })
+}
+
+/// # Safety
+///
+/// See [PathExt::pe_mmap_mut](crate::PathExt::pe_mmap_mut).
+unsafe fn open_and_map_mut(path: &Path, len: u64) -> Result<Option<MmapMut>> {
+    check_len(len)?;
+    let f = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    f.set_len(len)?;
+
+    // SAFETY: The caller upholds the obligations of `PathExt::pe_mmap_mut`.
+    (len > 0)
+        .then(|| unsafe { MmapMut::map_mut(&f) })
+        .transpose()
+}
+
+fn check_len(len: u64) -> Result<()> {
+    usize::try_from(len)
+        .map(|_| ())
+        .map_err(|_| other_error_fmt!("file too large to map on this platform"))
+}
@@ -1,10 +1,27 @@
-use crate::{other_error, PathDirEntry, PathMetadata, PathReadDir};
+use crate::progress::Quiet;
+use crate::{
+    other_error, CopyOptions, CopyReport, DirOptions, EnsureOutcome, FilesystemExt, Find, LockMode,
+    Manifest, MultiPathError, ObservedWalk, Observer, OsFs, PathDecoder, PathDirEntry, PathEncoder,
+    PathFile, PathLock, PathMetadata, PathReadDir, PathWalk, SyncOptions, SyncReport, Target,
+    Timestamp,
+};
 use error_annotation::AnnotateResult;
+use filetime::FileTime;
 use indoc::indoc;
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
 use std::fs::Permissions;
-use std::io::Result;
+use std::io::{Read, Result, Write};
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+#[cfg(any(feature = "tar", feature = "zip"))]
+use crate::ArchiveOptions;
+
+#[cfg(feature = "mmap")]
+use crate::{PathMmap, PathMmapMut};
+
+#[cfg(target_os = "linux")]
+use crate::xattrs::Follow;
 
 /// A trait to extend [std::path::Path] with error and [std::fs] operation improvements.
 ///
@@ -281,6 +298,199 @@ pub trait PathExt: AsRef<Path> {
         o2r(path, os.to_str(), "invalid utf8")
     }
 
+    /// Return all extensions of the file name in order, such as `tar` and `gz` for
+    /// `backup.tar.gz`, or the error explains "no file name". A leading `.` does not start an
+    /// extension, so `.bashrc` has none.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pathutil::PathExt;
+    ///
+    /// let p = std::path::Path::new("/tmp/backup.tar.gz");
+    /// assert_eq!(p.pe_extensions().unwrap(), ["tar", "gz"]);
+    /// assert_eq!(p.pe_file_prefix().unwrap(), "backup");
+    /// assert_eq!(p.pe_file_stem().unwrap(), "backup.tar");
+    /// ```
+    fn pe_extensions(&self) -> Result<Vec<&OsStr>> {
+        crate::components::split_extensions(self.as_ref()).map(|(_, exts)| exts)
+    }
+
+    /// Returns the file name before its first extension, or the error explains "no file name".
+    /// See [PathExt::pe_extensions].
+    fn pe_file_prefix(&self) -> Result<&OsStr> {
+        crate::components::split_extensions(self.as_ref()).map(|(prefix, _)| prefix)
+    }
+
+    /// Returns the file prefix as a utf8 [&str], or the error explains "no file name" or else
+    /// "invalid utf8".
+    fn pe_file_prefix_str(&self) -> Result<&str> {
+        let path = self.as_ref();
+        let os = self.pe_file_prefix()?;
+        o2r(path, os.to_str(), "invalid utf8")
+    }
+
+    /// Returns each component as a utf8 [&str], or the error explains "invalid utf8" with the
+    /// index of the first invalid `component`.
+    ///
+    #[cfg_attr(
+        target_os = "linux",
+        doc = indoc! {r#"
+            # Example
+
+            ```
+            use pathutil::PathExt;
+            use std::path::Path;
+            use std::ffi::OsStr;
+            use std::os::unix::ffi::OsStrExt;
+
+            let p = Path::new(OsStr::from_bytes(b"/tmp/\xff/f"));
+            let res = p.pe_components_str();
+            assert!(res.is_err());
+
+            let errstr = res.err().unwrap().to_string();
+            assert_eq!(&errstr, "
+
+            invalid utf8
+            -with path: /tmp/�/f
+            -with component: 2
+
+            ".trim());
+            ```
+        "#}
+    )]
+    fn pe_components_str(&self) -> Result<Vec<&str>> {
+        crate::components::components_str(self.as_ref())
+    }
+
+    /// Replace the file name with `name`, or the error explains "no file name", or else "invalid
+    /// file name" if `name` is not a single component such as `a/b` or `..`.
+    fn pe_with_file_name_checked<S>(&self, name: S) -> Result<PathBuf>
+    where
+        S: AsRef<OsStr>,
+    {
+        crate::components::with_file_name_checked(self.as_ref(), name.as_ref())
+    }
+
+    /// Replace the extension with `ext`, or the error explains "no file name", or else "invalid
+    /// extension" if `ext` starts with `.` or contains a separator. An empty `ext` removes the
+    /// extension.
+    fn pe_with_extension_checked<S>(&self, ext: S) -> Result<PathBuf>
+    where
+        S: AsRef<OsStr>,
+    {
+        crate::components::with_extension_checked(self.as_ref(), ext.as_ref())
+    }
+
+    /// Replace the leading `old` components with `new`, or if the path does not begin with
+    /// `old`, describe both as [PathExt::pe_strip_prefix] does.
+    fn pe_replace_prefix<P, Q>(&self, old: P, new: Q) -> Result<PathBuf>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        crate::components::replace_prefix(self.as_ref(), old.as_ref(), new.as_ref())
+    }
+
+    /// Check that this path, and every entry beneath it if it is a directory, can be created on
+    /// `target`, collecting every problem.
+    ///
+    /// The components of this path are checked as given, and entries beneath it are also checked
+    /// for names which collide on a case-insensitive target. Each error names the `component` and
+    /// the `rule` it breaks; see [Target] for the rules.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pathutil::{PathExt, Target};
+    ///
+    /// let p = std::path::Path::new("/this/path/does/not/exist/aux.txt/why?");
+    /// let res = p.pe_check_portable(Target::Windows);
+    /// assert!(res.is_err());
+    ///
+    /// let errstr = res.err().unwrap().to_string();
+    /// assert_eq!(&errstr, r#"
+    ///
+    /// 2 errors:
+    /// not portable to Windows
+    /// -with path: /this/path/does/not/exist/aux.txt/why?
+    /// -with component: aux.txt
+    /// -with rule: reserved name
+    /// not portable to Windows
+    /// -with path: /this/path/does/not/exist/aux.txt/why?
+    /// -with component: why?
+    /// -with rule: forbidden character '?'
+    ///
+    /// "#.trim());
+    /// ```
+    fn pe_check_portable(&self, target: Target) -> std::result::Result<(), MultiPathError> {
+        crate::portable::check_portable(self.as_ref(), target)
+    }
+
+    /// Returns this path and its ancestors up to and including `root`, deepest first, or the
+    /// error explains "not within root".
+    fn pe_ancestors_until<P>(&self, root: P) -> Result<Vec<&Path>>
+    where
+        P: AsRef<Path>,
+    {
+        crate::components::ancestors_until(self.as_ref(), root.as_ref())
+    }
+
+    /// Expand a leading `~` or `~user`, and any `$VAR`, `${VAR}`, or `${VAR:-default}` references
+    /// from the process environment, or the error names the undefined variable or unknown user.
+    ///
+    /// As in the shell, `${VAR:-default}` uses `default` when `VAR` is unset or empty, and a `$`
+    /// which is not followed by a variable name is kept literally.
+    fn pe_expand(&self) -> Result<PathBuf> {
+        self.pe_expand_with(crate::expand::env_var)
+    }
+
+    /// Identical to [PathExt::pe_expand] except variables, including `HOME`, are resolved by
+    /// `lookup` rather than from the process environment.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pathutil::PathExt;
+    /// use std::path::Path;
+    ///
+    /// let lookup = |name: &str| (name == "HOME").then(|| "/home/alice".into());
+    ///
+    /// let p = Path::new("~/${CACHE:-.cache}/x");
+    /// assert_eq!(p.pe_expand_with(lookup).unwrap(), Path::new("/home/alice/.cache/x"));
+    ///
+    /// let p = Path::new("$HOME/$APPNAME/config");
+    /// let res = p.pe_expand_with(lookup);
+    /// assert!(res.is_err());
+    ///
+    /// let errstr = res.err().unwrap().to_string();
+    /// assert_eq!(&errstr, "
+    ///
+    /// undefined variable
+    /// -with variable: APPNAME
+    /// -with path: $HOME/$APPNAME/config
+    ///
+    /// ".trim());
+    /// ```
+    fn pe_expand_with<F>(&self, lookup: F) -> Result<PathBuf>
+    where
+        F: Fn(&str) -> Option<OsString>,
+    {
+        let path = self.as_ref();
+        crate::expand::expand(path, lookup).annotate_err_into("path", || path.display())
+    }
+
+    /// Treating this path as a root directory, resolve `untrusted` beneath it, or the error names
+    /// the component which escapes the root.
+    ///
+    /// This is shorthand for [PathJail::resolve](crate::PathJail::resolve).
+    fn pe_join_within<P>(&self, untrusted: P) -> Result<PathBuf>
+    where
+        P: AsRef<Path>,
+    {
+        crate::PathJail::new(self)?.resolve(untrusted)
+    }
+
     /// Return the path's [PathMetadata] or include the path in the error description.
     ///
     /// # Example
@@ -333,6 +543,20 @@ pub trait PathExt: AsRef<Path> {
             .map(|md| PathMetadata::new(path, md))
     }
 
+    /// Return the path's [PathMetadata] with its extended attributes loaded, following symlinks.
+    #[cfg(target_os = "linux")]
+    fn pe_metadata_with_xattrs(&self) -> Result<PathMetadata<'_>> {
+        let xattrs = crate::xattrs::load(self.as_ref(), Follow::Deref)?;
+        self.pe_metadata().map(|md| md.with_xattrs(xattrs))
+    }
+
+    /// Return the symlink's [PathMetadata] with the symlink's own extended attributes loaded.
+    #[cfg(target_os = "linux")]
+    fn pe_symlink_metadata_with_xattrs(&self) -> Result<PathMetadata<'_>> {
+        let xattrs = crate::xattrs::load(self.as_ref(), Follow::NoDeref)?;
+        self.pe_symlink_metadata().map(|md| md.with_xattrs(xattrs))
+    }
+
     /// Return the canonicalized path or else include the path in the error description.
     ///
     /// # Example
@@ -426,14 +650,253 @@ pub trait PathExt: AsRef<Path> {
             .annotate_err_into("to", || topath.display())
     }
 
+    /// Copy to `to` destination with a reflink where possible, then `copy_file_range`, then a
+    /// buffered copy, reporting which [CopyStrategy](crate::CopyStrategy) was used.
+    ///
+    /// See [CopyOptions] for which attributes are kept, sparse copies, and progress reports.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pathutil::{CopyOptions, PathExt};
+    ///
+    /// let p = std::path::Path::new("/this/path/does/not/exist");
+    /// let res = p.pe_copy_with("/tmp/copy-dest", CopyOptions::default());
+    /// assert!(res.is_err());
+    ///
+    /// let errstr = res.err().unwrap().to_string();
+    /// assert_eq!(&errstr, "
+    ///
+    /// No such file or directory (os error 2)
+    /// -with from: /this/path/does/not/exist
+    /// -with to: /tmp/copy-dest
+    ///
+    /// ".trim());
+    /// ```
+    fn pe_copy_with<P>(&self, to: P, options: CopyOptions) -> Result<CopyReport>
+    where
+        P: AsRef<Path>,
+    {
+        crate::copy::copy(self.as_ref(), to.as_ref(), options)
+    }
+
+    /// Iterate over the tree rooted at this path in depth-first pre-order; see [PathWalk].
+    fn pe_walk(&self) -> PathWalk {
+        PathWalk::new(self.as_ref())
+    }
+
+    /// Search the tree rooted at this path for entries passing the tests added to the [Find].
+    fn pe_find(&self) -> Find {
+        Find::new(self.as_ref())
+    }
+
+    /// Iterate over the tree rooted at this path, reporting each entry to `observer`; see
+    /// [PathWalk::observed].
+    fn pe_walk_observed<'a>(&self, observer: &'a mut dyn Observer) -> ObservedWalk<'a> {
+        self.pe_walk().observed(observer)
+    }
+
+    /// Return the lowercase hex sha256 digest of the file contents.
+    fn pe_sha256(&self) -> Result<String> {
+        crate::hash::sha256_file(self.as_ref())
+    }
+
+    /// Return the lowercase hex sha256 digest of the file contents, reporting the bytes hashed
+    /// to `observer` and stopping between chunks if it is cancelled.
+    fn pe_sha256_observed(&self, observer: &mut dyn Observer) -> Result<String> {
+        crate::hash::sha256_file_observed(self.as_ref(), observer)
+    }
+
+    /// Snapshot the tree rooted at this path, optionally with file hashes; see [Manifest].
+    fn pe_manifest(&self, hash: bool) -> Result<Manifest> {
+        Manifest::build(self, hash)
+    }
+
+    /// Mirror the directory at this path into `dest` one way, copying new or changed files and
+    /// keeping permissions, modification times, and symlinks.
+    ///
+    /// Failures of individual entries do not abort the sync; they are collected in the
+    /// [SyncReport] annotated with the source and destination. Only a failure to start, such as
+    /// this path not being a directory, is returned as an error. See [SyncOptions] for change
+    /// detection, deletion of extra destination entries, and dry runs.
+    fn pe_sync_to<P>(&self, dest: P, options: SyncOptions) -> Result<SyncReport>
+    where
+        P: AsRef<Path>,
+    {
+        crate::sync::sync(self.as_ref(), dest.as_ref(), options, &mut Quiet)
+    }
+
+    /// [PathExt::pe_sync_to], reporting each entry and the bytes of each copy to `observer`.
+    ///
+    /// If the observer is cancelled, the sync stops between entries or chunks and returns the
+    /// cancellation error rather than a report, leaving the destination partially synced.
+    fn pe_sync_to_observed<P>(
+        &self,
+        dest: P,
+        options: SyncOptions,
+        observer: &mut dyn Observer,
+    ) -> Result<SyncReport>
+    where
+        P: AsRef<Path>,
+    {
+        crate::sync::sync(self.as_ref(), dest.as_ref(), options, observer)
+    }
+
+    /// Pack the tree rooted at this path into a new tar archive at `dest`.
+    ///
+    /// Member names are relative to this path, and symlinks are stored rather than followed.
+    /// Errors are annotated with the archive and the member being added.
+    #[cfg(feature = "tar")]
+    fn pe_tar_create<P>(&self, dest: P, options: ArchiveOptions) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        crate::tarball::create(self.as_ref(), dest.as_ref(), options)
+    }
+
+    /// Pack the tree rooted at this path into a new zip archive at `dest`.
+    ///
+    /// Member names are relative to this path, and symlinks are stored rather than followed.
+    /// Errors are annotated with the archive and the member being added.
+    #[cfg(feature = "zip")]
+    fn pe_zip_create<P>(&self, dest: P, options: ArchiveOptions) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        crate::ziparchive::create(self.as_ref(), dest.as_ref(), options)
+    }
+
+    /// Extract the tar or zip archive at this path into the directory `dest`, creating it if
+    /// necessary, and restoring modes and mtimes.
+    ///
+    /// The format is detected from magic bytes, or else the `.tar` or `.zip` extension. Members
+    /// which would be written outside `dest`, through `..` components, absolute paths, or
+    /// symlinks, are rejected with an error naming the member and the archive.
+    #[cfg(any(feature = "tar", feature = "zip"))]
+    fn pe_extract<P>(&self, dest: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        self.pe_extract_with(dest, ArchiveOptions::default())
+    }
+
+    /// As [PathExt::pe_extract] with the given [ArchiveOptions].
+    #[cfg(any(feature = "tar", feature = "zip"))]
+    fn pe_extract_with<P>(&self, dest: P, options: ArchiveOptions) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        crate::archive::extract(self.as_ref(), dest.as_ref(), options)
+    }
+
     /// Creates a new, empty directory at the provided path.
-    fn pe_create_dir<P>(&self) -> Result<()> {
+    fn pe_create_dir(&self) -> Result<()> {
         std::fs::create_dir(self).annotate_err_into("path", || self.as_ref().display())
     }
 
     /// Recursively create a directory and all of its parent components if they are missing.
-    fn pe_create_dir_all<P>(&self) -> Result<()> {
-        std::fs::create_dir_all(self).annotate_err_into("path", || self.as_ref().display())
+    ///
+    /// If a parent component cannot be created, the error names it as the `component`.
+    fn pe_create_dir_all(&self) -> Result<()> {
+        self.pe_create_dir_with(DirOptions {
+            recursive: true,
+            exist_ok: true,
+            ..DirOptions::default()
+        })
+    }
+
+    /// Create a directory according to [DirOptions], which set the mode, umask, whether parents
+    /// are created, and whether an existing directory is accepted.
+    ///
+    /// If a parent component cannot be created, the error names it as the `component`.
+    ///
+    #[cfg_attr(
+        target_os = "linux",
+        doc = indoc! {r#"
+            # Example
+
+            ```
+            use pathutil::{DirOptions, PathExt};
+
+            let p = std::path::Path::new("/proc/pathutil/a/b");
+            let res = p.pe_create_dir_with(DirOptions {
+                recursive: true,
+                mode: 0o750,
+                ..DirOptions::default()
+            });
+            assert!(res.is_err());
+
+            let errstr = res.err().unwrap().to_string();
+            assert_eq!(&errstr, "
+
+            No such file or directory (os error 2)
+            -with path: /proc/pathutil/a/b
+            -with component: /proc/pathutil
+
+            ".trim());
+            ```
+        "#}
+    )]
+    fn pe_create_dir_with(&self, options: DirOptions) -> Result<()> {
+        crate::createdir::create_dir_with(self.as_ref(), options)
+    }
+
+    /// Ensure a directory exists at this path with the permission `mode`, creating it and any
+    /// missing parents, or fixing its mode. The mode is ignored on non-unix platforms.
+    ///
+    /// Only the final directory gets `mode`. Missing parents are created with the default mode
+    /// of new directories, as filtered by the process umask, and existing parents are left as is.
+    ///
+    /// Symlinks to directories are accepted. Anything else at this path is an error.
+    fn pe_ensure_dir(&self, mode: u32) -> Result<EnsureOutcome> {
+        crate::ensure::ensure_dir(self.as_ref(), mode)
+    }
+
+    /// Ensure a file exists at this path with exactly `contents`, writing it only if it is
+    /// missing or differs. Anything other than a file at this path is an error.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pathutil::{EnsureOutcome, PathExt};
+    ///
+    /// let p = std::env::temp_dir().join("pathutil-ensure-file-doctest");
+    /// let _ = std::fs::remove_file(&p);
+    ///
+    /// assert_eq!(p.pe_ensure_file("v1").unwrap(), EnsureOutcome::Changed);
+    /// assert_eq!(p.pe_ensure_file("v1").unwrap(), EnsureOutcome::Unchanged);
+    /// assert_eq!(p.pe_ensure_file("v2").unwrap(), EnsureOutcome::Changed);
+    ///
+    /// let res = p.parent().unwrap().pe_ensure_file("v1");
+    /// assert!(res.is_err());
+    ///
+    /// let errstr = res.err().unwrap().to_string();
+    /// assert_eq!(errstr, format!("
+    ///
+    /// exists but is not a file
+    /// -with path: {}
+    ///
+    /// ", p.parent().unwrap().display()).trim());
+    /// ```
+    fn pe_ensure_file<C>(&self, contents: C) -> Result<EnsureOutcome>
+    where
+        C: AsRef<[u8]>,
+    {
+        crate::ensure::ensure_file(self.as_ref(), contents.as_ref())
+    }
+
+    /// Ensure this path is a symlink to `target`, creating it or replacing a symlink to another
+    /// target. Anything other than a symlink at this path is an error.
+    fn pe_ensure_symlink<P>(&self, target: P) -> Result<EnsureOutcome>
+    where
+        P: AsRef<Path>,
+    {
+        crate::ensure::ensure_symlink(self.as_ref(), target.as_ref())
+    }
+
+    /// Ensure nothing exists at this path, removing a file, symlink, or whole directory tree.
+    fn pe_ensure_absent(&self) -> Result<EnsureOutcome> {
+        crate::ensure::ensure_absent(self.as_ref())
     }
 
     /// Creates a new hard link on the filesystem.
@@ -457,14 +920,58 @@ pub trait PathExt: AsRef<Path> {
         std::fs::read_to_string(self).annotate_err_into("path", || self.as_ref().display())
     }
 
+    /// Open a reader of the decompressed contents, detecting the [Compression] by magic bytes or
+    /// else the extension; files with neither are read as-is.
+    ///
+    /// Read errors are annotated with the path and the compressed byte offset; see [PathDecoder].
+    fn pe_open_decompressed(&self) -> Result<PathDecoder> {
+        crate::compress::open(self.as_ref())
+    }
+
+    /// Read the entire decompressed contents, as with [PathExt::pe_open_decompressed].
+    fn pe_read_decompressed(&self) -> Result<Vec<u8>> {
+        let mut contents = vec![];
+        self.pe_open_decompressed()?.read_to_end(&mut contents)?;
+        Ok(contents)
+    }
+
+    /// Create a writer which compresses according to the extension, `.gz`, `.zst`, `.xz`, or
+    /// `.bz2`, and writes other paths as-is. Call [PathEncoder::finish] when done.
+    fn pe_create_compressed(&self) -> Result<PathEncoder> {
+        crate::compress::create(self.as_ref())
+    }
+
+    /// Write `contents` compressed according to the extension, as with
+    /// [PathExt::pe_create_compressed].
+    fn pe_write_compressed<C>(&self, contents: C) -> Result<()>
+    where
+        C: AsRef<[u8]>,
+    {
+        let mut encoder = self.pe_create_compressed()?;
+        encoder.write_all(contents.as_ref())?;
+        encoder.finish().map(|_| ())
+    }
+
     /// Removes an empty directory.
     fn pe_remove_dir(&self) -> Result<()> {
         std::fs::remove_dir(self).annotate_err_into("path", || self.as_ref().display())
     }
 
     /// Removes a directory at this path, after removing all its contents. Use carefully!
+    ///
+    /// Errors are annotated by [FilesystemExt] on [OsFs], so testing code with the same method of
+    /// a [FaultFs](crate::FaultFs) reproduces them exactly.
     fn pe_remove_dir_all(&self) -> Result<()> {
-        std::fs::remove_dir_all(self).annotate_err_into("path", || self.as_ref().display())
+        OsFs.pe_remove_dir_all(self)
+    }
+
+    /// Removes a directory at this path, after removing all its contents, reporting each entry
+    /// to `observer` before it is removed. Use carefully!
+    ///
+    /// If the observer is cancelled, removal stops between entries and the rest of the tree is
+    /// left in place.
+    fn pe_remove_dir_all_observed(&self, observer: &mut dyn Observer) -> Result<()> {
+        crate::walk::remove_dir_all_observed(self.as_ref(), observer)
     }
 
     /// Removes a file from the filesystem.
@@ -473,14 +980,51 @@ pub trait PathExt: AsRef<Path> {
     }
 
     /// Rename a file or directory to a new name, replacing the original file if `to` already exists.
+    ///
+    /// Errors are annotated by [FilesystemExt] on [OsFs], so testing code with the same method of
+    /// a [FaultFs](crate::FaultFs) reproduces them exactly.
     fn pe_rename<P>(&self, to: P) -> Result<()>
     where
         P: AsRef<Path>,
     {
-        let topath = to.as_ref();
-        std::fs::rename(self, topath)
-            .annotate_err_into("from", || self.as_ref().display())
-            .annotate_err_into("to", || topath.display())
+        OsFs.pe_rename(self, to)
+    }
+
+    /// Move to `to`, falling back to copy-then-remove when `to` is on a different filesystem.
+    ///
+    /// A [std::fs::rename] is tried first. If it fails because `from` and `to` are on different
+    /// devices, the source tree is copied to `to` preserving permissions, timestamps, and
+    /// symlinks, the copy is verified against the source, and finally the source is removed. As
+    /// with a rename, a directory may be moved onto an existing empty directory.
+    ///
+    /// Errors are annotated with the [MovePhase](crate::MovePhase) which failed. If the failure
+    /// happened after the fallback started, the error also lists the entries which may have been
+    /// left behind at the destination (copy or verify failure) or at the source (remove failure).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pathutil::PathExt;
+    ///
+    /// let p = std::path::Path::new("/this/path/does/not/exist");
+    /// let res = p.pe_move("/tmp/moved");
+    /// assert!(res.is_err());
+    ///
+    /// let errstr = res.err().unwrap().to_string();
+    /// assert_eq!(&errstr, "
+    ///
+    /// No such file or directory (os error 2)
+    /// -with phase: rename
+    /// -with from: /this/path/does/not/exist
+    /// -with to: /tmp/moved
+    ///
+    /// ".trim());
+    /// ```
+    fn pe_move<P>(&self, to: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        crate::pathmove::move_path(self.as_ref(), to.as_ref())
     }
 
     /// Changes the permissions found on a file or a directory.
@@ -491,12 +1035,267 @@ pub trait PathExt: AsRef<Path> {
             .annotate_err_into("permissions", || permdesc)
     }
 
+    /// List the extended attribute names, following symlinks.
+    #[cfg(target_os = "linux")]
+    fn pe_xattr_list(&self) -> Result<Vec<OsString>> {
+        crate::xattrs::list(self.as_ref(), Follow::Deref)
+    }
+
+    /// Get an extended attribute value, following symlinks, or the error explains "no such
+    /// attribute".
+    ///
+    #[cfg_attr(
+        target_os = "linux",
+        doc = indoc! {r#"
+            # Example
+
+            ```
+            use pathutil::PathExt;
+
+            let p = std::path::Path::new("/this/path/does/not/exist");
+            let res = p.pe_xattr_get("user.provenance");
+            assert!(res.is_err());
+
+            let errstr = res.err().unwrap().to_string();
+            assert_eq!(&errstr, "
+
+            No such file or directory (os error 2)
+            -with path: /this/path/does/not/exist
+            -with name: user.provenance
+
+            ".trim());
+            ```
+        "#}
+    )]
+    #[cfg(target_os = "linux")]
+    fn pe_xattr_get<N>(&self, name: N) -> Result<Vec<u8>>
+    where
+        N: AsRef<OsStr>,
+    {
+        crate::xattrs::get(self.as_ref(), name.as_ref(), Follow::Deref)
+    }
+
+    /// Set an extended attribute value, following symlinks.
+    #[cfg(target_os = "linux")]
+    fn pe_xattr_set<N, V>(&self, name: N, value: V) -> Result<()>
+    where
+        N: AsRef<OsStr>,
+        V: AsRef<[u8]>,
+    {
+        crate::xattrs::set(self.as_ref(), name.as_ref(), value.as_ref(), Follow::Deref)
+    }
+
+    /// Remove an extended attribute, following symlinks.
+    #[cfg(target_os = "linux")]
+    fn pe_xattr_remove<N>(&self, name: N) -> Result<()>
+    where
+        N: AsRef<OsStr>,
+    {
+        crate::xattrs::remove(self.as_ref(), name.as_ref(), Follow::Deref)
+    }
+
+    /// List the extended attribute names of a symlink itself rather than its referent.
+    #[cfg(target_os = "linux")]
+    fn pe_lxattr_list(&self) -> Result<Vec<OsString>> {
+        crate::xattrs::list(self.as_ref(), Follow::NoDeref)
+    }
+
+    /// Get an extended attribute value of a symlink itself rather than its referent.
+    #[cfg(target_os = "linux")]
+    fn pe_lxattr_get<N>(&self, name: N) -> Result<Vec<u8>>
+    where
+        N: AsRef<OsStr>,
+    {
+        crate::xattrs::get(self.as_ref(), name.as_ref(), Follow::NoDeref)
+    }
+
+    /// Set an extended attribute value on a symlink itself rather than its referent.
+    #[cfg(target_os = "linux")]
+    fn pe_lxattr_set<N, V>(&self, name: N, value: V) -> Result<()>
+    where
+        N: AsRef<OsStr>,
+        V: AsRef<[u8]>,
+    {
+        crate::xattrs::set(
+            self.as_ref(),
+            name.as_ref(),
+            value.as_ref(),
+            Follow::NoDeref,
+        )
+    }
+
+    /// Remove an extended attribute from a symlink itself rather than its referent.
+    #[cfg(target_os = "linux")]
+    fn pe_lxattr_remove<N>(&self, name: N) -> Result<()>
+    where
+        N: AsRef<OsStr>,
+    {
+        crate::xattrs::remove(self.as_ref(), name.as_ref(), Follow::NoDeref)
+    }
+
+    /// Set the access and modification times, following symlinks.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pathutil::PathExt;
+    /// use std::time::UNIX_EPOCH;
+    ///
+    /// let p = std::path::Path::new("/this/path/does/not/exist");
+    /// let res = p.pe_set_times(UNIX_EPOCH, UNIX_EPOCH);
+    /// assert!(res.is_err());
+    ///
+    /// let errstr = res.err().unwrap().to_string();
+    /// assert_eq!(&errstr, "
+    ///
+    /// No such file or directory (os error 2)
+    /// -with path: /this/path/does/not/exist
+    /// -with atime: 1970-01-01T00:00:00Z
+    /// -with mtime: 1970-01-01T00:00:00Z
+    ///
+    /// ".trim());
+    /// ```
+    fn pe_set_times(&self, atime: SystemTime, mtime: SystemTime) -> Result<()> {
+        let path = self.as_ref();
+        filetime::set_file_times(
+            path,
+            FileTime::from_system_time(atime),
+            FileTime::from_system_time(mtime),
+        )
+        .annotate_err_into("path", || path.display())
+        .annotate_err_into("atime", || Timestamp(atime))
+        .annotate_err_into("mtime", || Timestamp(mtime))
+    }
+
+    /// Set the modification time, following symlinks, and leave the access time unchanged.
+    fn pe_set_mtime(&self, mtime: SystemTime) -> Result<()> {
+        let path = self.as_ref();
+        filetime::set_file_mtime(path, FileTime::from_system_time(mtime))
+            .annotate_err_into("path", || path.display())
+            .annotate_err_into("mtime", || Timestamp(mtime))
+    }
+
+    /// Set the access and modification times of a symlink itself rather than its referent.
+    fn pe_set_symlink_times(&self, atime: SystemTime, mtime: SystemTime) -> Result<()> {
+        let path = self.as_ref();
+        filetime::set_symlink_file_times(
+            path,
+            FileTime::from_system_time(atime),
+            FileTime::from_system_time(mtime),
+        )
+        .annotate_err_into("path", || path.display())
+        .annotate_err_into("atime", || Timestamp(atime))
+        .annotate_err_into("mtime", || Timestamp(mtime))
+    }
+
+    /// Create an empty file if the path does not exist, otherwise set its modification time to
+    /// now.
+    fn pe_touch(&self) -> Result<()> {
+        let path = self.as_ref();
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .annotate_err_into("path", || path.display())?;
+        self.pe_set_mtime(SystemTime::now())
+    }
+
+    /// Open the file in read-only mode as a [PathFile].
+    fn pe_open(&self) -> Result<PathFile> {
+        let path = self.as_ref();
+        std::fs::File::open(path)
+            .annotate_err_into("path", || path.display())
+            .map(|f| PathFile::new(path, f))
+    }
+
+    /// Create or truncate the file in write-only mode as a [PathFile].
+    fn pe_create(&self) -> Result<PathFile> {
+        let path = self.as_ref();
+        std::fs::File::create(path)
+            .annotate_err_into("path", || path.display())
+            .map(|f| PathFile::new(path, f))
+    }
+
+    /// Map the file into memory read-only, or include the path and file length in the error.
+    ///
+    /// Empty files are supported and produce an empty mapping.
+    ///
+    /// # Safety
+    ///
+    /// The mapping aliases the file itself, so the caller must ensure that no process, including
+    /// this one, truncates or writes the file while the [PathMmap] is alive. Truncation makes
+    /// access raise `SIGBUS`, and concurrent writes break the immutability of the `&[u8]`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pathutil::PathExt;
+    ///
+    /// let p = std::path::Path::new("/this/path/does/not/exist");
+    /// let res = unsafe { p.pe_mmap() };
+    /// assert!(res.is_err());
+    ///
+    /// let errstr = res.err().unwrap().to_string();
+    /// assert_eq!(&errstr, "
+    ///
+    /// No such file or directory (os error 2)
+    /// -with path: /this/path/does/not/exist
+    ///
+    /// ".trim());
+    /// ```
+    #[cfg(feature = "mmap")]
+    unsafe fn pe_mmap(&self) -> Result<PathMmap> {
+        crate::mmap::map(self.as_ref())
+    }
+
+    /// Map the file into memory read-write with length `len`, creating the file if necessary
+    /// and truncating or extending it to `len`, or include the path and `len` in the error.
+    ///
+    /// # Safety
+    ///
+    /// As for [PathExt::pe_mmap], the caller must ensure that no other process or mapping
+    /// truncates or modifies the file while the [PathMmapMut] is alive.
+    #[cfg(feature = "mmap")]
+    unsafe fn pe_mmap_mut(&self, len: u64) -> Result<PathMmapMut> {
+        crate::mmap::map_mut(self.as_ref(), len)
+    }
+
+    /// Block until an exclusive advisory lock is held on the file, creating it if necessary.
+    ///
+    /// The returned [PathLock] unlocks when dropped.
+    fn pe_lock_exclusive(&self) -> Result<PathLock> {
+        crate::lock::lock(self.as_ref(), LockMode::Exclusive)
+    }
+
+    /// Block until a shared advisory lock is held on the file, creating it if necessary.
+    ///
+    /// The returned [PathLock] unlocks when dropped.
+    fn pe_lock_shared(&self) -> Result<PathLock> {
+        crate::lock::lock(self.as_ref(), LockMode::Shared)
+    }
+
+    /// Lock the file without blocking, or fail with [std::io::ErrorKind::WouldBlock] if another
+    /// process holds a conflicting lock. The error names its `holder` where that can be found:
+    /// the holder a [LockFile](crate::LockFile) records, or on Linux the pids in `/proc/locks`.
+    fn pe_try_lock(&self, mode: LockMode) -> Result<PathLock> {
+        crate::lock::try_lock(self.as_ref(), mode)
+    }
+
+    /// Lock the file, waiting at most `timeout`, or fail with [std::io::ErrorKind::TimedOut],
+    /// naming the `holder` as for [PathExt::pe_try_lock].
+    fn pe_lock_timeout(&self, mode: LockMode, timeout: Duration) -> Result<PathLock> {
+        crate::lock::lock_timeout(self.as_ref(), mode, timeout)
+    }
+
     /// Write a slice as the entire contents of a file.
+    ///
+    /// Errors are annotated by [FilesystemExt] on [OsFs], so testing code with the same method of
+    /// a [FaultFs](crate::FaultFs) reproduces them exactly.
     fn pe_write<C>(&self, contents: C) -> Result<()>
     where
         C: AsRef<[u8]>,
     {
-        std::fs::write(self, contents).annotate_err_into("path", || self.as_ref().display())
+        OsFs.pe_write(self, contents)
     }
 }
 
@@ -0,0 +1,96 @@
+use crate::PathMetadata;
+use error_annotation::AnnotateResult;
+use std::fs::File;
+use std::io::{Read, Result, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// A [File] with the originating [Path] for improved error messages.
+///
+/// This enables [std::io::Error] results to be annotated with the offending path, including those
+/// from the [Read], [Write], and [Seek] impls.
+#[derive(Debug)]
+pub struct PathFile {
+    path: PathBuf,
+    f: File,
+}
+
+impl PathFile {
+    /// Wrap `f`, which was opened from `path`.
+    pub fn new<P>(path: P, f: File) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        PathFile {
+            path: path.into(),
+            f,
+        }
+    }
+
+    /// Access associated [Path].
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Access associated [File].
+    pub fn file(&self) -> &File {
+        &self.f
+    }
+
+    /// Unwrap the underlying [File].
+    pub fn unwrap(self) -> File {
+        self.f
+    }
+
+    /// Return the [PathMetadata] of the open file, annotating errors with the path.
+    pub fn metadata(&self) -> Result<PathMetadata> {
+        self.f
+            .metadata()
+            .annotate_err_into("path", || self.path.display())
+            .map(|md| PathMetadata::new(self.path(), md))
+    }
+
+    /// Truncate or extend the file to `size` bytes, annotating errors with the path and size.
+    pub fn set_len(&self, size: u64) -> Result<()> {
+        self.f
+            .set_len(size)
+            .annotate_err_into("path", || self.path.display())
+            .annotate_err_into("size", || size)
+    }
+
+    /// Flush data and metadata to the storage device, annotating errors with the path.
+    pub fn sync_all(&self) -> Result<()> {
+        self.f
+            .sync_all()
+            .annotate_err_into("path", || self.path.display())
+    }
+}
+
+impl Read for PathFile {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.f
+            .read(buf)
+            .annotate_err_into("path", || self.path.display())
+    }
+}
+
+impl Write for PathFile {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.f
+            .write(buf)
+            .annotate_err_into("path", || self.path.display())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.f
+            .flush()
+            .annotate_err_into("path", || self.path.display())
+    }
+}
+
+impl Seek for PathFile {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        self.f
+            .seek(pos)
+            .annotate_err_into("path", || self.path.display())
+    }
+}
@@ -0,0 +1,328 @@
+use crate::PathExt;
+use error_annotation::AnnotateResult;
+use std::fmt;
+use std::io::{ErrorKind, Read, Result};
+use std::path::{Path, PathBuf};
+
+/// The phase of [PathExt::pe_move] in which a failure occurred.
+///
+/// This appears in [PathExt::pe_move] errors with the `phase` annotation.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MovePhase {
+    /// The initial [std::fs::rename] attempt.
+    Rename,
+    /// Copying the source tree to the destination after a cross-device rename failure.
+    Copy,
+    /// Checking that the destination tree matches the source tree.
+    Verify,
+    /// Removing the source tree after a verified copy.
+    Remove,
+}
+
+impl fmt::Display for MovePhase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use MovePhase::*;
+
+        let name = match self {
+            Rename => "rename",
+            Copy => "copy",
+            Verify => "verify",
+            Remove => "remove",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A list of paths rendered on a single line for error annotations.
+struct PathList(Vec<PathBuf>);
+
+impl fmt::Display for PathList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str("(none)");
+        }
+        for (i, p) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}", p.display())?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn move_path(from: &Path, to: &Path) -> Result<()> {
+    match std::fs::rename(from, to) {
+        Ok(()) => return Ok(()),
+        Err(e) if e.kind() == ErrorKind::CrossesDevices => {}
+        Err(e) => {
+            return Err(e)
+                .annotate_err_into("phase", || MovePhase::Rename)
+                .annotate_err_into("from", || from.display())
+                .annotate_err_into("to", || to.display());
+        }
+    }
+
+    let mut created = vec![];
+    let copied = copy_tree(from, to, &mut created)
+        .annotate_err_into("phase", || MovePhase::Copy)
+        .and_then(|()| verify_tree(from, to).annotate_err_into("phase", || MovePhase::Verify));
+
+    if let Err(e) = copied {
+        return Err(e)
+            .annotate_err_into("from", || from.display())
+            .annotate_err_into("to", || to.display())
+            .annotate_err_into("left at destination", || PathList(created));
+    }
+
+    remove_tree(from)
+        .annotate_err_into("phase", || MovePhase::Remove)
+        .annotate_err_into("from", || from.display())
+        .annotate_err_into("to", || to.display())
+        .annotate_err_into("left at source", || PathList(remaining(from)))
+}
+
+fn copy_tree(from: &Path, to: &Path, created: &mut Vec<PathBuf>) -> Result<()> {
+    let md = from.pe_symlink_metadata()?;
+    let ftype = md.file_type();
+
+    if ftype.is_symlink() {
+        let target = from.pe_read_link()?;
+        symlink(&target, to)?;
+        created.push(to.to_path_buf());
+        to.pe_set_symlink_times(md.accessed()?, md.modified()?)?;
+    } else if ftype.is_dir() {
+        // A rename replaces an empty directory, so the copy may reuse one:
+        match std::fs::create_dir(to) {
+            Ok(()) => created.push(to.to_path_buf()),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists && is_empty_dir(to) => {}
+            Err(e) => return Err(e).annotate_err_into("path", || to.display()),
+        }
+        for entry in from.pe_read_dir()? {
+            let name = entry?.file_name();
+            copy_tree(&from.join(&name), &to.join(&name), created)?;
+        }
+        std::fs::set_permissions(to, md.permissions())
+            .annotate_err_into("path", || to.display())?;
+        set_times(to, &md)?;
+    } else if ftype.is_file() {
+        // Record the destination before copying so a partially written file is reported:
+        created.push(to.to_path_buf());
+        from.pe_copy(to)?;
+        set_times(to, &md)?;
+    } else {
+        return Err(other_error_fmt!("unsupported file type {:?}", ftype))
+            .annotate_err_into("path", || from.display());
+    }
+
+    Ok(())
+}
+
+fn set_times(path: &Path, md: &crate::PathMetadata) -> Result<()> {
+    path.pe_set_times(md.accessed()?, md.modified()?)
+}
+
+fn is_empty_dir(path: &Path) -> bool {
+    path.symlink_metadata().is_ok_and(|md| md.is_dir())
+        && path.read_dir().is_ok_and(|mut rd| rd.next().is_none())
+}
+
+#[cfg(unix)]
+pub(crate) fn symlink(target: &Path, link: &Path) -> Result<()> {
+    std::os::unix::fs::symlink(target, link)
+        .annotate_err_into("target", || target.display())
+        .annotate_err_into("link", || link.display())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn symlink(target: &Path, link: &Path) -> Result<()> {
+    Err(other_error_fmt!(
+        "symlinks are unsupported on this platform"
+    ))
+    .annotate_err_into("target", || target.display())
+    .annotate_err_into("link", || link.display())
+}
+
+fn verify_tree(from: &Path, to: &Path) -> Result<()> {
+    let srcmd = from.pe_symlink_metadata()?;
+    let dstmd = to.pe_symlink_metadata()?;
+    let (srctype, dsttype) = (srcmd.file_type(), dstmd.file_type());
+
+    // The source is removed after this, so compare contents rather than trusting lengths:
+    let mismatch = if srctype.is_symlink() {
+        !dsttype.is_symlink() || from.pe_read_link()? != to.pe_read_link()?
+    } else if srctype.is_dir() {
+        !dsttype.is_dir() || srcmd.permissions() != dstmd.permissions()
+    } else {
+        !dsttype.is_file()
+            || srcmd.len() != dstmd.len()
+            || srcmd.permissions() != dstmd.permissions()
+            || !same_contents(from, to)?
+    };
+
+    if mismatch {
+        return Err(other_error_fmt!("copy does not match source"))
+            .annotate_err_into("source", || from.display())
+            .annotate_err_into("copy", || to.display());
+    }
+
+    if srctype.is_dir() {
+        for entry in from.pe_read_dir()? {
+            let name = entry?.file_name();
+            verify_tree(&from.join(&name), &to.join(&name))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the files at `a` and `b` have the same contents, compared a chunk at a time.
+pub(crate) fn same_contents(a: &Path, b: &Path) -> Result<bool> {
+    let (mut fa, mut fb) = (a.pe_open()?, b.pe_open()?);
+    let (mut bufa, mut bufb) = (vec![0u8; 64 * 1024], vec![0u8; 64 * 1024]);
+    loop {
+        let n = read_full(&mut fa, &mut bufa)?;
+        let m = read_full(&mut fb, &mut bufb)?;
+        if bufa[..n] != bufb[..m] {
+            return Ok(false);
+        }
+        if n == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Fill `buf` from `r` as far as possible, returning less than its length only at end of file.
+fn read_full<R: Read>(r: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match r.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+pub(crate) fn remove_tree(path: &Path) -> Result<()> {
+    if path.pe_symlink_metadata()?.is_dir() {
+        path.pe_remove_dir_all()
+    } else {
+        path.pe_remove_file()
+    }
+}
+
+/// Best-effort listing of everything still present at `path`.
+fn remaining(path: &Path) -> Vec<PathBuf> {
+    let mut found = vec![];
+    let mut pending = vec![path.to_path_buf()];
+
+    while let Some(p) = pending.pop() {
+        let Ok(md) = p.symlink_metadata() else {
+            continue;
+        };
+        if md.is_dir() {
+            if let Ok(rd) = p.read_dir() {
+                pending.extend(rd.filter_map(|de| de.ok()).map(|de| de.path()));
+            }
+        }
+        found.push(p);
+    }
+
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{copy_tree, verify_tree};
+    use crate::testing::scratch;
+
+    #[test]
+    fn verify_rejects_same_length_corruption() {
+        let dir = scratch("move-verify-contents");
+        std::fs::write(dir.join("src"), "original").unwrap();
+        std::fs::write(dir.join("dst"), "corrupt!").unwrap();
+
+        let err = verify_tree(&dir.join("src"), &dir.join("dst")).unwrap_err();
+        assert!(err.to_string().starts_with("copy does not match source"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn verify_rejects_permission_mismatch() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = scratch("move-verify-perms");
+        std::fs::write(dir.join("src"), "same").unwrap();
+        std::fs::write(dir.join("dst"), "same").unwrap();
+        std::fs::set_permissions(dir.join("src"), PermissionsExt::from_mode(0o600)).unwrap();
+        std::fs::set_permissions(dir.join("dst"), PermissionsExt::from_mode(0o644)).unwrap();
+
+        assert!(verify_tree(&dir.join("src"), &dir.join("dst")).is_err());
+    }
+
+    #[test]
+    fn verify_accepts_identical_tree() {
+        let dir = scratch("move-verify-same");
+        for root in ["src", "dst"] {
+            std::fs::create_dir_all(dir.join(root).join("sub")).unwrap();
+            std::fs::write(dir.join(root).join("sub/f"), vec![7u8; 200_000]).unwrap();
+        }
+
+        verify_tree(&dir.join("src"), &dir.join("dst")).unwrap();
+    }
+
+    #[test]
+    fn copy_reuses_empty_destination_dir() {
+        let dir = scratch("move-copy-empty-dest");
+        std::fs::create_dir_all(dir.join("src/sub")).unwrap();
+        std::fs::write(dir.join("src/sub/f"), "f").unwrap();
+        std::fs::create_dir(dir.join("dst")).unwrap();
+
+        let mut created = vec![];
+        copy_tree(&dir.join("src"), &dir.join("dst"), &mut created).unwrap();
+        verify_tree(&dir.join("src"), &dir.join("dst")).unwrap();
+        // The destination itself was not created by the copy, so is not reported as left over:
+        assert_eq!(created, [dir.join("dst/sub"), dir.join("dst/sub/f")]);
+    }
+
+    #[test]
+    fn copy_rejects_non_empty_destination_dir() {
+        let dir = scratch("move-copy-full-dest");
+        std::fs::create_dir(dir.join("src")).unwrap();
+        std::fs::create_dir(dir.join("dst")).unwrap();
+        std::fs::write(dir.join("dst/keep"), "keep").unwrap();
+
+        let mut created = vec![];
+        let err = copy_tree(&dir.join("src"), &dir.join("dst"), &mut created).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+        assert!(created.is_empty());
+        assert_eq!(
+            std::fs::read_to_string(dir.join("dst/keep")).unwrap(),
+            "keep"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copy_preserves_symlink_times() {
+        use crate::PathExt;
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let dir = scratch("move-copy-symlink-times");
+        std::os::unix::fs::symlink("missing", dir.join("src")).unwrap();
+        let (atime, mtime) = (
+            UNIX_EPOCH + Duration::from_secs(1_000_000),
+            UNIX_EPOCH + Duration::from_secs(2_000_000),
+        );
+        dir.join("src").pe_set_symlink_times(atime, mtime).unwrap();
+
+        copy_tree(&dir.join("src"), &dir.join("dst"), &mut vec![]).unwrap();
+        let md = dir.join("dst").symlink_metadata().unwrap();
+        assert!(md.is_symlink());
+        assert_eq!(md.modified().unwrap(), mtime);
+        assert_eq!(md.accessed().unwrap(), atime);
+    }
+}
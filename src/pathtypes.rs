@@ -0,0 +1,204 @@
+use crate::other_error;
+use derive_more::{Deref, Into};
+use error_annotation::AnnotateResult;
+use std::borrow::Borrow;
+use std::ffi::OsStr;
+use std::fmt;
+use std::io::Result;
+use std::path::{Component, Path, PathBuf};
+
+macro_rules! path_newtype {
+    ( $( #[$meta:meta] )* $name:ident, $check:expr ) => {
+        $( #[$meta] )*
+        #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deref, Into)]
+        pub struct $name(PathBuf);
+
+        impl $name {
+            /// Validate `path`, or the error explains why it is invalid.
+            pub fn new<P>(path: P) -> Result<Self>
+            where
+                P: Into<PathBuf>,
+            {
+                let path = path.into();
+                let check: fn(&Path) -> std::result::Result<(), &'static str> = $check;
+                match check(&path) {
+                    Ok(()) => Ok($name(path)),
+                    Err(desc) => Err(other_error(desc.to_string()))
+                        .annotate_err_into("path", || path.display()),
+                }
+            }
+
+            /// Access the validated [Path].
+            pub fn as_path(&self) -> &Path {
+                &self.0
+            }
+
+            /// Unwrap the underlying [PathBuf].
+            pub fn into_path_buf(self) -> PathBuf {
+                self.0
+            }
+        }
+
+        impl AsRef<Path> for $name {
+            fn as_ref(&self) -> &Path {
+                &self.0
+            }
+        }
+
+        impl AsRef<OsStr> for $name {
+            fn as_ref(&self) -> &OsStr {
+                self.0.as_os_str()
+            }
+        }
+
+        impl Borrow<Path> for $name {
+            fn borrow(&self) -> &Path {
+                &self.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                self.0.display().fmt(f)
+            }
+        }
+
+        impl TryFrom<PathBuf> for $name {
+            type Error = std::io::Error;
+
+            fn try_from(path: PathBuf) -> Result<Self> {
+                $name::new(path)
+            }
+        }
+
+        impl TryFrom<&Path> for $name {
+            type Error = std::io::Error;
+
+            fn try_from(path: &Path) -> Result<Self> {
+                $name::new(path)
+            }
+        }
+
+        impl TryFrom<&str> for $name {
+            type Error = std::io::Error;
+
+            fn try_from(path: &str) -> Result<Self> {
+                $name::new(path)
+            }
+        }
+    };
+}
+
+path_newtype!(
+    /// A [PathBuf] which is checked to be absolute when constructed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pathutil::AbsPathBuf;
+    ///
+    /// let res = AbsPathBuf::new("tmp/foo.txt");
+    /// assert!(res.is_err());
+    ///
+    /// let errstr = res.err().unwrap().to_string();
+    /// assert_eq!(&errstr, "
+    ///
+    /// not an absolute path
+    /// -with path: tmp/foo.txt
+    ///
+    /// ".trim());
+    /// ```
+    AbsPathBuf,
+    |p| p.is_absolute().then_some(()).ok_or("not an absolute path")
+);
+
+path_newtype!(
+    /// A [PathBuf] which is checked to be relative when constructed.
+    RelPathBuf,
+    |p| p.is_relative().then_some(()).ok_or("not a relative path")
+);
+
+path_newtype!(
+    /// A [PathBuf] which is checked to be valid utf8 when constructed, so [Utf8PathBuf::as_str]
+    /// and [Utf8PathBuf::pe_to_str] are infallible.
+    Utf8PathBuf,
+    |p| p.to_str().map(|_| ()).ok_or("invalid utf8")
+);
+
+path_newtype!(
+    /// A [PathBuf] which is checked to be lexically normalized when constructed: it is identical
+    /// to the output of [NormalizedPathBuf::normalize].
+    ///
+    /// [NormalizedPathBuf::normalize] produces one from any path without touching the filesystem.
+    NormalizedPathBuf,
+    |p| is_normalized(p).then_some(()).ok_or("not normalized")
+);
+
+impl AbsPathBuf {
+    /// Join a [RelPathBuf], which always produces another absolute path.
+    pub fn join_rel(&self, rel: &RelPathBuf) -> AbsPathBuf {
+        AbsPathBuf(self.0.join(rel))
+    }
+}
+
+impl Utf8PathBuf {
+    /// Access the path as a `&str`.
+    pub fn as_str(&self) -> &str {
+        self.0.to_str().expect("validated utf8")
+    }
+
+    /// An infallible alternative to [PathExt::pe_to_str](crate::PathExt::pe_to_str) since utf8
+    /// was checked on construction.
+    pub fn pe_to_str(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl NormalizedPathBuf {
+    /// Lexically normalize `path`, removing `.` components and resolving `..` against preceding
+    /// components, without consulting the filesystem, so symlinks are not taken into account.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pathutil::NormalizedPathBuf;
+    /// use std::path::Path;
+    ///
+    /// let n = NormalizedPathBuf::normalize("/usr/./lib/../bin/");
+    /// assert_eq!(n.as_path(), Path::new("/usr/bin"));
+    ///
+    /// let n = NormalizedPathBuf::normalize("../a/../../b");
+    /// assert_eq!(n.as_path(), Path::new("../../b"));
+    /// ```
+    pub fn normalize<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let mut out = PathBuf::new();
+        for c in path.as_ref().components() {
+            match c {
+                Component::CurDir => {}
+                Component::ParentDir => match out.components().next_back() {
+                    Some(Component::Normal(_)) => {
+                        out.pop();
+                    }
+                    Some(Component::RootDir | Component::Prefix(_)) => {}
+                    Some(Component::ParentDir) | Some(Component::CurDir) | None => {
+                        out.push(c);
+                    }
+                },
+                c => out.push(c),
+            }
+        }
+
+        if out.as_os_str().is_empty() {
+            out.push(Component::CurDir);
+        }
+
+        NormalizedPathBuf(out)
+    }
+}
+
+fn is_normalized(path: &Path) -> bool {
+    NormalizedPathBuf::normalize(path).as_os_str() == path.as_os_str()
+}
@@ -0,0 +1,247 @@
+use crate::{MultiPathError, PathExt};
+use error_annotation::AnnotateResult;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fmt;
+use std::path::{Component, Path};
+
+/// The longest file name portable to every [Target], in bytes of utf8.
+const NAME_MAX: usize = 255;
+
+/// Device names which Windows reserves in every directory, with or without an extension.
+const WINDOWS_RESERVED: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Characters which Windows forbids in file names, besides control characters.
+const WINDOWS_FORBIDDEN: &[char] = &['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+/// A platform whose file naming rules [PathExt::pe_check_portable] checks against.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// Reserved device names, the characters `<>:"\|?*` and control characters, trailing dots
+    /// and spaces, and case-insensitive collisions are rejected. Names are limited to 255 UTF-16
+    /// units and paths to 259, the `MAX_PATH` of 260 less its terminator.
+    Windows,
+    /// Names are limited to 255 bytes and paths to 4095. Non-utf8 names are allowed.
+    Posix,
+    /// The character `:` and case-insensitive collisions are rejected. Names are limited to 255
+    /// bytes and paths to 1023.
+    MacOS,
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Target::Windows => "Windows",
+            Target::Posix => "POSIX",
+            Target::MacOS => "macOS",
+        })
+    }
+}
+
+impl Target {
+    fn path_max(self) -> usize {
+        match self {
+            Target::Windows => 259,
+            Target::Posix => 4095,
+            Target::MacOS => 1023,
+        }
+    }
+
+    fn case_insensitive(self) -> bool {
+        self != Target::Posix
+    }
+
+    /// The length of `s` in the units this target limits, UTF-16 units on Windows and else bytes.
+    fn len(self, s: &OsStr) -> usize {
+        match (self, s.to_str()) {
+            (Target::Windows, Some(s)) => s.encode_utf16().count(),
+            _ => s.len(),
+        }
+    }
+}
+
+pub(crate) fn check_portable(path: &Path, target: Target) -> Result<(), MultiPathError> {
+    let mut checker = Checker {
+        target,
+        errors: MultiPathError::new(),
+    };
+
+    for c in path.components() {
+        if let Component::Normal(name) = c {
+            checker.check_name(path, name);
+        }
+    }
+    checker.check_len(path);
+
+    if path.symlink_metadata().is_ok_and(|md| md.is_dir()) {
+        checker.check_dir(path);
+    }
+    checker.errors.into_result()
+}
+
+struct Checker {
+    target: Target,
+    errors: MultiPathError,
+}
+
+impl Checker {
+    fn problem<C>(&mut self, path: &Path, component: &OsStr, rule: C)
+    where
+        C: fmt::Display,
+    {
+        let target = self.target;
+        let err = Err::<(), _>(other_error_fmt!("not portable to {}", target))
+            .annotate_err_into("path", || path.display())
+            .annotate_err_into("component", || component.to_string_lossy())
+            .annotate_err_into("rule", || rule)
+            .unwrap_err();
+        self.errors.push(err);
+    }
+
+    fn check_len(&mut self, path: &Path) {
+        let max = self.target.path_max();
+        if self.target.len(path.as_os_str()) > max {
+            let name = path.file_name().unwrap_or(path.as_os_str());
+            self.problem(path, name, format!("path longer than {}", max));
+        }
+    }
+
+    fn check_name(&mut self, path: &Path, name: &OsStr) {
+        if self.target.len(name) > NAME_MAX {
+            self.problem(path, name, format!("name longer than {}", NAME_MAX));
+        }
+        let Some(s) = name.to_str() else {
+            if self.target != Target::Posix {
+                self.problem(path, name, "invalid utf8");
+            }
+            return;
+        };
+
+        match self.target {
+            Target::Windows => {
+                if is_reserved(s) {
+                    self.problem(path, name, "reserved name");
+                }
+                if let Some(c) = s
+                    .chars()
+                    .find(|c| WINDOWS_FORBIDDEN.contains(c) || c.is_ascii_control())
+                {
+                    self.problem(path, name, format!("forbidden character {:?}", c));
+                }
+                if s.ends_with(['.', ' ']) {
+                    self.problem(path, name, "trailing dot or space");
+                }
+            }
+            Target::MacOS => {
+                if s.contains(':') {
+                    self.problem(path, name, "forbidden character ':'");
+                }
+            }
+            Target::Posix => {}
+        }
+    }
+
+    /// Check every entry beneath `dir`, not following symlinks.
+    fn check_dir(&mut self, dir: &Path) {
+        let entries = match dir.pe_read_dir_entries() {
+            Ok(entries) => entries,
+            Err(e) => return self.errors.push(e),
+        };
+        let mut names: Vec<_> = entries.iter().map(|de| de.file_name()).collect();
+        names.sort();
+
+        let mut folded: HashMap<String, &OsStr> = HashMap::new();
+        for name in &names {
+            let path = dir.join(name);
+            self.check_name(&path, name);
+            self.check_len(&path);
+
+            if self.target.case_insensitive() {
+                let key = name.to_string_lossy().to_lowercase();
+                if let Some(other) = folded.get(&key) {
+                    let rule = format!("collides with {:?} ignoring case", other);
+                    self.problem(&path, name, rule);
+                } else {
+                    folded.insert(key, name);
+                }
+            }
+
+            if path.symlink_metadata().is_ok_and(|md| md.is_dir()) {
+                self.check_dir(&path);
+            }
+        }
+    }
+}
+
+/// Whether Windows treats `name` as a device. It ignores any extension, and spaces before it.
+fn is_reserved(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or_default();
+    let stem = stem.trim_end_matches(' ');
+    WINDOWS_RESERVED
+        .iter()
+        .any(|r| r.eq_ignore_ascii_case(stem))
+}
+
+/// Make `name` a file name which is portable to every [Target].
+///
+/// Forbidden and control characters are replaced with `_`, trailing dots and spaces are removed,
+/// reserved names such as `CON` gain a leading `_`, and the result is cut to 255 bytes. A name
+/// left empty, or which is `.` or `..`, becomes `_`.
+///
+/// # Example
+///
+/// ```
+/// use pathutil::sanitize_file_name;
+///
+/// assert_eq!(sanitize_file_name("report: draft?.txt"), "report_ draft_.txt");
+/// assert_eq!(sanitize_file_name("nul.tar.gz"), "_nul.tar.gz");
+/// assert_eq!(sanitize_file_name("nul "), "_nul");
+/// assert_eq!(sanitize_file_name("CON .txt"), "_CON .txt");
+/// assert_eq!(sanitize_file_name("notes. "), "notes");
+/// assert_eq!(sanitize_file_name(".."), "_");
+/// ```
+pub fn sanitize_file_name(name: &str) -> String {
+    let mut s: String = name
+        .chars()
+        .map(|c| match WINDOWS_FORBIDDEN.contains(&c) || c.is_control() {
+            true => '_',
+            false => c,
+        })
+        .collect();
+
+    // Windows strips these itself, so `nul ` would still name the device:
+    s.truncate(s.trim_end_matches(['.', ' ']).len());
+    if is_reserved(&s) {
+        s.insert(0, '_');
+    }
+
+    let mut end = s.len().min(NAME_MAX);
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    s.truncate(end);
+    s.truncate(s.trim_end_matches(['.', ' ']).len());
+
+    match s.as_str() {
+        "" | "." | ".." => "_".to_string(),
+        _ => s,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_portable, Target};
+    use std::path::Path;
+
+    #[test]
+    fn reserved_names_ignore_spaces_before_extension() {
+        for name in ["CON .txt", "nul", "Aux  .tar.gz"] {
+            let err = check_portable(Path::new(name), Target::Windows).unwrap_err();
+            assert!(err.to_string().contains("reserved name"), "{}", err);
+        }
+        assert!(check_portable(Path::new("CONSOLE.txt"), Target::Windows).is_ok());
+    }
+}
@@ -0,0 +1,193 @@
+use std::fmt;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// The message of the error returned by an operation stopped through its [CancelToken].
+const CANCELLED: &str = "cancelled";
+
+/// The payload of the error returned by an operation stopped through its [CancelToken].
+#[derive(Debug)]
+struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(CANCELLED)
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// An event reported to an [Observer] by a long-running operation.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ProgressEvent<'a> {
+    /// The operation reached an entry, such as a file to copy or hash or a path to remove.
+    Entry(&'a Path),
+    /// `done` of the `total` bytes of the entry at `path` have been processed.
+    Bytes {
+        path: &'a Path,
+        done: u64,
+        total: u64,
+    },
+}
+
+/// Receives [ProgressEvent]s from the `…_observed` operations of [PathExt](crate::PathExt), and
+/// from [CopyOptions::observer](crate::CopyOptions::observer).
+///
+/// Closures taking a [ProgressEvent] are observers, and so is a [CancelToken] which reports no
+/// progress. An operation checks the [Observer::cancel_token] before each entry, and before each
+/// chunk of data, and stops with an error for which [is_cancelled] holds once it is cancelled.
+///
+/// # Example
+///
+/// ```
+/// use pathutil::{is_cancelled, CancelToken, PathExt};
+///
+/// let mut token = CancelToken::new();
+/// token.cancel();
+///
+/// let p = std::path::Path::new("/this/path/does/not/exist");
+/// let res: std::io::Result<Vec<_>> = p.pe_walk_observed(&mut token).collect();
+/// let err = res.err().unwrap();
+/// assert!(is_cancelled(&err));
+///
+/// let errstr = err.to_string();
+/// assert_eq!(&errstr, "
+///
+/// cancelled
+/// -with root: /this/path/does/not/exist
+///
+/// ".trim());
+/// ```
+pub trait Observer {
+    fn event(&mut self, event: ProgressEvent<'_>);
+
+    /// The token which cancels the observed operation, if any.
+    fn cancel_token(&self) -> Option<&CancelToken> {
+        None
+    }
+}
+
+impl<F> Observer for F
+where
+    F: FnMut(ProgressEvent<'_>),
+{
+    fn event(&mut self, event: ProgressEvent<'_>) {
+        self(event)
+    }
+}
+
+impl Observer for CancelToken {
+    fn event(&mut self, _event: ProgressEvent<'_>) {}
+
+    fn cancel_token(&self) -> Option<&CancelToken> {
+        Some(self)
+    }
+}
+
+/// An [Observer] which ignores all events, used by the unobserved operations.
+pub(crate) struct Quiet;
+
+impl Observer for Quiet {
+    fn event(&mut self, _event: ProgressEvent<'_>) {}
+}
+
+/// A flag shared between an observed operation and the code which may cancel it, such as a
+/// Ctrl-C handler on another thread. Clones share the same flag.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+
+    /// Ask operations observing this token to stop at their next check.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Whether `err` is the error of an operation stopped through its [CancelToken], regardless of
+/// the annotations added to it.
+///
+/// Cancelled operations fail with [ErrorKind::Interrupted], which the OS only reports for
+/// interrupted system calls, and which are retried rather than returned by this crate.
+pub fn is_cancelled(err: &Error) -> bool {
+    match err.get_ref() {
+        Some(payload) if payload.is::<Cancelled>() => true,
+        // Annotating rebuilds the error from its kind and message, dropping the payload:
+        _ => {
+            err.kind() == ErrorKind::Interrupted
+                && err.to_string().lines().next() == Some(CANCELLED)
+        }
+    }
+}
+
+/// Fail with the cancellation error if the token of `observer` has been cancelled.
+pub(crate) fn check_cancelled(observer: &dyn Observer) -> Result<()> {
+    match observer
+        .cancel_token()
+        .is_some_and(CancelToken::is_cancelled)
+    {
+        true => Err(Error::new(ErrorKind::Interrupted, Cancelled)),
+        false => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_cancelled, is_cancelled, CancelToken};
+    use crate::testing::scratch;
+    use crate::{PathExt, RetryPolicy};
+    use error_annotation::AnnotateResult;
+    use std::io::{Error, ErrorKind};
+
+    #[test]
+    fn cancellation_survives_annotation() {
+        let token = CancelToken::new();
+        assert!(check_cancelled(&token).is_ok());
+        token.cancel();
+
+        let err = check_cancelled(&token).unwrap_err();
+        assert!(is_cancelled(&err));
+        let annotated = Err::<(), _>(err)
+            .annotate_err_into("path", || "/some/path")
+            .unwrap_err();
+        assert!(is_cancelled(&annotated));
+        assert!(!RetryPolicy::default().is_retryable(&annotated));
+    }
+
+    #[test]
+    fn other_errors_named_cancelled_are_not_cancellations() {
+        assert!(!is_cancelled(&Error::other("cancelled")));
+        assert!(!is_cancelled(&Error::from(ErrorKind::Interrupted)));
+        assert!(!is_cancelled(&Error::new(
+            ErrorKind::Interrupted,
+            "cancelled by peer"
+        )));
+    }
+
+    #[test]
+    fn cancelled_removal_stops_before_removing() {
+        let dir = scratch("progress-remove-cancel");
+        std::fs::create_dir(dir.join("tree")).unwrap();
+        for name in ["a", "b", "c"] {
+            std::fs::write(dir.join("tree").join(name), name).unwrap();
+        }
+
+        let mut token = CancelToken::new();
+        token.cancel();
+        let err = dir
+            .join("tree")
+            .pe_remove_dir_all_observed(&mut token)
+            .unwrap_err();
+        assert!(is_cancelled(&err));
+        assert_eq!(dir.join("tree").read_dir().unwrap().count(), 3);
+    }
+}
@@ -0,0 +1,114 @@
+use error_annotation::AnnotateResult;
+use std::io::{Error, ErrorKind, Result};
+use std::time::Duration;
+
+/// When and how often [RetryPolicy::run] retries an operation which failed with a transient
+/// error, as on NFS or busy machines.
+///
+/// The delay before each retry starts at `initial_delay` and is multiplied by `multiplier` after
+/// each attempt, up to `max_delay`. The default makes up to 5 attempts starting at 10ms and
+/// retries [ErrorKind::Interrupted] (`EINTR`), [ErrorKind::WouldBlock] (`EAGAIN`),
+/// [ErrorKind::ResourceBusy] (`EBUSY`), and [ErrorKind::ExecutableFileBusy] (`ETXTBSY`).
+///
+/// # Example
+///
+/// ```
+/// use pathutil::RetryPolicy;
+/// use std::io::{Error, ErrorKind};
+/// use std::time::Duration;
+///
+/// let policy = RetryPolicy {
+///     max_attempts: 3,
+///     initial_delay: Duration::ZERO,
+///     ..RetryPolicy::default()
+/// };
+///
+/// let mut calls = 0;
+/// let res: std::io::Result<()> = policy.run(|| {
+///     calls += 1;
+///     Err(Error::from(ErrorKind::ResourceBusy))
+/// });
+/// assert_eq!(calls, 3);
+///
+/// let errstr = res.err().unwrap().to_string();
+/// assert_eq!(&errstr, "
+///
+/// resource busy
+/// -with attempts: 3
+/// -with earlier error: resource busy
+/// -with earlier error: resource busy
+///
+/// ".trim());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The most attempts to make, including the first; at least one is always made.
+    pub max_attempts: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: u32,
+    /// The error kinds which are retried; any other error is returned at once.
+    pub retryable: Vec<ErrorKind>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            initial_delay: Duration::from_millis(10),
+            max_delay: Duration::from_secs(1),
+            multiplier: 2,
+            retryable: vec![
+                ErrorKind::Interrupted,
+                ErrorKind::WouldBlock,
+                ErrorKind::ResourceBusy,
+                ErrorKind::ExecutableFileBusy,
+            ],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Whether this policy retries `err`. Cancellations are never retried, though they are
+    /// [ErrorKind::Interrupted] errors.
+    pub fn is_retryable(&self, err: &Error) -> bool {
+        self.retryable.contains(&err.kind()) && !crate::is_cancelled(err)
+    }
+
+    /// Call `f` until it succeeds, fails with an error which is not retryable, or runs out of
+    /// attempts, sleeping between attempts. This wraps any [PathExt](crate::PathExt) operation,
+    /// as in `policy.run(|| path.pe_rename(&dest))`.
+    ///
+    /// The final error is annotated with the number of `attempts` made and the first line of
+    /// each `earlier error`, since the earlier errors usually share the final one's annotations.
+    pub fn run<T, F>(&self, mut f: F) -> Result<T>
+    where
+        F: FnMut() -> Result<T>,
+    {
+        let mut earlier = vec![];
+        let mut delay = self.initial_delay;
+        loop {
+            let err = match f() {
+                Ok(v) => return Ok(v),
+                Err(e) => e,
+            };
+            let attempts = earlier.len() as u32 + 1;
+            if attempts >= self.max_attempts || !self.is_retryable(&err) {
+                let mut res = Err(err).annotate_err_into("attempts", || attempts);
+                for e in &earlier {
+                    res = res.annotate_err_into("earlier error", || first_line(e));
+                }
+                return res;
+            }
+
+            earlier.push(err);
+            std::thread::sleep(delay);
+            delay = delay.saturating_mul(self.multiplier).min(self.max_delay);
+        }
+    }
+}
+
+fn first_line(err: &Error) -> String {
+    let msg = err.to_string();
+    msg.lines().next().unwrap_or_default().to_string()
+}
@@ -0,0 +1,447 @@
+use crate::pathmove::{remove_tree, symlink};
+use crate::progress::{check_cancelled, is_cancelled};
+use crate::tempname::{create_new_file, create_temp};
+use crate::{
+    CancelToken, CopyOptions, FileTypeEnum, Filesystem, FsMetadata, Observer, OsFs, PathExt,
+    ProgressEvent,
+};
+use error_annotation::AnnotateResult;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fmt;
+use std::io::{ErrorKind, Result};
+use std::path::{Path, PathBuf};
+
+/// How [PathExt::pe_sync_to] decides whether a file which exists at both ends has changed.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum SyncCompare {
+    /// Changed if the size or modification time differ, which is fast but trusts mtimes.
+    #[default]
+    SizeMtime,
+    /// Changed if the size or sha256 digest of the contents differ.
+    Hash,
+}
+
+/// Options for [PathExt::pe_sync_to].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SyncOptions {
+    pub compare: SyncCompare,
+    /// Remove destination entries which are absent from the source.
+    pub delete: bool,
+    /// Plan and report the actions without performing them.
+    pub dry_run: bool,
+}
+
+/// An action taken, or planned in a dry run, by [PathExt::pe_sync_to], naming the destination
+/// path it applies to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SyncAction {
+    CreateDir(PathBuf),
+    Copy(PathBuf),
+    Symlink(PathBuf),
+    /// Set the permissions and modification time of an otherwise unchanged entry.
+    UpdateAttrs(PathBuf),
+    Remove(PathBuf),
+}
+
+impl fmt::Display for SyncAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use SyncAction::*;
+
+        let (verb, path) = match self {
+            CreateDir(p) => ("create dir", p),
+            Copy(p) => ("copy", p),
+            Symlink(p) => ("symlink", p),
+            UpdateAttrs(p) => ("update attrs", p),
+            Remove(p) => ("remove", p),
+        };
+        write!(f, "{} {}", verb, path.display())
+    }
+}
+
+/// The outcome of [PathExt::pe_sync_to]: the actions in the order they were taken or planned,
+/// and the errors of entries which could not be synced.
+///
+/// Each error is annotated with the `source` and `destination` of the entry which failed. An
+/// action is listed even when performing it failed.
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    pub actions: Vec<SyncAction>,
+    pub errors: Vec<std::io::Error>,
+}
+
+impl SyncReport {
+    /// Whether every entry was synced without error.
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+pub(crate) fn sync(
+    src: &Path,
+    dst: &Path,
+    options: SyncOptions,
+    observer: &mut dyn Observer,
+) -> Result<SyncReport> {
+    let srcmd = src.pe_metadata()?;
+    if !srcmd.is_dir() {
+        return Err(other_error_fmt!("not a directory"))
+            .annotate_err_into("path", || src.display());
+    }
+
+    let mut syncer = Syncer {
+        options,
+        report: SyncReport::default(),
+        observer,
+    };
+
+    let dst_exists = match dst.symlink_metadata() {
+        Ok(md) if md.is_dir() => true,
+        Ok(_) => {
+            return Err(other_error_fmt!("not a directory"))
+                .annotate_err_into("path", || dst.display());
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            syncer.act(SyncAction::CreateDir(dst.to_path_buf()), || {
+                dst.pe_create_dir_all()
+            })?;
+            false
+        }
+        Err(e) => return Err(e).annotate_err_into("path", || dst.display()),
+    };
+
+    syncer.sync_dir(src, dst, dst_exists)?;
+    Ok(syncer.report)
+}
+
+struct Syncer<'a> {
+    options: SyncOptions,
+    report: SyncReport,
+    observer: &'a mut dyn Observer,
+}
+
+impl Syncer<'_> {
+    /// Record `action`, then perform it with `f` unless this is a dry run.
+    fn act<F>(&mut self, action: SyncAction, f: F) -> Result<()>
+    where
+        F: FnOnce() -> Result<()>,
+    {
+        self.report.actions.push(action);
+        match self.options.dry_run {
+            true => Ok(()),
+            false => f(),
+        }
+    }
+
+    fn error(&mut self, e: std::io::Error, src: Option<&Path>, dst: &Path) {
+        self.report.errors.push(annotate(e, src, dst));
+    }
+
+    /// Report reaching an entry, and fail if the observer has been cancelled.
+    fn checkpoint(&mut self, src: Option<&Path>, dst: &Path) -> Result<()> {
+        self.observer
+            .event(ProgressEvent::Entry(src.unwrap_or(dst)));
+        check_cancelled(self.observer).map_err(|e| annotate(e, src, dst))
+    }
+
+    /// Sync the entries of `src` into `dst`, collecting their errors in the report. Only a
+    /// cancellation is returned as an error.
+    fn sync_dir(&mut self, src: &Path, dst: &Path, dst_exists: bool) -> Result<()> {
+        let srcnames = match read_names(src) {
+            Ok(names) => names,
+            Err(e) => {
+                self.error(e, Some(src), dst);
+                return Ok(());
+            }
+        };
+        let mut dstnames = match dst_exists {
+            true => match read_names(dst) {
+                Ok(names) => names,
+                Err(e) => {
+                    self.error(e, Some(src), dst);
+                    return Ok(());
+                }
+            },
+            false => BTreeMap::new(),
+        };
+
+        for (name, srcmd) in srcnames {
+            let (s, d) = (src.join(&name), dst.join(&name));
+            self.checkpoint(Some(&s), &d)?;
+            // An entry which could not be inspected at either end is skipped rather than guessed:
+            let res = srcmd.and_then(|srcmd| {
+                let dstmd = dstnames.remove(&name).transpose()?;
+                self.sync_entry(&s, &d, srcmd, dstmd)
+            });
+            match res {
+                Err(e) if is_cancelled(&e) => return Err(e),
+                Err(e) => self.error(e, Some(&s), &d),
+                Ok(()) => {}
+            }
+        }
+
+        if self.options.delete {
+            for (name, dstmd) in dstnames {
+                let d = dst.join(name);
+                self.checkpoint(None, &d)?;
+                if let Err(e) = dstmd {
+                    self.error(e, None, &d);
+                    continue;
+                }
+                if let Err(e) = self.act(SyncAction::Remove(d.clone()), || remove_tree(&d)) {
+                    self.error(e, None, &d);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn sync_entry(
+        &mut self,
+        s: &Path,
+        d: &Path,
+        srcmd: FsMetadata,
+        mut dstmd: Option<FsMetadata>,
+    ) -> Result<()> {
+        if dstmd
+            .as_ref()
+            .is_some_and(|dmd| dmd.file_type != srcmd.file_type)
+        {
+            self.act(SyncAction::Remove(d.to_path_buf()), || remove_tree(d))?;
+            dstmd = None;
+        }
+
+        match srcmd.file_type {
+            FileTypeEnum::Dir => {
+                let stale = dstmd.as_ref().is_some_and(|dmd| attrs_differ(&srcmd, dmd));
+                match dstmd {
+                    None => {
+                        self.act(SyncAction::CreateDir(d.to_path_buf()), || d.pe_create_dir())?
+                    }
+                    Some(_) if stale => {
+                        self.report
+                            .actions
+                            .push(SyncAction::UpdateAttrs(d.to_path_buf()));
+                    }
+                    Some(_) => {}
+                }
+                self.sync_dir(s, d, dstmd.is_some())?;
+
+                // Syncing the contents changes the directory mtime, so attrs are always restored:
+                if !self.options.dry_run {
+                    set_attrs(d, &srcmd)?;
+                }
+            }
+            FileTypeEnum::File => {
+                let changed = match &dstmd {
+                    None => true,
+                    Some(dmd) => self.contents_differ(s, d, &srcmd, dmd)?,
+                };
+                if changed {
+                    self.report.actions.push(SyncAction::Copy(d.to_path_buf()));
+                    if !self.options.dry_run {
+                        copy_file(s, d, &srcmd, &mut BytesOnly(&mut *self.observer))?;
+                    }
+                } else if dstmd.is_some_and(|dmd| attrs_differ(&srcmd, &dmd)) {
+                    self.act(SyncAction::UpdateAttrs(d.to_path_buf()), || {
+                        set_attrs(d, &srcmd)
+                    })?;
+                }
+            }
+            FileTypeEnum::Symlink => {
+                let target = s.pe_read_link()?;
+                if dstmd.is_some() {
+                    if d.pe_read_link()? == target {
+                        return Ok(());
+                    }
+                    self.act(SyncAction::Remove(d.to_path_buf()), || d.pe_remove_file())?;
+                }
+                self.act(SyncAction::Symlink(d.to_path_buf()), || {
+                    symlink(&target, d)?;
+                    d.pe_set_symlink_times(srcmd.modified, srcmd.modified)
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    fn contents_differ(
+        &self,
+        s: &Path,
+        d: &Path,
+        srcmd: &FsMetadata,
+        dstmd: &FsMetadata,
+    ) -> Result<bool> {
+        if srcmd.len != dstmd.len {
+            return Ok(true);
+        }
+        match self.options.compare {
+            SyncCompare::SizeMtime => Ok(srcmd.modified != dstmd.modified),
+            SyncCompare::Hash => Ok(crate::hash::sha256_file(s)? != crate::hash::sha256_file(d)?),
+        }
+    }
+}
+
+fn annotate(e: std::io::Error, src: Option<&Path>, dst: &Path) -> std::io::Error {
+    Err::<(), _>(e)
+        .annotate_err_into("source", || match src {
+            Some(src) => src.display().to_string(),
+            None => "(none)".to_string(),
+        })
+        .annotate_err_into("destination", || dst.display())
+        .unwrap_err()
+}
+
+/// Forwards only the byte counts of a file copy, since the sync already reported the entry.
+struct BytesOnly<'a>(&'a mut dyn Observer);
+
+impl Observer for BytesOnly<'_> {
+    fn event(&mut self, event: ProgressEvent<'_>) {
+        if let ProgressEvent::Bytes { .. } = event {
+            self.0.event(event);
+        }
+    }
+
+    fn cancel_token(&self) -> Option<&CancelToken> {
+        self.0.cancel_token()
+    }
+}
+
+/// Read the entries of `dir` with their metadata, not following symlinks. An entry whose
+/// metadata cannot be read, or whose type is unsupported such as a fifo, maps to its error.
+fn read_names(dir: &Path) -> Result<BTreeMap<OsString, Result<FsMetadata>>> {
+    let mut names = BTreeMap::new();
+    for res in dir.pe_read_dir()? {
+        let de = res?;
+        let path = de.path();
+        let md = OsFs
+            .symlink_metadata(&path)
+            .annotate_err_into("path", || path.display());
+        names.insert(de.file_name(), md);
+    }
+    Ok(names)
+}
+
+fn attrs_differ(srcmd: &FsMetadata, dstmd: &FsMetadata) -> bool {
+    srcmd.mode != dstmd.mode || srcmd.modified != dstmd.modified
+}
+
+fn set_attrs(path: &Path, md: &FsMetadata) -> Result<()> {
+    OsFs.set_mode(path, md.mode)
+        .annotate_err_into("path", || path.display())?;
+    path.pe_set_mtime(md.modified)
+}
+
+/// Copy via a temporary file renamed into place, so the destination is never partially written.
+///
+/// The temporary file is created exclusively under a fresh name, so an existing entry is never
+/// overwritten, written through if it is a symlink, or removed on failure.
+fn copy_file(s: &Path, d: &Path, srcmd: &FsMetadata, observer: &mut dyn Observer) -> Result<()> {
+    let (tmp, file) = create_temp(d, "sync-tmp", create_new_file)?;
+
+    let options = CopyOptions {
+        observer: Some(observer),
+        ..CopyOptions::default()
+    };
+    let res = crate::copy::copy_to_file(s, &tmp, &file, options)
+        .and_then(|_| tmp.pe_set_mtime(srcmd.modified))
+        .and_then(|()| tmp.pe_rename(d));
+    if res.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    res
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use crate::tempname::plan_temp_paths;
+    use crate::testing::scratch;
+    use crate::{PathExt, SyncOptions};
+    use std::path::Path;
+
+    fn mkfifo(path: &Path) {
+        use std::os::unix::ffi::OsStrExt;
+
+        let cpath = std::ffi::CString::new(path.as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(cpath.as_ptr(), 0o644) }, 0);
+    }
+
+    #[test]
+    fn fifo_in_source_is_an_entry_error() {
+        let dir = scratch("sync-fifo");
+        let (src, dst) = (dir.join("src"), dir.join("dst"));
+        std::fs::create_dir(&src).unwrap();
+        std::fs::write(src.join("a"), "regular").unwrap();
+        mkfifo(&src.join("pipe"));
+
+        let report = src.pe_sync_to(&dst, SyncOptions::default()).unwrap();
+        assert_eq!(std::fs::read_to_string(dst.join("a")).unwrap(), "regular");
+        assert!(!dst.join("pipe").exists());
+
+        assert_eq!(report.errors.len(), 1);
+        let errstr = report.errors[0].to_string();
+        assert!(errstr.starts_with("unsupported file type"), "{}", errstr);
+        assert!(errstr.contains("pipe"), "{}", errstr);
+    }
+
+    #[test]
+    fn fifo_in_destination_is_left_alone() {
+        let dir = scratch("sync-fifo-dst");
+        let (src, dst) = (dir.join("src"), dir.join("dst"));
+        std::fs::create_dir(&src).unwrap();
+        std::fs::create_dir(&dst).unwrap();
+        std::fs::write(src.join("pipe"), "regular").unwrap();
+        mkfifo(&dst.join("pipe"));
+        mkfifo(&dst.join("extra"));
+
+        let options = SyncOptions {
+            delete: true,
+            ..SyncOptions::default()
+        };
+        let report = src.pe_sync_to(&dst, options).unwrap();
+        assert_eq!(report.errors.len(), 2);
+        assert!(report.actions.is_empty(), "{:?}", report.actions);
+    }
+
+    #[test]
+    fn existing_temp_names_are_untouched() {
+        let dir = scratch("sync-temp-collision");
+        let (src, dst) = (dir.join("src"), dir.join("dst"));
+        std::fs::create_dir(&src).unwrap();
+        std::fs::create_dir(&dst).unwrap();
+        std::fs::write(src.join("a"), "new a").unwrap();
+        std::fs::write(src.join("b"), "new b").unwrap();
+
+        // `a` first collides with a file, then `b` with a symlink out of the destination:
+        let atmp = plan_temp_paths(&dst.join("a"), "sync-tmp", &[1, 2]);
+        let btmp = plan_temp_paths(&dst.join("b"), "sync-tmp", &[3]);
+        std::fs::write(&atmp[0], "not ours").unwrap();
+        std::fs::write(dir.join("outside"), "outside").unwrap();
+        std::os::unix::fs::symlink(dir.join("outside"), &btmp[0]).unwrap();
+
+        let report = src.pe_sync_to(&dst, SyncOptions::default()).unwrap();
+        assert!(report.is_ok(), "{:?}", report.errors);
+
+        assert_eq!(std::fs::read_to_string(dst.join("a")).unwrap(), "new a");
+        assert_eq!(std::fs::read_to_string(dst.join("b")).unwrap(), "new b");
+        assert_eq!(std::fs::read_to_string(&atmp[0]).unwrap(), "not ours");
+        assert!(btmp[0].symlink_metadata().unwrap().is_symlink());
+        assert_eq!(
+            std::fs::read_to_string(dir.join("outside")).unwrap(),
+            "outside"
+        );
+        assert_eq!(dst.read_dir().unwrap().count(), 4);
+    }
+
+    #[test]
+    fn unreadable_destination_is_not_created() {
+        let dir = scratch("sync-dst-eloop");
+        std::fs::create_dir(dir.join("src")).unwrap();
+        std::os::unix::fs::symlink("loop", dir.join("loop")).unwrap();
+
+        let dst = dir.join("loop/dst");
+        let err = dir.join("src").pe_sync_to(&dst, SyncOptions::default());
+        assert!(err.is_err());
+        assert!(dir.join("loop").symlink_metadata().unwrap().is_symlink());
+    }
+}
@@ -0,0 +1,260 @@
+use crate::archive::{self, ArchiveOptions, DeferredDirs};
+use crate::pathmove::symlink;
+use crate::{PathExt, PathJail};
+use error_annotation::AnnotateResult;
+use std::io::Result;
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+use tar::{Archive, Builder, EntryType, Header, HeaderMode};
+
+pub(crate) fn create(root: &Path, dest: &Path, options: ArchiveOptions) -> Result<()> {
+    let mut builder = Builder::new(dest.pe_create()?);
+    builder.follow_symlinks(false);
+
+    for res in archive::members(root) {
+        let (name, md) = res?;
+        append(&mut builder, &name, &md, options)
+            .annotate_err_into("entry", || &name)
+            .annotate_err_into("archive", || dest.display())?;
+    }
+
+    builder
+        .into_inner()
+        .and_then(|mut pf| std::io::Write::flush(&mut pf))
+        .annotate_err_into("archive", || dest.display())
+}
+
+fn append(
+    builder: &mut Builder<crate::PathFile>,
+    name: &str,
+    md: &crate::PathMetadata,
+    options: ArchiveOptions,
+) -> Result<()> {
+    let mut header = Header::new_gnu();
+    header.set_metadata_in_mode(md.metadata(), HeaderMode::Complete);
+    header.set_mode(archive::mode(md, options));
+    if !options.keep_mtime {
+        header.set_mtime(0);
+    }
+
+    let path = md.path();
+    if md.is_symlink() {
+        header.set_size(0);
+        builder.append_link(&mut header, name, path.pe_read_link()?)
+    } else if md.is_dir() {
+        header.set_size(0);
+        builder.append_data(&mut header, name, std::io::empty())
+    } else if md.is_file() {
+        builder.append_data(&mut header, name, path.pe_open()?)
+    } else {
+        Err(other_error_fmt!("unsupported file type"))
+    }
+}
+
+pub(crate) fn extract(archive_path: &Path, jail: &PathJail, options: ArchiveOptions) -> Result<()> {
+    let mut ar = Archive::new(archive_path.pe_open()?);
+
+    let entries = ar
+        .entries()
+        .annotate_err_into("archive", || archive_path.display())?;
+
+    let mut dirs = DeferredDirs::default();
+    for res in entries {
+        let mut entry = res.annotate_err_into("archive", || archive_path.display())?;
+        let name = entry
+            .path()
+            .map(|p| p.into_owned())
+            .annotate_err_into("archive", || archive_path.display())?;
+
+        extract_entry(&mut entry, &name, jail, options, &mut dirs)
+            .annotate_err_into("entry", || name.display())
+            .annotate_err_into("archive", || archive_path.display())?;
+    }
+
+    dirs.apply()
+        .annotate_err_into("archive", || archive_path.display())
+}
+
+fn extract_entry<R>(
+    entry: &mut tar::Entry<R>,
+    name: &Path,
+    jail: &PathJail,
+    options: ArchiveOptions,
+    dirs: &mut DeferredDirs,
+) -> Result<()>
+where
+    R: std::io::Read,
+{
+    let header = entry.header();
+    let mode = match options.keep_mode {
+        true => Some(header.mode()? & 0o7777),
+        false => None,
+    };
+    let mtime = match options.keep_mtime {
+        true => Some(UNIX_EPOCH + Duration::from_secs(header.mtime()?)),
+        false => None,
+    };
+
+    if header.entry_type() == EntryType::Directory {
+        if let Some(dest) = archive::dir_destination(jail, name)? {
+            dest.pe_create_dir_all()?;
+            dirs.push(dest, mode, mtime);
+        }
+        return Ok(());
+    }
+
+    let dest = archive::destination(jail, name)?;
+    let link_name = entry.link_name()?.map(|l| l.into_owned());
+
+    match (entry.header().entry_type(), link_name) {
+        (EntryType::Regular | EntryType::Continuous | EntryType::GNUSparse, _) => {
+            archive::write_file(&dest, entry, mode, mtime)
+        }
+        (EntryType::Symlink, Some(target)) => {
+            archive::check_symlink(jail, name, &target)?;
+            archive::create_parent(&dest)?;
+            symlink(&target, &dest)
+        }
+        (EntryType::Link, Some(target)) => {
+            let original = archive::destination(jail, &target)
+                .annotate_err_into("target", || target.display())?;
+            archive::create_parent(&dest)?;
+            original.pe_hard_link(&dest)
+        }
+        (EntryType::XGlobalHeader | EntryType::XHeader, _) => Ok(()),
+        (entry_type, _) => Err(other_error_fmt!("unsupported entry type {:?}", entry_type)),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use crate::testing::{assert_extract_rejected, scratch};
+    use crate::PathExt;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+    use std::time::{Duration, UNIX_EPOCH};
+    use tar::{Builder, EntryType, Header};
+
+    /// Write a tar of `(name, mode, type, data)` entries, where `data` is the contents of a file
+    /// or the target of a link, keeping names verbatim so they can start with `./`, `..`, or `/`.
+    fn write_tar(path: &Path, entries: &[(&str, u32, EntryType, &str)]) {
+        let mut builder = Builder::new(std::fs::File::create(path).unwrap());
+        for &(name, mode, entry_type, data) in entries {
+            let mut header = Header::new_ustar();
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_mode(mode);
+            header.set_mtime(1_000_000_000);
+            header.set_entry_type(entry_type);
+            let contents = match entry_type {
+                EntryType::Regular => data,
+                _ => {
+                    header.as_old_mut().linkname[..data.len()].copy_from_slice(data.as_bytes());
+                    ""
+                }
+            };
+            header.set_size(contents.len() as u64);
+            header.set_cksum();
+            builder.append(&header, contents.as_bytes()).unwrap();
+        }
+        builder.finish().unwrap();
+    }
+
+    #[test]
+    fn skips_dot_root_entry() {
+        let dir = scratch("tar-dot-root");
+        let tar = dir.join("a.tar");
+        write_tar(
+            &tar,
+            &[
+                ("./", 0o755, EntryType::Directory, ""),
+                ("./sub/", 0o755, EntryType::Directory, ""),
+                ("./sub/f", 0o644, EntryType::Regular, "file"),
+            ],
+        );
+
+        tar.pe_extract(dir.join("out")).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.join("out/sub/f")).unwrap(),
+            "file"
+        );
+    }
+
+    #[test]
+    fn restores_directory_mode_and_mtime_after_its_children() {
+        let dir = scratch("tar-read-only-dir");
+        let tar = dir.join("a.tar");
+        write_tar(
+            &tar,
+            &[
+                ("ro/", 0o555, EntryType::Directory, ""),
+                ("ro/f", 0o644, EntryType::Regular, "child"),
+            ],
+        );
+
+        tar.pe_extract(dir.join("out")).unwrap();
+
+        let ro = dir.join("out/ro");
+        assert_eq!(std::fs::read_to_string(ro.join("f")).unwrap(), "child");
+        let md = ro.symlink_metadata().unwrap();
+        assert_eq!(md.permissions().mode() & 0o7777, 0o555);
+        assert_eq!(
+            md.modified().unwrap(),
+            UNIX_EPOCH + Duration::from_secs(1_000_000_000)
+        );
+        std::fs::set_permissions(ro, PermissionsExt::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn ignores_member_mode_unless_kept() {
+        let dir = scratch("tar-no-keep-mode");
+        let tar = dir.join("a.tar");
+        write_tar(&tar, &[("f", 0o700, EntryType::Regular, "file")]);
+
+        let options = crate::ArchiveOptions {
+            keep_mode: false,
+            keep_mtime: true,
+        };
+        tar.pe_extract_with(dir.join("out"), options).unwrap();
+        std::fs::write(dir.join("fresh"), "").unwrap();
+
+        let mode = |p: &Path| p.metadata().unwrap().permissions().mode() & 0o7777;
+        assert_eq!(mode(&dir.join("out/f")), mode(&dir.join("fresh")));
+    }
+
+    #[test]
+    fn rejects_parent_member() {
+        let dir = scratch("tar-slip-parent");
+        let tar = dir.join("a.tar");
+        write_tar(&tar, &[("../escaped", 0o644, EntryType::Regular, "x")]);
+
+        assert_extract_rejected(&dir, &tar, "../escaped", &dir.join("escaped"));
+    }
+
+    #[test]
+    fn rejects_absolute_member() {
+        let dir = scratch("tar-slip-absolute");
+        let tar = dir.join("a.tar");
+        let outside = dir.join("escaped");
+        let name = outside.to_str().unwrap();
+        write_tar(&tar, &[(name, 0o644, EntryType::Regular, "x")]);
+
+        assert_extract_rejected(&dir, &tar, name, &outside);
+    }
+
+    #[test]
+    fn rejects_escaping_symlink() {
+        let dir = scratch("tar-slip-symlink");
+        let tar = dir.join("a.tar");
+        write_tar(
+            &tar,
+            &[
+                ("link", 0o777, EntryType::Symlink, "../escaped"),
+                ("link/f", 0o644, EntryType::Regular, "x"),
+            ],
+        );
+        std::fs::create_dir(dir.join("escaped")).unwrap();
+
+        assert_extract_rejected(&dir, &tar, "link", &dir.join("escaped/f"));
+        assert!(dir.join("out/link").symlink_metadata().is_err());
+    }
+}
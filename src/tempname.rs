@@ -0,0 +1,123 @@
+use crate::PathExt;
+use error_annotation::AnnotateResult;
+use std::collections::hash_map::RandomState;
+use std::ffi::{OsStr, OsString};
+use std::fs::{File, OpenOptions};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// How many names [create_temp] tries before giving up.
+const ATTEMPTS: usize = 16;
+
+/// Create a temporary sibling of `path` named `.<name>.<tag>-<random>` by calling `create` with
+/// candidate paths until one does not exist yet, returning the path along with the result.
+///
+/// `create` must fail with [ErrorKind::AlreadyExists] rather than replace or follow an existing
+/// entry, as `O_CREAT | O_EXCL`, `symlink`, and `link` do. The returned path is then known to be
+/// the caller's own, and safe to remove if a later step fails.
+pub(crate) fn create_temp<T, F>(path: &Path, tag: &str, mut create: F) -> Result<(PathBuf, T)>
+where
+    F: FnMut(&Path) -> Result<T>,
+{
+    let name = path.pe_file_name()?;
+    for _ in 0..ATTEMPTS {
+        let tmp = temp_path(path, name, tag, random());
+        match create(&tmp) {
+            Ok(v) => return Ok((tmp, v)),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Err(other_error_fmt!("no unused temporary name"))
+        .annotate_err_into("path", || path.display())
+        .annotate_err_into("attempts", || ATTEMPTS)
+}
+
+/// The temporary sibling of `path`, whose file name is `name`, for the random value `n`.
+fn temp_path(path: &Path, name: &OsStr, tag: &str, n: u64) -> PathBuf {
+    let mut tmpname = OsString::from(".");
+    tmpname.push(name);
+    tmpname.push(format!(".{}-{:016x}", tag, n));
+    path.with_file_name(tmpname)
+}
+
+/// Create a new file at `path` for writing, failing with [ErrorKind::AlreadyExists] if any
+/// entry exists there, even a dangling symlink. Suitable as the `create` of [create_temp].
+pub(crate) fn create_new_file(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .annotate_err_into("path", || path.display())
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Values [random] returns on this thread before any random ones.
+    static PLANNED: std::cell::RefCell<std::collections::VecDeque<u64>> = Default::default();
+}
+
+/// Make the next [create_temp] attempts on this thread use the random values `planned`, and
+/// return the temporary paths for `path` and `tag` they produce, so tests can occupy them.
+#[cfg(test)]
+pub(crate) fn plan_temp_paths(path: &Path, tag: &str, planned: &[u64]) -> Vec<PathBuf> {
+    PLANNED.with(|p| p.borrow_mut().extend(planned));
+    let name = path.file_name().unwrap();
+    planned
+        .iter()
+        .map(|&n| temp_path(path, name, tag, n))
+        .collect()
+}
+
+/// A number which differs between calls and processes, for naming temporary files.
+fn random() -> u64 {
+    #[cfg(test)]
+    if let Some(n) = PLANNED.with(|p| p.borrow_mut().pop_front()) {
+        return n;
+    }
+
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.write_u32(std::process::id());
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::create_temp;
+    use crate::testing::scratch;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn retries_names_which_exist() {
+        let dir = scratch("tempname-retry");
+        let mut tried = vec![];
+        let (tmp, ()) = create_temp(&dir.join("f"), "test-tmp", |tmp| {
+            tried.push(tmp.to_path_buf());
+            match tried.len() {
+                1 => Err(ErrorKind::AlreadyExists.into()),
+                _ => Ok(()),
+            }
+        })
+        .unwrap();
+
+        assert_eq!(tried.len(), 2);
+        assert_ne!(tried[0], tried[1]);
+        assert_eq!(tmp, tried[1]);
+        let name = tmp.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with(".f.test-tmp-"), "{}", name);
+    }
+
+    #[test]
+    fn gives_up_after_repeated_collisions() {
+        let dir = scratch("tempname-give-up");
+        let res = create_temp(&dir.join("f"), "test-tmp", |_| {
+            Err::<(), _>(ErrorKind::AlreadyExists.into())
+        });
+        assert!(res.is_err());
+    }
+}
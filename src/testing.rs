@@ -0,0 +1,38 @@
+use std::path::PathBuf;
+
+/// A fresh, empty directory for the test `name` under the system temp dir.
+pub(crate) fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("pathutil-test-{}", name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Assert that extracting `archive` into `dir/out` fails naming both the archive and `entry`, and
+/// leaves nothing at `outside`.
+#[cfg(any(feature = "tar", feature = "zip"))]
+pub(crate) fn assert_extract_rejected(
+    dir: &std::path::Path,
+    archive: &std::path::Path,
+    entry: &str,
+    outside: &std::path::Path,
+) {
+    use crate::PathExt;
+
+    let err = archive.pe_extract(dir.join("out")).unwrap_err().to_string();
+    assert!(
+        err.contains(&format!("-with entry: {}\n", entry)),
+        "{}",
+        err
+    );
+    assert!(
+        err.contains(&format!("-with archive: {}", archive.display())),
+        "{}",
+        err
+    );
+    assert!(
+        outside.symlink_metadata().is_err(),
+        "{} exists",
+        outside.display()
+    );
+}
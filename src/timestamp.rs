@@ -0,0 +1,84 @@
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Display a [SystemTime] as an RFC 3339 UTC timestamp, such as `1970-01-01T00:00:00Z`.
+///
+/// Sub-second precision is only shown when nonzero.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Timestamp(pub SystemTime);
+
+impl Timestamp {
+    /// Create a `Timestamp` from UTC calendar fields, or `None` if they are out of range.
+    #[cfg(feature = "zip")]
+    pub(crate) fn from_utc(y: i64, mo: u32, d: u32, h: u32, mi: u32, s: u32) -> Option<Self> {
+        if !(1..=12).contains(&mo) || !(1..=31).contains(&d) || h > 23 || mi > 59 || s > 60 {
+            return None;
+        }
+
+        let secs = days_from_civil(y, mo, d) * 86400 + i64::from(h * 3600 + mi * 60 + s);
+        let t = if secs >= 0 {
+            UNIX_EPOCH.checked_add(Duration::from_secs(secs as u64))
+        } else {
+            UNIX_EPOCH.checked_sub(Duration::from_secs(secs.unsigned_abs()))
+        };
+        t.map(Timestamp)
+    }
+
+    /// Split into `(year, month, day, hour, minute, second, nanos)` in UTC.
+    pub(crate) fn civil(&self) -> (i64, u32, u32, u32, u32, u32, u32) {
+        let (secs, nanos) = match self.0.duration_since(UNIX_EPOCH) {
+            Ok(d) => (d.as_secs() as i64, d.subsec_nanos()),
+            Err(e) => negative(e.duration()),
+        };
+
+        let days = secs.div_euclid(86400);
+        let tod = secs.rem_euclid(86400) as u32;
+        let (y, m, d) = civil_from_days(days);
+        (y, m, d, tod / 3600, tod / 60 % 60, tod % 60, nanos)
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (y, mo, d, h, mi, s, nanos) = self.civil();
+        write!(f, "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}", y, mo, d, h, mi, s)?;
+        if nanos != 0 {
+            write!(f, ".{:09}", nanos)?;
+        }
+        f.write_str("Z")
+    }
+}
+
+fn negative(d: Duration) -> (i64, u32) {
+    let secs = -(d.as_secs() as i64);
+    match d.subsec_nanos() {
+        0 => (secs, 0),
+        n => (secs - 1, 1_000_000_000 - n),
+    }
+}
+
+/// Howard Hinnant's `civil_from_days` algorithm, converting days since the unix epoch.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + i64::from(m <= 2);
+    (y, m, d)
+}
+
+/// The inverse of [civil_from_days].
+#[cfg(feature = "zip")]
+fn days_from_civil(y: i64, m: u32, d: u32) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = i64::from(if m > 2 { m - 3 } else { m + 9 });
+    let doy = (153 * mp + 2) / 5 + i64::from(d) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
@@ -0,0 +1,107 @@
+use std::ffi::{CStr, CString, OsStr};
+use std::mem::MaybeUninit;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+use std::ptr;
+
+/// Look up the user name for `uid`, or `None` if there is no such user.
+pub(crate) fn user_name(uid: u32) -> Option<String> {
+    getpwuid(uid, |pwd| unsafe { cstr_string(pwd.pw_name) })
+}
+
+/// Look up the home directory of the user named `name`, or `None` if there is no such user.
+pub(crate) fn home_dir_of(name: &str) -> Option<PathBuf> {
+    let cname = CString::new(name).ok()?;
+    with_buffer(|buf| {
+        let mut pwd = MaybeUninit::<libc::passwd>::uninit();
+        let mut result = ptr::null_mut();
+        let rc = unsafe {
+            libc::getpwnam_r(
+                cname.as_ptr(),
+                pwd.as_mut_ptr(),
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut result,
+            )
+        };
+        (
+            rc,
+            (!result.is_null()).then(|| unsafe { cstr_path(pwd.assume_init().pw_dir) }),
+        )
+    })
+}
+
+/// Look up the home directory of the current user in the user database.
+pub(crate) fn current_home_dir() -> Option<PathBuf> {
+    let uid = unsafe { libc::getuid() };
+    getpwuid(uid, |pwd| unsafe { cstr_path(pwd.pw_dir) })
+}
+
+/// Look up the user database entry for `uid` and extract a field with `get` while its strings
+/// are still in the scratch buffer.
+fn getpwuid<T, F>(uid: libc::uid_t, get: F) -> Option<T>
+where
+    F: Fn(&libc::passwd) -> T,
+{
+    with_buffer(|buf| {
+        let mut pwd = MaybeUninit::<libc::passwd>::uninit();
+        let mut result = ptr::null_mut();
+        let rc = unsafe {
+            libc::getpwuid_r(
+                uid,
+                pwd.as_mut_ptr(),
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut result,
+            )
+        };
+        (
+            rc,
+            (!result.is_null()).then(|| get(unsafe { pwd.assume_init_ref() })),
+        )
+    })
+}
+
+/// Look up the group name for `gid`, or `None` if there is no such group.
+pub(crate) fn group_name(gid: u32) -> Option<String> {
+    with_buffer(|buf| {
+        let mut grp = MaybeUninit::<libc::group>::uninit();
+        let mut result = ptr::null_mut();
+        let rc = unsafe {
+            libc::getgrgid_r(
+                gid,
+                grp.as_mut_ptr(),
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut result,
+            )
+        };
+        (
+            rc,
+            (!result.is_null()).then(|| unsafe { cstr_string(grp.assume_init().gr_name) }),
+        )
+    })
+}
+
+/// Retry `f` with a growing scratch buffer while it reports `ERANGE`.
+fn with_buffer<T, F>(mut f: F) -> Option<T>
+where
+    F: FnMut(&mut [libc::c_char]) -> (libc::c_int, Option<T>),
+{
+    let mut buf = vec![0; 1024];
+    loop {
+        match f(&mut buf) {
+            (libc::ERANGE, _) if buf.len() < 1 << 20 => buf.resize(buf.len() * 2, 0),
+            (0, found) => return found,
+            _ => return None,
+        }
+    }
+}
+
+unsafe fn cstr_string(p: *const libc::c_char) -> String {
+    CStr::from_ptr(p).to_string_lossy().into_owned()
+}
+
+unsafe fn cstr_path(p: *const libc::c_char) -> PathBuf {
+    PathBuf::from(OsStr::from_bytes(CStr::from_ptr(p).to_bytes()))
+}
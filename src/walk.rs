@@ -0,0 +1,241 @@
+use crate::progress::check_cancelled;
+use crate::{MultiPathError, Observer, PathExt, PathMetadata, ProgressEvent};
+use error_annotation::AnnotateResult;
+use std::fmt;
+use std::io::{ErrorKind, Result};
+use std::path::{Component, Path, PathBuf};
+
+/// A depth-first pre-order traversal of a directory tree, created by [PathExt::pe_walk].
+///
+/// Each item is the [PathMetadata] of an entry, loaded without following symlinks, starting with
+/// the root itself at depth 0. Directory entries are visited in file name order so traversals are
+/// deterministic. Errors are annotated with the offending path, and traversal continues past them.
+///
+/// # Example
+///
+/// ```
+/// use pathutil::PathExt;
+///
+/// let p = std::path::Path::new("/this/path/does/not/exist");
+/// let res: std::io::Result<Vec<_>> = p.pe_walk().collect();
+/// assert!(res.is_err());
+///
+/// let errstr = res.err().unwrap().to_string();
+/// assert_eq!(&errstr, "
+///
+/// No such file or directory (os error 2)
+/// -with path: /this/path/does/not/exist
+///
+/// ".trim());
+/// ```
+#[derive(Debug)]
+pub struct PathWalk {
+    root: PathBuf,
+    started: bool,
+    /// Remaining entries of each directory being visited, in reverse order, with their depth.
+    /// Entries which could not be read are kept as their errors.
+    stack: Vec<(Vec<Result<PathBuf>>, usize)>,
+    /// The last yielded directory, which is read on the next call unless skipped.
+    pending_dir: Option<(PathBuf, usize)>,
+    max_depth: Option<usize>,
+    depth: usize,
+}
+
+impl PathWalk {
+    pub fn new<P>(root: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        PathWalk {
+            root: root.into(),
+            started: false,
+            stack: vec![],
+            pending_dir: None,
+            max_depth: None,
+            depth: 0,
+        }
+    }
+
+    /// Do not descend into directories deeper than `max_depth`, where the root is depth 0.
+    pub fn max_depth(self, max_depth: usize) -> Self {
+        PathWalk {
+            max_depth: Some(max_depth),
+            ..self
+        }
+    }
+
+    /// The depth of the most recently yielded entry.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Do not descend into the most recently yielded entry if it is a directory.
+    pub fn skip_current_dir(&mut self) {
+        self.pending_dir = None;
+    }
+
+    /// Visit the whole tree, collecting the entries and, separately, every error met on the way.
+    pub fn collect_all(self) -> (Vec<PathMetadata<'static>>, MultiPathError) {
+        let mut errors = MultiPathError::new();
+        let entries = self.filter_map(|res| errors.collect(res)).collect();
+        (entries, errors)
+    }
+
+    /// Report each entry to `observer` as it is yielded, and stop with an error annotated with
+    /// the `root` if the observer is cancelled.
+    pub fn observed(self, observer: &mut dyn Observer) -> ObservedWalk<'_> {
+        ObservedWalk {
+            walk: self,
+            observer,
+            cancelled: false,
+        }
+    }
+
+    fn read_dir(&mut self, dir: &Path, depth: usize) -> Result<()> {
+        let (mut children, errors): (Vec<_>, Vec<_>) = dir
+            .pe_read_dir()?
+            .map(|res| res.map(|de| de.path()))
+            .partition(|res| res.is_ok());
+        children.sort_by(|a, b| match (a, b) {
+            (Ok(a), Ok(b)) => b.file_name().cmp(&a.file_name()),
+            _ => std::cmp::Ordering::Equal,
+        });
+        // Errors are popped first, then the entries in file name order:
+        children.extend(errors);
+        self.stack.push((children, depth + 1));
+        Ok(())
+    }
+
+    fn visit(&mut self, path: PathBuf, depth: usize) -> Result<PathMetadata<'static>> {
+        self.depth = depth;
+        let md = path.pe_symlink_metadata()?.unwrap();
+        if md.is_dir() && self.max_depth.map(|max| depth < max).unwrap_or(true) {
+            self.pending_dir = Some((path.clone(), depth));
+        }
+        Ok(PathMetadata::new(path, md))
+    }
+}
+
+impl Iterator for PathWalk {
+    type Item = Result<PathMetadata<'static>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            self.started = true;
+            return Some(self.visit(self.root.clone(), 0));
+        }
+
+        if let Some((dir, depth)) = self.pending_dir.take() {
+            if let Err(e) = self.read_dir(&dir, depth) {
+                return Some(Err(e));
+            }
+        }
+
+        loop {
+            let (children, depth) = self.stack.last_mut()?;
+            match children.pop() {
+                Some(Ok(path)) => {
+                    let depth = *depth;
+                    return Some(self.visit(path, depth));
+                }
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
+
+/// A [PathWalk] which reports each entry to an [Observer], created by [PathWalk::observed] or
+/// [PathExt::pe_walk_observed].
+pub struct ObservedWalk<'a> {
+    walk: PathWalk,
+    observer: &'a mut dyn Observer,
+    cancelled: bool,
+}
+
+impl ObservedWalk<'_> {
+    /// The depth of the most recently yielded entry.
+    pub fn depth(&self) -> usize {
+        self.walk.depth()
+    }
+
+    /// Do not descend into the most recently yielded entry if it is a directory.
+    pub fn skip_current_dir(&mut self) {
+        self.walk.skip_current_dir()
+    }
+}
+
+impl fmt::Debug for ObservedWalk<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ObservedWalk")
+            .field("walk", &self.walk)
+            .field("cancelled", &self.cancelled)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Iterator for ObservedWalk<'_> {
+    type Item = Result<PathMetadata<'static>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.cancelled {
+            return None;
+        }
+        if let Err(e) = check_cancelled(self.observer) {
+            self.cancelled = true;
+            let root = &self.walk.root;
+            return Some(Err(e).annotate_err_into("root", || root.display()));
+        }
+
+        let res = self.walk.next()?;
+        if let Ok(md) = &res {
+            self.observer.event(ProgressEvent::Entry(md.path()));
+        }
+        Some(res)
+    }
+}
+
+/// Remove the directory at `path` and its contents depth-first, reporting each entry to
+/// `observer` before it is removed and stopping between entries if it is cancelled.
+pub(crate) fn remove_dir_all_observed(path: &Path, observer: &mut dyn Observer) -> Result<()> {
+    if path.pe_symlink_metadata()?.is_file() {
+        return Err(crate::error::errno(ErrorKind::NotADirectory))
+            .annotate_err_into("path", || path.display());
+    }
+    remove_observed(path, observer)
+}
+
+fn remove_observed(path: &Path, observer: &mut dyn Observer) -> Result<()> {
+    check_cancelled(observer).annotate_err_into("path", || path.display())?;
+    observer.event(ProgressEvent::Entry(path));
+    if path.pe_symlink_metadata()?.is_dir() {
+        let children = path
+            .pe_read_dir()?
+            .map(|res| res.map(|de| de.path()))
+            .collect::<Result<Vec<_>>>()?;
+        for child in children {
+            remove_observed(&child, observer)?;
+        }
+        path.pe_remove_dir()
+    } else {
+        path.pe_remove_file()
+    }
+}
+
+/// The name of `path` relative to `root` with `/` separators, as used for archive members and
+/// manifest entries, or an error if it is not valid utf8.
+pub(crate) fn relative_name(root: &Path, path: &Path) -> Result<String> {
+    let rel = path.pe_strip_prefix(root)?;
+    let mut name = String::new();
+    for c in rel.components() {
+        if let Component::Normal(part) = c {
+            if !name.is_empty() {
+                name.push('/');
+            }
+            name.push_str(Path::new(part).pe_to_str()?);
+        }
+    }
+    Ok(name)
+}
@@ -0,0 +1,128 @@
+use crate::XattrMap;
+use error_annotation::AnnotateResult;
+use std::ffi::{OsStr, OsString};
+use std::io::Result;
+use std::path::Path;
+
+/// Whether an xattr operation follows a symlink at the path or applies to the symlink itself.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Follow {
+    Deref,
+    NoDeref,
+}
+
+pub(crate) fn list(path: &Path, follow: Follow) -> Result<Vec<OsString>> {
+    let res = match follow {
+        Follow::Deref => ::xattr::list_deref(path),
+        Follow::NoDeref => ::xattr::list(path),
+    };
+    res.map(|names| names.collect())
+        .annotate_err_into("path", || path.display())
+}
+
+pub(crate) fn get(path: &Path, name: &OsStr, follow: Follow) -> Result<Vec<u8>> {
+    let res = match follow {
+        Follow::Deref => ::xattr::get_deref(path, name),
+        Follow::NoDeref => ::xattr::get(path, name),
+    };
+    res.and_then(|optval| optval.ok_or_else(|| other_error_fmt!("no such attribute")))
+        .annotate_err_into("path", || path.display())
+        .annotate_err_into("name", || name.to_string_lossy())
+}
+
+pub(crate) fn set(path: &Path, name: &OsStr, value: &[u8], follow: Follow) -> Result<()> {
+    let res = match follow {
+        Follow::Deref => ::xattr::set_deref(path, name, value),
+        Follow::NoDeref => ::xattr::set(path, name, value),
+    };
+    res.annotate_err_into("path", || path.display())
+        .annotate_err_into("name", || name.to_string_lossy())
+}
+
+pub(crate) fn remove(path: &Path, name: &OsStr, follow: Follow) -> Result<()> {
+    let res = match follow {
+        Follow::Deref => ::xattr::remove_deref(path, name),
+        Follow::NoDeref => ::xattr::remove(path, name),
+    };
+    res.annotate_err_into("path", || path.display())
+        .annotate_err_into("name", || name.to_string_lossy())
+}
+
+pub(crate) fn load(path: &Path, follow: Follow) -> Result<XattrMap> {
+    list(path, follow)?
+        .into_iter()
+        .map(|name| get(path, &name, follow).map(|value| (name, value)))
+        .collect()
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use crate::testing::scratch;
+    use crate::PathExt;
+    use std::ffi::OsString;
+
+    fn names(list: &[&str]) -> Vec<OsString> {
+        list.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn deref_round_trip_through_symlink() {
+        let dir = scratch("xattr-deref");
+        let (file, link) = (dir.join("file"), dir.join("link"));
+        std::fs::write(&file, "x").unwrap();
+        std::os::unix::fs::symlink("file", &link).unwrap();
+
+        link.pe_xattr_set("user.a", "one").unwrap();
+        link.pe_xattr_set("user.b", [0, 255]).unwrap();
+        assert_eq!(link.pe_xattr_get("user.a").unwrap(), b"one");
+        assert_eq!(file.pe_xattr_get("user.b").unwrap(), [0, 255]);
+        let mut listed = link.pe_xattr_list().unwrap();
+        listed.sort();
+        assert_eq!(listed, names(&["user.a", "user.b"]));
+        // The attributes landed on the referent, not the symlink:
+        assert!(link.pe_lxattr_list().unwrap().is_empty());
+
+        link.pe_xattr_remove("user.a").unwrap();
+        assert_eq!(file.pe_xattr_list().unwrap(), names(&["user.b"]));
+        let err = link.pe_xattr_get("user.a").unwrap_err().to_string();
+        let expected = format!("-with path: {}\n-with name: user.a", link.display());
+        assert!(err.starts_with("no such attribute"), "{}", err);
+        assert!(err.ends_with(&expected), "{}", err);
+    }
+
+    #[test]
+    fn no_deref_round_trip_on_symlink() {
+        let dir = scratch("xattr-no-deref");
+        let (file, link) = (dir.join("file"), dir.join("link"));
+        std::fs::write(&file, "x").unwrap();
+        std::os::unix::fs::symlink("file", &link).unwrap();
+
+        // Linux refuses `user.` attributes on symlinks; `trusted.` ones need CAP_SYS_ADMIN:
+        let err = link.pe_lxattr_set("user.a", "one").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied, "{}", err);
+        let res = link.pe_lxattr_set("trusted.a", "one");
+        if unsafe { libc::geteuid() } != 0 {
+            assert_eq!(res.unwrap_err().kind(), err.kind());
+            return;
+        }
+
+        res.unwrap();
+        assert_eq!(link.pe_lxattr_get("trusted.a").unwrap(), b"one");
+        assert_eq!(link.pe_lxattr_list().unwrap(), names(&["trusted.a"]));
+        let md = link.pe_symlink_metadata_with_xattrs().unwrap();
+        assert_eq!(md.xattrs().unwrap().len(), 1);
+        // The referent is untouched:
+        assert!(file.pe_xattr_list().unwrap().is_empty());
+        assert!(link
+            .pe_metadata_with_xattrs()
+            .unwrap()
+            .xattrs()
+            .unwrap()
+            .is_empty());
+
+        link.pe_lxattr_remove("trusted.a").unwrap();
+        assert!(link.pe_lxattr_list().unwrap().is_empty());
+        let err = link.pe_lxattr_remove("trusted.a").unwrap_err().to_string();
+        assert!(err.contains("-with name: trusted.a"), "{}", err);
+    }
+}
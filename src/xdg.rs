@@ -0,0 +1,108 @@
+//! Resolve [XDG base directories](https://specifications.freedesktop.org/basedir-spec/latest/).
+//!
+//! Each `XDG_…` variable overrides the default when it is set to an absolute path; relative or
+//! empty values are ignored as the spec requires. The `…_with` variants take an environment
+//! lookup function in place of the process environment, which is useful for tests.
+use crate::expand::{env_var, home_dir};
+use error_annotation::AnnotateResult;
+use std::ffi::OsString;
+use std::io::Result;
+use std::path::PathBuf;
+
+/// `$XDG_CONFIG_HOME`, defaulting to `$HOME/.config`.
+pub fn config_home() -> Result<PathBuf> {
+    config_home_with(env_var)
+}
+
+/// [config_home] with an explicit environment lookup.
+pub fn config_home_with<F>(lookup: F) -> Result<PathBuf>
+where
+    F: Fn(&str) -> Option<OsString>,
+{
+    base_dir(&lookup, "XDG_CONFIG_HOME", ".config")
+}
+
+/// `$XDG_CACHE_HOME`, defaulting to `$HOME/.cache`.
+pub fn cache_home() -> Result<PathBuf> {
+    cache_home_with(env_var)
+}
+
+/// [cache_home] with an explicit environment lookup.
+pub fn cache_home_with<F>(lookup: F) -> Result<PathBuf>
+where
+    F: Fn(&str) -> Option<OsString>,
+{
+    base_dir(&lookup, "XDG_CACHE_HOME", ".cache")
+}
+
+/// `$XDG_DATA_HOME`, defaulting to `$HOME/.local/share`.
+pub fn data_home() -> Result<PathBuf> {
+    data_home_with(env_var)
+}
+
+/// [data_home] with an explicit environment lookup.
+pub fn data_home_with<F>(lookup: F) -> Result<PathBuf>
+where
+    F: Fn(&str) -> Option<OsString>,
+{
+    base_dir(&lookup, "XDG_DATA_HOME", ".local/share")
+}
+
+/// `$XDG_RUNTIME_DIR`, which has no default, so the error explains when it is unset or not
+/// absolute.
+///
+/// # Example
+///
+/// ```
+/// let res = pathutil::xdg::runtime_dir_with(|_| None);
+/// assert!(res.is_err());
+///
+/// let errstr = res.err().unwrap().to_string();
+/// assert_eq!(&errstr, "
+///
+/// undefined variable
+/// -with variable: XDG_RUNTIME_DIR
+///
+/// ".trim());
+/// ```
+pub fn runtime_dir() -> Result<PathBuf> {
+    runtime_dir_with(env_var)
+}
+
+/// [runtime_dir] with an explicit environment lookup.
+pub fn runtime_dir_with<F>(lookup: F) -> Result<PathBuf>
+where
+    F: Fn(&str) -> Option<OsString>,
+{
+    const VAR: &str = "XDG_RUNTIME_DIR";
+
+    let dir = lookup(VAR)
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .ok_or_else(|| other_error_fmt!("undefined variable"))
+        .annotate_err_into("variable", || VAR)?;
+
+    if dir.is_absolute() {
+        Ok(dir)
+    } else {
+        Err(other_error_fmt!("not an absolute path"))
+            .annotate_err_into("variable", || VAR)
+            .annotate_err_into("path", || dir.display())
+    }
+}
+
+fn base_dir<F>(lookup: &F, var: &'static str, default: &str) -> Result<PathBuf>
+where
+    F: Fn(&str) -> Option<OsString>,
+{
+    if let Some(dir) = lookup(var).map(PathBuf::from) {
+        if dir.is_absolute() {
+            return Ok(dir);
+        }
+    }
+
+    home_dir(lookup)
+        .map(|home| home.join(default))
+        .ok_or_else(|| other_error_fmt!("unknown home directory"))
+        .annotate_err_into("variable", || var)
+}
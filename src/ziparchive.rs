@@ -0,0 +1,223 @@
+use crate::archive::{self, ArchiveOptions, DeferredDirs};
+use crate::pathmove::symlink;
+use crate::{PathExt, PathJail, PathMetadata, Timestamp};
+use error_annotation::AnnotateResult;
+use std::io::{Read, Result};
+use std::path::Path;
+use zip::read::ZipFile;
+use zip::write::SimpleFileOptions;
+use zip::{DateTime, ZipArchive, ZipWriter};
+
+pub(crate) fn create(root: &Path, dest: &Path, options: ArchiveOptions) -> Result<()> {
+    let mut writer = ZipWriter::new(dest.pe_create()?);
+
+    for res in archive::members(root) {
+        let (name, md) = res?;
+        append(&mut writer, &name, &md, options)
+            .annotate_err_into("entry", || &name)
+            .annotate_err_into("archive", || dest.display())?;
+    }
+
+    writer
+        .finish()
+        .map(|_| ())
+        .map_err(std::io::Error::from)
+        .annotate_err_into("archive", || dest.display())
+}
+
+fn append(
+    writer: &mut ZipWriter<crate::PathFile>,
+    name: &str,
+    md: &PathMetadata,
+    options: ArchiveOptions,
+) -> Result<()> {
+    let mtime = match options.keep_mtime {
+        true => md.modified().map(zip_time)?,
+        false => DateTime::default(),
+    };
+    let fileopts = SimpleFileOptions::default()
+        .unix_permissions(archive::mode(md, options))
+        .last_modified_time(mtime);
+
+    let path = md.path();
+    if md.is_symlink() {
+        let target = path.pe_read_link()?;
+        let target = target.pe_to_str()?;
+        writer.add_symlink(name, target, fileopts)?;
+    } else if md.is_dir() {
+        writer.add_directory(name, fileopts)?;
+    } else if md.is_file() {
+        writer.start_file(name, fileopts)?;
+        std::io::copy(&mut path.pe_open()?, writer)?;
+    } else {
+        return Err(other_error_fmt!("unsupported file type"));
+    }
+    Ok(())
+}
+
+/// Convert to a zip timestamp, clamping to the representable range of 1980 through 2107.
+fn zip_time(t: std::time::SystemTime) -> DateTime {
+    let (y, mo, d, h, mi, s, _) = Timestamp(t).civil();
+    let y = y.clamp(1980, 2107) as u16;
+    DateTime::from_date_and_time(y, mo as u8, d as u8, h as u8, mi as u8, s as u8)
+        .unwrap_or_default()
+}
+
+pub(crate) fn extract(archive_path: &Path, jail: &PathJail, options: ArchiveOptions) -> Result<()> {
+    let mut ar = ZipArchive::new(archive_path.pe_open()?)
+        .map_err(std::io::Error::from)
+        .annotate_err_into("archive", || archive_path.display())?;
+
+    let mut dirs = DeferredDirs::default();
+    for i in 0..ar.len() {
+        let mut entry = ar
+            .by_index(i)
+            .map_err(std::io::Error::from)
+            .annotate_err_into("archive", || archive_path.display())?;
+        let name = entry.name().to_string();
+
+        extract_entry(&mut entry, Path::new(&name), jail, options, &mut dirs)
+            .annotate_err_into("entry", || &name)
+            .annotate_err_into("archive", || archive_path.display())?;
+    }
+
+    dirs.apply()
+        .annotate_err_into("archive", || archive_path.display())
+}
+
+fn extract_entry<R>(
+    entry: &mut ZipFile<'_, R>,
+    name: &Path,
+    jail: &PathJail,
+    options: ArchiveOptions,
+    dirs: &mut DeferredDirs,
+) -> Result<()>
+where
+    R: Read,
+{
+    let mode = match options.keep_mode {
+        true => entry.unix_mode().map(|mode| mode & 0o7777),
+        false => None,
+    };
+    let mtime = match options.keep_mtime {
+        true => entry.last_modified().and_then(system_time),
+        false => None,
+    };
+
+    if entry.is_dir() {
+        if let Some(dest) = archive::dir_destination(jail, name)? {
+            dest.pe_create_dir_all()?;
+            dirs.push(dest, mode, mtime);
+        }
+        return Ok(());
+    }
+
+    let dest = archive::destination(jail, name)?;
+    if entry.is_symlink() {
+        let mut target = String::new();
+        entry.read_to_string(&mut target)?;
+        archive::check_symlink(jail, name, Path::new(&target))?;
+        archive::create_parent(&dest)?;
+        return symlink(Path::new(&target), &dest);
+    }
+
+    archive::write_file(&dest, entry, mode, mtime)
+}
+
+fn system_time(dt: DateTime) -> Option<std::time::SystemTime> {
+    Timestamp::from_utc(
+        i64::from(dt.year()),
+        u32::from(dt.month()),
+        u32::from(dt.day()),
+        u32::from(dt.hour()),
+        u32::from(dt.minute()),
+        u32::from(dt.second()),
+    )
+    .map(|ts| ts.0)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use crate::testing::{assert_extract_rejected, scratch};
+    use crate::PathExt;
+    use std::io::Write;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+    use std::time::{Duration, UNIX_EPOCH};
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    /// Write a zip of `(name, symlink target)` entries, or files with contents `x` where the
+    /// target is `None`, keeping names verbatim so they can start with `..` or `/`.
+    fn write_zip(path: &Path, entries: &[(&str, Option<&str>)]) {
+        let mut writer = ZipWriter::new(std::fs::File::create(path).unwrap());
+        for &(name, target) in entries {
+            let options = SimpleFileOptions::default();
+            match target {
+                Some(target) => writer.add_symlink(name, target, options).unwrap(),
+                None => {
+                    writer.start_file(name, options).unwrap();
+                    writer.write_all(b"x").unwrap();
+                }
+            }
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn restores_read_only_directory_after_its_children() {
+        let dir = scratch("zip-read-only-dir");
+        let mtime = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        std::fs::create_dir_all(dir.join("src/ro")).unwrap();
+        std::fs::write(dir.join("src/ro/f"), "child").unwrap();
+        dir.join("src/ro").pe_set_mtime(mtime).unwrap();
+        std::fs::set_permissions(dir.join("src/ro"), PermissionsExt::from_mode(0o555)).unwrap();
+
+        let zip = dir.join("a.zip");
+        dir.join("src")
+            .pe_zip_create(&zip, Default::default())
+            .unwrap();
+        zip.pe_extract(dir.join("out")).unwrap();
+
+        let ro = dir.join("out/ro");
+        assert_eq!(std::fs::read_to_string(ro.join("f")).unwrap(), "child");
+        let md = ro.symlink_metadata().unwrap();
+        assert_eq!(md.permissions().mode() & 0o7777, 0o555);
+        assert_eq!(md.modified().unwrap(), mtime);
+
+        for p in [dir.join("src/ro"), ro] {
+            std::fs::set_permissions(p, PermissionsExt::from_mode(0o755)).unwrap();
+        }
+    }
+
+    #[test]
+    fn rejects_parent_member() {
+        let dir = scratch("zip-slip-parent");
+        let zip = dir.join("a.zip");
+        write_zip(&zip, &[("../escaped", None)]);
+
+        assert_extract_rejected(&dir, &zip, "../escaped", &dir.join("escaped"));
+    }
+
+    #[test]
+    fn rejects_absolute_member() {
+        let dir = scratch("zip-slip-absolute");
+        let zip = dir.join("a.zip");
+        let outside = dir.join("escaped");
+        let name = outside.to_str().unwrap();
+        write_zip(&zip, &[(name, None)]);
+
+        assert_extract_rejected(&dir, &zip, name, &outside);
+    }
+
+    #[test]
+    fn rejects_escaping_symlink() {
+        let dir = scratch("zip-slip-symlink");
+        let zip = dir.join("a.zip");
+        write_zip(&zip, &[("link", Some("../escaped")), ("link/f", None)]);
+        std::fs::create_dir(dir.join("escaped")).unwrap();
+
+        assert_extract_rejected(&dir, &zip, "link", &dir.join("escaped/f"));
+        assert!(dir.join("out/link").symlink_metadata().is_err());
+    }
+}